@@ -0,0 +1,6 @@
+-- Add migration script here
+CREATE TABLE email_send_rate (
+    window_start timestamptz NOT NULL,
+    n_sent INTEGER NOT NULL,
+    PRIMARY KEY (window_start)
+);
@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE issue_delivery_queue ADD COLUMN created_at timestamptz NOT NULL DEFAULT now();
@@ -0,0 +1,12 @@
+-- Add migration script here
+CREATE TABLE issue_delivery_failures (
+    failure_id uuid NOT NULL,
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_email TEXT NOT NULL,
+    n_attempts SMALLINT NOT NULL,
+    last_error TEXT NOT NULL,
+    enqueued_at timestamptz NOT NULL,
+    failed_at timestamptz NOT NULL,
+    PRIMARY KEY (failure_id),
+    UNIQUE (newsletter_issue_id, subscriber_email)
+);
@@ -0,0 +1,4 @@
+-- Everybody could do everything until now, so existing users become owners
+ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'owner'
+    CHECK (role IN ('owner', 'editor', 'viewer'));
+ALTER TABLE users ALTER COLUMN role DROP DEFAULT;
@@ -0,0 +1,10 @@
+-- Invited users keep the address their invite was sent to
+ALTER TABLE users ADD COLUMN email TEXT UNIQUE;
+
+CREATE TABLE user_invites(
+    invite_token_hash TEXT PRIMARY KEY,
+    email TEXT NOT NULL,
+    role TEXT NOT NULL CHECK (role IN ('owner', 'editor', 'viewer')),
+    invited_by uuid REFERENCES users (user_id) ON DELETE SET NULL,
+    created_at timestamptz NOT NULL DEFAULT now()
+);
@@ -0,0 +1,5 @@
+CREATE TABLE password_reset_tokens(
+    reset_token_hash TEXT PRIMARY KEY,
+    user_id uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    created_at timestamptz NOT NULL DEFAULT now()
+);
@@ -0,0 +1,6 @@
+-- Lets every session of a user be revoked at once, whichever store holds it
+CREATE TABLE user_sessions(
+    session_id TEXT PRIMARY KEY,
+    user_id uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    created_at timestamptz NOT NULL DEFAULT now()
+);
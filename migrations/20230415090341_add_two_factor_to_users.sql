@@ -0,0 +1,9 @@
+-- The last accepted time step stops an observed code from being replayed
+ALTER TABLE users ADD COLUMN totp_secret TEXT;
+ALTER TABLE users ADD COLUMN totp_last_used_step BIGINT;
+
+CREATE TABLE totp_recovery_codes(
+    user_id uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    code_hash TEXT NOT NULL,
+    PRIMARY KEY (user_id, code_hash)
+);
@@ -0,0 +1,6 @@
+-- Only used when login throttling is configured to keep its counters in Postgres
+CREATE TABLE login_failures(
+    throttle_key TEXT PRIMARY KEY,
+    failures INT NOT NULL,
+    last_failure_at timestamptz NOT NULL
+);
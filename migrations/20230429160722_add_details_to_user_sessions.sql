@@ -0,0 +1,11 @@
+-- public_id identifies a session on the sessions page without exposing the
+-- key it is stored under
+ALTER TABLE user_sessions
+    ADD COLUMN public_id uuid,
+    ADD COLUMN last_seen_at timestamptz NOT NULL DEFAULT now(),
+    ADD COLUMN ip TEXT,
+    ADD COLUMN user_agent TEXT;
+UPDATE user_sessions SET public_id = md5(random()::text || session_id)::uuid;
+ALTER TABLE user_sessions
+    ALTER COLUMN public_id SET NOT NULL,
+    ADD CONSTRAINT user_sessions_public_id_key UNIQUE (public_id);
@@ -0,0 +1,7 @@
+-- Only used when sessions are configured to be kept in Postgres
+CREATE TABLE sessions(
+    session_id TEXT PRIMARY KEY,
+    session TEXT NOT NULL,
+    expires_at timestamptz
+);
+CREATE INDEX sessions_expires_at_idx ON sessions (expires_at);
@@ -5,11 +5,13 @@ use argon2::{
 };
 use axum::headers::{authorization::Basic, Authorization};
 use secrecy::{ExposeSecret, Secret};
-use sqlx::PgPool;
+use sqlx::{PgExecutor, PgPool};
 use tokio::task::spawn_blocking;
-use tracing::{warn, Span};
+use tracing::{warn, Instrument, Span};
 use uuid::Uuid;
 
+use crate::{configuration::PasswordHashingSettings, log::LogErr};
+
 pub struct Credentials {
     pub username: String,
     pub password: Secret<String>,
@@ -32,27 +34,54 @@ impl From<Authorization<Basic>> for Credentials {
     }
 }
 
+/// Once the password is verified, a hash weaker than `hashing` asks for is
+/// replaced in the background, so that raising the cost needs no resets.
 #[tracing::instrument(skip_all)]
 pub async fn validate_credentials(
     credentials: Credentials,
+    hashing: PasswordHashingSettings,
     db_pool: &PgPool,
 ) -> Result<Uuid, AuthError> {
-    let (user_id, password_hash) = get_user_credentials(credentials.username, db_pool).await?;
+    let (user_id, password_hash) =
+        get_user_credentials(credentials.username, hashing, db_pool).await?;
+    let rehash = needs_rehash(&password_hash, hashing)?
+        .then(|| (credentials.password.clone(), password_hash.clone()));
     spawn_blocking(move || {
         Span::current().in_scope(|| verify_password_hash(credentials.password, password_hash))
     })
     .await
     .context("Failed to spawn blocking password verification task")??;
+
+    if let Some((password, old_hash)) = rehash {
+        let db_pool = db_pool.clone();
+        tokio::spawn(
+            async move {
+                let _ = rehash_password(user_id, password, old_hash, hashing, &db_pool)
+                    .await
+                    .log_err();
+            }
+            .in_current_span(),
+        );
+    }
+
     Ok(user_id)
 }
 
-const FALLBACK_PASSWORD_HASH: &str = "$argon2id$v=19$m=15000,t=2,p=1$\
-gZiV/M1gPc22ElAH/Jh1Hw$\
-CWOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno";
+/// Verifying it costs as much as verifying a real hash with the current
+/// parameters, which never succeeds.
+fn fallback_password_hash(hashing: PasswordHashingSettings) -> String {
+    format!(
+        "$argon2id$v=19$m={},t={},p={}$\
+        gZiV/M1gPc22ElAH/Jh1Hw$\
+        CWOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno",
+        hashing.memory_kib, hashing.iterations, hashing.parallelism
+    )
+}
 
 #[tracing::instrument(skip_all)]
 async fn get_user_credentials(
     username: String,
+    hashing: PasswordHashingSettings,
     db_pool: &PgPool,
 ) -> Result<(Uuid, Secret<String>), AuthError> {
     let user_id = sqlx::query!(
@@ -67,10 +96,7 @@ async fn get_user_credentials(
         .map(|r| (r.user_id, Secret::new(r.password_hash)))
         .unwrap_or_else(|| {
             warn!("invalid username, returning fallback password hash to prevent timing attacks");
-            (
-                Uuid::new_v4(),
-                Secret::new(FALLBACK_PASSWORD_HASH.to_string()),
-            )
+            (Uuid::new_v4(), Secret::new(fallback_password_hash(hashing)))
         });
 
     Ok((user_id, password_hash))
@@ -90,16 +116,85 @@ pub fn verify_password_hash(
     Ok(())
 }
 
+/// Whether `password_hash` is weaker than a new hash would be: from another
+/// algorithm or version, or with any cost parameter below the configured one.
+fn needs_rehash(
+    password_hash: &Secret<String>,
+    hashing: PasswordHashingSettings,
+) -> Result<bool, anyhow::Error> {
+    let password_hash =
+        PasswordHash::new(password_hash.expose_secret()).context("Failed to parse PHC hash")?;
+    if password_hash.algorithm != Algorithm::Argon2id.ident()
+        || password_hash.version != Some(Version::V0x13.into())
+    {
+        return Ok(true);
+    }
+    let params = Params::try_from(&password_hash).context("Failed to parse hash params")?;
+
+    Ok(params.m_cost() < hashing.memory_kib
+        || params.t_cost() < hashing.iterations
+        || params.p_cost() < hashing.parallelism)
+}
+
+/// Leaves the hash alone if the password changed in the meantime.
+#[tracing::instrument(skip(password, old_hash, hashing, db_pool))]
+async fn rehash_password(
+    user_id: Uuid,
+    password: Secret<String>,
+    old_hash: Secret<String>,
+    hashing: PasswordHashingSettings,
+    db_pool: &PgPool,
+) -> Result<(), AuthError> {
+    let password_hash = hash_password(password, hashing).await?;
+
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET password_hash = $1
+        WHERE user_id = $2 AND password_hash = $3
+        "#,
+        password_hash.expose_secret(),
+        user_id,
+        old_hash.expose_secret()
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed to store rehashed password")?;
+
+    Ok(())
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum PasswordRuleError {
+    #[error("The new password is too short.")]
+    TooShort,
+    #[error("The new password is too long.")]
+    TooLong,
+}
+
+/// Rules every new password must follow, whichever way it is set.
+pub fn check_password_rules(password: &Secret<String>) -> Result<(), PasswordRuleError> {
+    const PASSWORD_LOWER_BOUND: usize = 13;
+    if password.expose_secret().len() <= PASSWORD_LOWER_BOUND {
+        return Err(PasswordRuleError::TooShort);
+    }
+
+    const PASSWORD_UPPER_BOUND: usize = 128;
+    if password.expose_secret().len() >= PASSWORD_UPPER_BOUND {
+        return Err(PasswordRuleError::TooLong);
+    }
+
+    Ok(())
+}
+
 #[tracing::instrument(skip_all)]
 pub async fn change_password(
     user_id: Uuid,
     password: Secret<String>,
-    db_pool: &PgPool,
+    hashing: PasswordHashingSettings,
+    executor: impl PgExecutor<'_>,
 ) -> Result<(), AuthError> {
-    let password_hash =
-        spawn_blocking(move || Span::current().in_scope(|| compute_password_hash(password)))
-            .await
-            .context("Failed to spawn blocking password hashing task")??;
+    let password_hash = hash_password(password, hashing).await?;
 
     sqlx::query!(
         r#"
@@ -110,22 +205,101 @@ pub async fn change_password(
         password_hash.expose_secret(),
         user_id
     )
-    .execute(db_pool)
+    .execute(executor)
     .await
     .context("Failed to execute password change query.")?;
 
     Ok(())
 }
 
-fn compute_password_hash(password: Secret<String>) -> Result<Secret<String>, AuthError> {
+/// Hashes `password` on the blocking thread pool, as argon2 is deliberately slow.
+pub async fn hash_password(
+    password: Secret<String>,
+    hashing: PasswordHashingSettings,
+) -> Result<Secret<String>, AuthError> {
+    spawn_blocking(move || Span::current().in_scope(|| compute_password_hash(password, hashing)))
+        .await
+        .context("Failed to spawn blocking password hashing task")?
+}
+
+fn compute_password_hash(
+    password: Secret<String>,
+    hashing: PasswordHashingSettings,
+) -> Result<Secret<String>, AuthError> {
     let salt = SaltString::generate(&mut rand::thread_rng());
-    let password_hash = Argon2::new(
-        Algorithm::Argon2id,
-        Version::V0x13,
-        Params::new(15000, 2, 1, None).unwrap(),
-    )
-    .hash_password(password.expose_secret().as_bytes(), &salt)
-    .map_err(AuthError::AuthError)?;
+    let params = hashing
+        .params()
+        .context("Invalid password hashing settings")?;
+    let password_hash = Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password(password.expose_secret().as_bytes(), &salt)
+        .map_err(AuthError::AuthError)?;
 
     Ok(Secret::new(password_hash.to_string()))
 }
+
+#[cfg(test)]
+mod tests {
+    use secrecy::Secret;
+
+    use super::{
+        compute_password_hash, fallback_password_hash, needs_rehash, verify_password_hash,
+        AuthError,
+    };
+    use crate::configuration::PasswordHashingSettings;
+
+    const HASHING: PasswordHashingSettings = PasswordHashingSettings {
+        memory_kib: 8,
+        iterations: 2,
+        parallelism: 1,
+    };
+
+    fn hash(hashing: PasswordHashingSettings) -> Secret<String> {
+        compute_password_hash(Secret::new("password".into()), hashing).unwrap()
+    }
+
+    #[test]
+    fn hashes_with_the_configured_params_are_kept() {
+        assert!(!needs_rehash(&hash(HASHING), HASHING).unwrap());
+        let stronger = PasswordHashingSettings {
+            memory_kib: 16,
+            ..HASHING
+        };
+        assert!(!needs_rehash(&hash(stronger), HASHING).unwrap());
+    }
+
+    #[test]
+    fn hashes_with_any_lower_cost_are_rehashed() {
+        let more_memory = PasswordHashingSettings {
+            memory_kib: 16,
+            iterations: 1,
+            ..HASHING
+        };
+        let more_iterations = PasswordHashingSettings {
+            iterations: 3,
+            ..HASHING
+        };
+        let target = PasswordHashingSettings {
+            memory_kib: 16,
+            ..HASHING
+        };
+
+        assert!(needs_rehash(&hash(more_memory), target).unwrap());
+        assert!(needs_rehash(&hash(more_iterations), target).unwrap());
+    }
+
+    #[test]
+    fn hashes_from_other_algorithms_are_rehashed() {
+        let argon2i =
+            Secret::new("$argon2i$v=19$m=16,t=2,p=1$c29tZXNhbHQ$TgrUyRcXjxnCQmeS9SZxDg".into());
+        assert!(needs_rehash(&argon2i, HASHING).unwrap());
+    }
+
+    #[test]
+    fn the_fallback_hash_has_the_configured_params_and_never_matches() {
+        let fallback = Secret::new(fallback_password_hash(HASHING));
+
+        assert!(!needs_rehash(&fallback, HASHING).unwrap());
+        let result = verify_password_hash(Secret::new("password".into()), fallback);
+        assert!(matches!(result, Err(AuthError::AuthError(_))));
+    }
+}
@@ -0,0 +1,15 @@
+use std::net::{IpAddr, SocketAddr};
+
+use axum::http::HeaderMap;
+
+/// The address of the client, as passed on by the reverse proxy in
+/// `trusted_header` if there is one, or else the address of the peer.
+pub fn client_ip(headers: &HeaderMap, peer: SocketAddr, trusted_header: Option<&str>) -> IpAddr {
+    trusted_header
+        .and_then(|name| headers.get(name))
+        .and_then(|value| value.to_str().ok())
+        // Proxies append to the header, so only the last address can be trusted
+        .and_then(|value| value.rsplit(',').next())
+        .and_then(|ip| ip.trim().parse().ok())
+        .unwrap_or_else(|| peer.ip())
+}
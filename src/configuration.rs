@@ -1,36 +1,61 @@
-use std::time::Duration;
+use std::{path::PathBuf, sync::Arc, time::Duration};
 
+use argon2::Params;
 use config::{Config, ConfigError, Environment, File};
 use secrecy::{ExposeSecret, Secret};
-use serde::Deserialize;
-use serde_aux::prelude::deserialize_number_from_string;
-use serde_with::{serde_as, DurationMilliSeconds};
+use serde::{de::Error as _, Deserialize, Deserializer};
+use serde_aux::prelude::{deserialize_number_from_string, deserialize_option_number_from_string};
+use serde_with::{serde_as, DurationMilliSeconds, DurationSeconds};
 use sqlx::{
     postgres::{PgConnectOptions, PgSslMode},
     ConnectOptions,
 };
 use url::Url;
 
-use crate::{domain::SubscriberEmail, email_client::EmailClient};
+use crate::{
+    domain::SubscriberEmail,
+    email_client::{EmailClient, HttpEmailClient, MailboxEmailClient, SmtpEmailClient},
+};
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 pub struct Settings {
     pub application: ApplicationSettings,
     pub database: DatabaseSettings,
     pub email_client: EmailClientSettings,
+    pub worker: WorkerSettings,
+    pub login_throttle: LoginThrottleSettings,
+    pub session: SessionSettings,
+    pub password_hashing: PasswordHashingSettings,
     pub redis_uri: Secret<String>,
 }
 
-#[derive(Deserialize)]
+#[serde_as]
+#[derive(Deserialize, Clone)]
 pub struct ApplicationSettings {
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub port: u16,
     pub host: String,
     pub base_url: Url,
     pub hmac_secret: Secret<String>,
+    #[serde(rename = "subscription_token_ttl_secs")]
+    #[serde_as(as = "DurationSeconds")]
+    pub subscription_token_ttl: Duration,
+    #[serde(rename = "invite_ttl_secs")]
+    #[serde_as(as = "DurationSeconds")]
+    pub invite_ttl: Duration,
+    #[serde(rename = "password_reset_ttl_secs")]
+    #[serde_as(as = "DurationSeconds")]
+    pub password_reset_ttl: Duration,
+    /// How long in-flight requests may take to complete once shutdown starts.
+    #[serde(rename = "shutdown_timeout_secs")]
+    #[serde_as(as = "DurationSeconds")]
+    pub shutdown_timeout: Duration,
+    /// Header in which a trusted reverse proxy passes on the client IP, such
+    /// as `X-Forwarded-For`. The peer address is used when unset.
+    pub client_ip_header: Option<String>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 pub struct DatabaseSettings {
     pub username: String,
     pub password: Secret<String>,
@@ -50,6 +75,177 @@ pub struct EmailClientSettings {
     #[serde(rename = "timeout_millis")]
     #[serde_as(as = "DurationMilliSeconds")]
     pub timeout: Duration,
+    #[serde(default)]
+    pub transport: EmailTransportKind,
+    pub smtp: Option<SmtpSettings>,
+    pub mailbox: Option<MailboxSettings>,
+}
+
+#[derive(Deserialize, Clone, Copy, Default, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailTransportKind {
+    #[default]
+    Http,
+    Smtp,
+    Mailbox,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct SmtpSettings {
+    pub host: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
+    pub tls: SmtpTls,
+    pub username: Option<String>,
+    pub password: Option<Secret<String>>,
+    pub auth_mechanism: Option<SmtpAuthMechanism>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct MailboxSettings {
+    pub directory: PathBuf,
+    #[serde(default)]
+    pub page_enabled: bool,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    Plaintext,
+    StartTls,
+    Implicit,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpAuthMechanism {
+    Plain,
+    Login,
+}
+
+#[serde_as]
+#[derive(Deserialize, Clone)]
+pub struct WorkerSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub concurrency: usize,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub batch_size: i64,
+    /// Shared by every worker process connected to the same database.
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub max_messages_per_second: Option<i32>,
+    #[serde(rename = "empty_queue_sleep_millis")]
+    #[serde_as(as = "DurationMilliSeconds")]
+    pub empty_queue_sleep: Duration,
+    #[serde(rename = "error_sleep_millis")]
+    #[serde_as(as = "DurationMilliSeconds")]
+    pub error_sleep: Duration,
+    /// How long a worker has to send the batch it dequeued before other
+    /// workers may pick it up again.
+    #[serde(rename = "claim_timeout_secs")]
+    #[serde_as(as = "DurationSeconds")]
+    pub claim_timeout: Duration,
+    pub retry: RetrySettings,
+    /// Port for `GET /health_check` when the worker runs without the web server.
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub health_check_port: Option<u16>,
+}
+
+#[serde_as]
+#[derive(Deserialize, Clone)]
+pub struct RetrySettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_attempts: i16,
+    #[serde(rename = "base_delay_millis")]
+    #[serde_as(as = "DurationMilliSeconds")]
+    pub base_delay: Duration,
+    #[serde(rename = "max_delay_millis")]
+    #[serde_as(as = "DurationMilliSeconds")]
+    pub max_delay: Duration,
+    /// Fraction of each delay, between 0 and 1, that is randomised.
+    #[serde(deserialize_with = "deserialize_finite")]
+    pub jitter: f64,
+    /// Provider responses worth retrying. Any other status fails the delivery straight away.
+    pub retryable_status_codes: Vec<u16>,
+}
+
+#[serde_as]
+#[derive(Deserialize, Clone)]
+pub struct LoginThrottleSettings {
+    #[serde(default)]
+    pub store: ThrottleStoreKind,
+    /// Namespaces the counters, e.g. when several deployments share a Redis.
+    pub key_prefix: String,
+    /// Failed logins for a username before further attempts are delayed.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub free_failures: u32,
+    /// Doubles with each failure past `free_failures`.
+    #[serde(rename = "base_delay_millis")]
+    #[serde_as(as = "DurationMilliSeconds")]
+    pub base_delay: Duration,
+    /// Failed logins for a username that lock it out.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_failures: u32,
+    /// Failed logins from a client IP, whatever the username, that lock it out.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_failures_per_ip: u32,
+    /// How long a lockout lasts, and how long failures are remembered for.
+    #[serde(rename = "lockout_secs")]
+    #[serde_as(as = "DurationSeconds")]
+    pub lockout: Duration,
+}
+
+#[serde_as]
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct SessionSettings {
+    #[serde(default)]
+    pub store: SessionStoreKind,
+    /// How long a session lasts without any request. Each request restarts it.
+    #[serde(rename = "idle_timeout_secs")]
+    #[serde_as(as = "DurationSeconds")]
+    pub idle_timeout: Duration,
+    /// How long a login lasts, however active the session is.
+    #[serde(rename = "lifetime_secs")]
+    #[serde_as(as = "DurationSeconds")]
+    pub lifetime: Duration,
+}
+
+/// Argon2id cost of new password hashes. Stored hashes with a lower cost are
+/// rehashed the next time their user logs in, so these can be raised over time.
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct PasswordHashingSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub memory_kib: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub iterations: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub parallelism: u32,
+}
+
+#[derive(Deserialize, Clone, Copy, Default, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionStoreKind {
+    #[default]
+    Redis,
+    Postgres,
+    /// For tests and single instance deployments. Sessions are lost on restart.
+    Memory,
+}
+
+#[derive(Deserialize, Clone, Copy, Default, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ThrottleStoreKind {
+    #[default]
+    Redis,
+    /// For deployments without Redis.
+    Postgres,
+}
+
+fn deserialize_finite<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    let value = f64::deserialize(deserializer)?;
+    if !value.is_finite() {
+        return Err(D::Error::custom(format!("{value} is not a finite number")));
+    }
+    Ok(value)
 }
 
 pub fn get_configuration() -> Result<Settings, ConfigError> {
@@ -90,13 +286,89 @@ impl DatabaseSettings {
     }
 }
 
+impl PasswordHashingSettings {
+    pub fn params(&self) -> Result<Params, argon2::Error> {
+        Params::new(self.memory_kib, self.iterations, self.parallelism, None)
+    }
+}
+
 impl EmailClientSettings {
     pub fn client(self) -> EmailClient {
         let sender_email = self.sender().expect("Invalid sender email address");
-        EmailClient::new(self.base_url, sender_email, self.auth_token, self.timeout)
+        match self.transport {
+            EmailTransportKind::Http => Arc::new(HttpEmailClient::new(
+                self.base_url,
+                sender_email,
+                self.auth_token,
+                self.timeout,
+            )),
+            EmailTransportKind::Smtp => {
+                let smtp = self
+                    .smtp
+                    .as_ref()
+                    .expect("Missing `email_client.smtp` settings for the SMTP transport");
+                Arc::new(
+                    SmtpEmailClient::new(smtp, sender_email, self.timeout)
+                        .expect("Failed to build SMTP email client"),
+                )
+            }
+            EmailTransportKind::Mailbox => {
+                let mailbox = self
+                    .mailbox
+                    .as_ref()
+                    .expect("Missing `email_client.mailbox` settings for the mailbox transport");
+                Arc::new(
+                    MailboxEmailClient::new(mailbox.directory.clone(), sender_email)
+                        .expect("Failed to build mailbox email client"),
+                )
+            }
+        }
+    }
+
+    /// The directory served by the `/dev/mailbox` page, if it is enabled.
+    pub fn dev_mailbox(&self) -> Option<PathBuf> {
+        match (self.transport, &self.mailbox) {
+            (EmailTransportKind::Mailbox, Some(mailbox)) if mailbox.page_enabled => {
+                Some(mailbox.directory.clone())
+            }
+            _ => None,
+        }
     }
 
     pub fn sender(&self) -> Result<SubscriberEmail, String> {
         SubscriberEmail::parse(self.sender_email.clone())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use config::{Config, File, FileFormat};
+
+    use super::RetrySettings;
+
+    fn retry_settings(jitter: &str) -> Result<RetrySettings, config::ConfigError> {
+        let yaml = format!(
+            "max_attempts: 10\n\
+            base_delay_millis: 1000\n\
+            max_delay_millis: 60000\n\
+            jitter: {jitter}\n\
+            retryable_status_codes: [500]\n"
+        );
+        Config::builder()
+            .add_source(File::from_str(&yaml, FileFormat::Yaml))
+            .build()?
+            .try_deserialize()
+    }
+
+    #[test]
+    fn a_finite_jitter_is_accepted() {
+        assert_eq!(retry_settings("0.2").unwrap().jitter, 0.2);
+    }
+
+    #[test]
+    fn a_non_finite_jitter_is_rejected() {
+        for jitter in [".nan", ".inf"] {
+            assert!(retry_settings(jitter).is_err(), "{jitter} was accepted");
+        }
+    }
+}
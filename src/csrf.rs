@@ -0,0 +1,89 @@
+use axum::{
+    body::Body,
+    http::{Method, Request, StatusCode},
+    middleware::Next,
+    response::{Html, IntoResponse, Response},
+};
+use http_body::{LengthLimitError, Limited};
+use url::form_urlencoded;
+
+use crate::{log::WrapAndLogErr, user_session::UserSession};
+
+/// The hidden form field carrying [`UserSession::csrf_token`].
+pub const CSRF_FIELD: &str = "csrf_token";
+
+/// The same as the default limit of the `Form` extractor, which only applies
+/// once this middleware has read the body.
+const FORM_SIZE_LIMIT: usize = 2 * 1024 * 1024;
+
+#[derive(thiserror::Error, Debug)]
+pub enum CsrfError {
+    #[error("Something went wrong")]
+    UnexpectedError(#[from] anyhow::Error),
+    #[error("The CSRF token is missing or invalid")]
+    InvalidToken,
+    #[error("The form is too large")]
+    TooLarge,
+}
+
+impl IntoResponse for CsrfError {
+    fn into_response(self) -> Response {
+        match self {
+            CsrfError::InvalidToken => (
+                StatusCode::FORBIDDEN,
+                Html(
+                    r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Forbidden</title>
+</head>
+<body>
+    <p>This form has expired or was sent from another site. Reload the page and try again.</p>
+    <p><a href="/login">&lt;- Back</a></p>
+</body>
+</html>"#,
+                ),
+            )
+                .into_response(),
+            CsrfError::TooLarge => {
+                (StatusCode::PAYLOAD_TOO_LARGE, self.to_string()).into_response()
+            }
+            CsrfError::UnexpectedError(_) => {
+                (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()).into_response()
+            }
+        }
+    }
+}
+
+/// Rejects form posts to the admin area and the login forms unless they carry
+/// the token of the session, so other sites cannot post them on behalf of a
+/// user. Has to run within the session middleware.
+pub async fn csrf_protection(
+    session: UserSession,
+    request: Request<Body>,
+    next: Next<Body>,
+) -> Result<Response, CsrfError> {
+    let path = request.uri().path();
+    if request.method() != Method::POST
+        || !(path.starts_with("/admin") || path.starts_with("/login"))
+    {
+        return Ok(next.run(request).await);
+    }
+
+    let (parts, body) = request.into_parts();
+    let body = match hyper::body::to_bytes(Limited::new(body, FORM_SIZE_LIMIT)).await {
+        Ok(body) => body,
+        Err(e) if e.is::<LengthLimitError>() => return Err(CsrfError::TooLarge),
+        Err(e) => Err(anyhow::anyhow!(e)).wrap_and_log_err("Failed to read form")?,
+    };
+    let token = form_urlencoded::parse(&body)
+        .find(|(name, _)| name == CSRF_FIELD)
+        .map(|(_, token)| token);
+    if !token.is_some_and(|token| session.verify_csrf_token(&token)) {
+        tracing::warn!("Rejected a form post without a valid CSRF token");
+        return Err(CsrfError::InvalidToken);
+    }
+
+    Ok(next.run(Request::from_parts(parts, Body::from(body))).await)
+}
@@ -1,7 +1,17 @@
 mod new_subscriber;
+mod one_time_token;
+mod recovery_code;
+mod role;
 mod subscriber_email;
 mod subscriber_name;
+mod totp_secret;
+mod unsubscribe_token;
 
 pub use new_subscriber::NewSubscriber;
+pub use one_time_token::{is_expired, OneTimeToken};
+pub use recovery_code::RecoveryCode;
+pub use role::Role;
 pub use subscriber_email::SubscriberEmail;
 pub use subscriber_name::SubscriberName;
+pub use totp_secret::TotpSecret;
+pub use unsubscribe_token::UnsubscribeToken;
@@ -0,0 +1,81 @@
+use std::{iter, time::Duration};
+
+use chrono::{DateTime, Utc};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use sha2::{Digest, Sha256};
+
+/// A random token sent in an email link. Only its hash is stored, so the
+/// database alone is not enough to use the link.
+pub struct OneTimeToken(String);
+
+impl OneTimeToken {
+    pub fn generate() -> Self {
+        let mut rng = thread_rng();
+        Self(
+            iter::repeat_with(|| rng.sample(Alphanumeric))
+                .map(char::from)
+                .take(32)
+                .collect(),
+        )
+    }
+
+    pub fn hash(&self) -> String {
+        format!("{:x}", Sha256::digest(self.0.as_bytes()))
+    }
+}
+
+impl From<String> for OneTimeToken {
+    fn from(token: String) -> Self {
+        Self(token)
+    }
+}
+
+impl AsRef<str> for OneTimeToken {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Whether a token created at `created_at` has outlived its `ttl`. A `ttl` too
+/// large for `chrono` never expires.
+pub fn is_expired(created_at: DateTime<Utc>, ttl: Duration) -> bool {
+    match chrono::Duration::from_std(ttl) {
+        Ok(ttl) => created_at + ttl < Utc::now(),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::Utc;
+
+    use crate::domain::{is_expired, OneTimeToken};
+
+    #[test]
+    fn generated_tokens_are_unique() {
+        let token = OneTimeToken::generate();
+        assert_eq!(token.as_ref().len(), 32);
+        assert_ne!(token.as_ref(), OneTimeToken::generate().as_ref());
+    }
+
+    #[test]
+    fn the_hash_depends_only_on_the_token() {
+        let token = OneTimeToken::generate();
+        let same_token = OneTimeToken::from(token.as_ref().to_string());
+
+        assert_eq!(token.hash(), same_token.hash());
+        assert_ne!(token.hash(), token.as_ref());
+        assert_ne!(token.hash(), OneTimeToken::generate().hash());
+    }
+
+    #[test]
+    fn tokens_expire_once_their_ttl_has_passed() {
+        let ttl = Duration::from_secs(60);
+
+        assert!(!is_expired(Utc::now(), ttl));
+        assert!(is_expired(Utc::now() - chrono::Duration::seconds(61), ttl));
+        assert!(!is_expired(Utc::now(), Duration::MAX));
+    }
+}
@@ -0,0 +1,67 @@
+use std::iter;
+
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use sha2::{Digest, Sha256};
+
+/// A single-use code that stands in for a TOTP code when the authenticator
+/// is lost. Like [`OneTimeToken`](super::OneTimeToken), only its hash is stored.
+pub struct RecoveryCode(String);
+
+impl RecoveryCode {
+    pub fn generate() -> Self {
+        let mut rng = thread_rng();
+        let chars: String = iter::repeat_with(|| rng.sample(Alphanumeric))
+            .map(|c| char::from(c).to_ascii_lowercase())
+            .take(10)
+            .collect();
+        Self(format!("{}-{}", &chars[..5], &chars[5..]))
+    }
+
+    /// Ignores case, spaces and dashes, which are easy to get wrong when
+    /// typing a code back in.
+    pub fn hash(&self) -> String {
+        let normalized: String = self
+            .0
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != '-')
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+        format!("{:x}", Sha256::digest(normalized.as_bytes()))
+    }
+}
+
+impl From<String> for RecoveryCode {
+    fn from(code: String) -> Self {
+        Self(code)
+    }
+}
+
+impl AsRef<str> for RecoveryCode {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::RecoveryCode;
+
+    #[test]
+    fn generated_codes_are_unique() {
+        let code = RecoveryCode::generate();
+        assert_eq!(code.as_ref().len(), 11);
+        assert_ne!(code.as_ref(), RecoveryCode::generate().as_ref());
+    }
+
+    #[test]
+    fn the_hash_ignores_how_the_code_is_typed() {
+        let code = RecoveryCode::generate();
+        let typed = RecoveryCode::from(format!(
+            " {} ",
+            code.as_ref().replace('-', "").to_uppercase()
+        ));
+
+        assert_eq!(code.hash(), typed.hash());
+        assert_ne!(code.hash(), RecoveryCode::generate().hash());
+    }
+}
@@ -0,0 +1,66 @@
+use std::fmt::Display;
+
+/// What an admin user is allowed to do. Variants are ordered from least to most
+/// privileged, so a role grants everything the roles before it do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    /// Can look at stats, such as delivery failures.
+    Viewer,
+    /// Can also draft and publish newsletters.
+    Editor,
+    /// Can also manage other admin users.
+    Owner,
+}
+
+impl Role {
+    pub fn parse(s: String) -> Result<Role, String> {
+        match s.as_str() {
+            "viewer" => Ok(Role::Viewer),
+            "editor" => Ok(Role::Editor),
+            "owner" => Ok(Role::Owner),
+            _ => Err(format!("{s} is not a valid role")),
+        }
+    }
+}
+
+impl AsRef<str> for Role {
+    fn as_ref(&self) -> &str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Editor => "editor",
+            Role::Owner => "owner",
+        }
+    }
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_ref().fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio_test::assert_err;
+
+    use crate::domain::Role;
+
+    #[test]
+    fn roles_round_trip_through_their_names() {
+        for role in [Role::Viewer, Role::Editor, Role::Owner] {
+            assert_eq!(Role::parse(role.to_string()), Ok(role));
+        }
+    }
+
+    #[test]
+    fn unknown_roles_are_rejected() {
+        assert_err!(Role::parse("admin".into()));
+        assert_err!(Role::parse("Owner".into()));
+    }
+
+    #[test]
+    fn owners_have_every_permission_of_editors_and_viewers() {
+        assert!(Role::Owner > Role::Editor);
+        assert!(Role::Editor > Role::Viewer);
+    }
+}
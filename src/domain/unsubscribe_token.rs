@@ -0,0 +1,110 @@
+use std::fmt;
+
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use uuid::Uuid;
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Debug, Clone)]
+pub struct UnsubscribeToken {
+    subscriber_id: Uuid,
+    token: String,
+}
+
+impl UnsubscribeToken {
+    pub fn generate(subscriber_id: Uuid, hmac_secret: &Secret<String>) -> Self {
+        let tag = hex::encode(mac(subscriber_id, hmac_secret).finalize().into_bytes());
+        Self {
+            subscriber_id,
+            token: format!("{}.{tag}", subscriber_id.simple()),
+        }
+    }
+
+    pub fn parse(s: String, hmac_secret: &Secret<String>) -> Result<UnsubscribeToken, String> {
+        let invalid = || format!("{s} is not a valid unsubscribe token");
+
+        let (subscriber_id, tag) = s.split_once('.').ok_or_else(invalid)?;
+        let subscriber_id = Uuid::try_parse(subscriber_id).map_err(|_| invalid())?;
+        let tag = hex::decode(tag).map_err(|_| invalid())?;
+        mac(subscriber_id, hmac_secret)
+            .verify_slice(&tag)
+            .map_err(|_| invalid())?;
+
+        Ok(Self {
+            subscriber_id,
+            token: s,
+        })
+    }
+
+    pub fn subscriber_id(&self) -> Uuid {
+        self.subscriber_id
+    }
+}
+
+fn mac(subscriber_id: Uuid, hmac_secret: &Secret<String>) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(hmac_secret.expose_secret().as_bytes())
+        .expect("HMAC can take a key of any size");
+    mac.update(b"unsubscribe:");
+    mac.update(subscriber_id.as_bytes());
+    mac
+}
+
+impl fmt::Display for UnsubscribeToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.token.fmt(f)
+    }
+}
+
+impl AsRef<str> for UnsubscribeToken {
+    fn as_ref(&self) -> &str {
+        &self.token
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::Secret;
+    use tokio_test::{assert_err, assert_ok};
+    use uuid::Uuid;
+
+    use super::UnsubscribeToken;
+
+    fn secret() -> Secret<String> {
+        Secret::new("super-long-and-secret-random-key".to_string())
+    }
+
+    #[test]
+    fn a_generated_token_is_parsed_successfully() {
+        let subscriber_id = Uuid::new_v4();
+        let token = UnsubscribeToken::generate(subscriber_id, &secret());
+
+        let token = assert_ok!(UnsubscribeToken::parse(token.to_string(), &secret()));
+        assert_eq!(token.subscriber_id(), subscriber_id);
+    }
+
+    #[test]
+    fn a_token_signed_with_another_secret_is_rejected() {
+        let token = UnsubscribeToken::generate(Uuid::new_v4(), &secret());
+        let other_secret = Secret::new("another-secret".to_string());
+
+        assert_err!(UnsubscribeToken::parse(token.to_string(), &other_secret));
+    }
+
+    #[test]
+    fn a_token_for_another_subscriber_is_rejected() {
+        let token = UnsubscribeToken::generate(Uuid::new_v4(), &secret()).to_string();
+        let (_, tag) = token.split_once('.').unwrap();
+        let forged = format!("{}.{tag}", Uuid::new_v4().simple());
+
+        assert_err!(UnsubscribeToken::parse(forged, &secret()));
+    }
+
+    #[test]
+    fn malformed_tokens_are_rejected() {
+        for token in ["", "no-separator", "not-a-uuid.abcd", "."] {
+            assert_err!(UnsubscribeToken::parse(token.to_string(), &secret()));
+        }
+    }
+}
@@ -0,0 +1,487 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use reqwest::{header::RETRY_AFTER, Client, Response, Url};
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+
+use super::{EmailError, EmailMessage, EmailTransport};
+use crate::domain::SubscriberEmail;
+
+/// Postmark accepts up to 500 messages per `/email/batch` call.
+const MAX_BATCH_SIZE: usize = 500;
+
+#[derive(Debug, Clone)]
+pub struct HttpEmailClient {
+    http_client: Client,
+    base_url: Url,
+    sender: SubscriberEmail,
+    auth_token: Secret<String>,
+}
+
+impl HttpEmailClient {
+    pub fn new(
+        base_url: Url,
+        sender: SubscriberEmail,
+        auth_token: Secret<String>,
+        timeout: Duration,
+    ) -> Self {
+        Self {
+            http_client: Client::builder().timeout(timeout).build().unwrap(),
+            base_url,
+            sender,
+            auth_token,
+        }
+    }
+}
+
+#[async_trait]
+impl EmailTransport for HttpEmailClient {
+    async fn send_with_headers(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_body: &str,
+        text_body: &str,
+        headers: &[(&str, &str)],
+    ) -> Result<(), EmailError> {
+        let request_body = SendEmailRequest {
+            from: self.sender.as_ref(),
+            to: recipient.as_ref(),
+            subject,
+            html_body,
+            text_body,
+            headers: headers
+                .iter()
+                .map(|&(name, value)| EmailHeader { name, value })
+                .collect(),
+        };
+        let response = self
+            .http_client
+            .post(self.base_url.join("email").unwrap())
+            .bearer_auth(self.auth_token.expose_secret())
+            .json(&request_body)
+            .send()
+            .await
+            .context("Failed to send email request")?;
+        check_status(response)?;
+        Ok(())
+    }
+
+    async fn send_batch(&self, messages: &[EmailMessage]) -> Vec<Result<(), EmailError>> {
+        let mut results = Vec::with_capacity(messages.len());
+        for chunk in messages.chunks(MAX_BATCH_SIZE) {
+            match self.send_chunk(chunk).await {
+                Ok(chunk_results) => results.extend(chunk_results),
+                Err(e) => results.extend(chunk.iter().map(|_| Err(e.replicate()))),
+            }
+        }
+        results
+    }
+}
+
+impl HttpEmailClient {
+    async fn send_chunk(
+        &self,
+        messages: &[EmailMessage],
+    ) -> Result<Vec<Result<(), EmailError>>, EmailError> {
+        let headers: Vec<_> = messages.iter().map(EmailMessage::headers).collect();
+        let request_body: Vec<_> = messages
+            .iter()
+            .zip(&headers)
+            .map(|(message, headers)| SendEmailRequest {
+                from: self.sender.as_ref(),
+                to: message.recipient.as_ref(),
+                subject: &message.subject,
+                html_body: &message.html_body,
+                text_body: &message.text_body,
+                headers: headers
+                    .iter()
+                    .map(|&(name, value)| EmailHeader { name, value })
+                    .collect(),
+            })
+            .collect();
+        let response = self
+            .http_client
+            .post(self.base_url.join("email/batch").unwrap())
+            .bearer_auth(self.auth_token.expose_secret())
+            .json(&request_body)
+            .send()
+            .await
+            .context("Failed to send email batch request")?;
+        let responses: Vec<SendEmailResponse> = check_status(response)?
+            .json()
+            .await
+            .context("Failed to parse email batch response")?;
+        if responses.len() != messages.len() {
+            return Err(anyhow::anyhow!(
+                "Email API returned {} results for {} messages",
+                responses.len(),
+                messages.len()
+            )
+            .into());
+        }
+
+        Ok(responses
+            .into_iter()
+            .map(|response| match response.error_code {
+                0 => Ok(()),
+                code => Err(EmailError::from(anyhow::anyhow!(
+                    "Email API rejected the message ({code}): {}",
+                    response.message
+                ))
+                .permanent()),
+            })
+            .collect())
+    }
+}
+
+fn check_status(response: Response) -> Result<Response, EmailError> {
+    let status = response.status();
+    let retry_after = response
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_retry_after);
+    match response.error_for_status() {
+        Ok(response) => Ok(response),
+        Err(e) => Err(EmailError::from(
+            anyhow::Error::new(e).context("Email API returned an error"),
+        )
+        .with_status(status.as_u16(), retry_after)),
+    }
+}
+
+/// `Retry-After` is either a number of seconds or an HTTP date.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    if let Ok(seconds) = value.trim().parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (date.with_timezone(&Utc) - Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    subject: &'a str,
+    html_body: &'a str,
+    text_body: &'a str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    headers: Vec<EmailHeader<'a>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct EmailHeader<'a> {
+    name: &'a str,
+    value: &'a str,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailResponse {
+    error_code: i64,
+    #[serde(default)]
+    message: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use fake::{
+        faker::{
+            internet::en::SafeEmail,
+            lorem::en::{Paragraph, Sentence},
+        },
+        Fake, Faker,
+    };
+    use reqwest::Url;
+    use secrecy::Secret;
+    use tokio_test::{assert_err, assert_ok};
+    use wiremock::{
+        matchers::{any, header, header_exists, method, path},
+        Match, Mock, MockServer, Request, ResponseTemplate,
+    };
+
+    use crate::{
+        domain::SubscriberEmail,
+        email_client::{EmailMessage, EmailTransport, HttpEmailClient},
+    };
+
+    #[tokio::test]
+    async fn send_email_fires_a_request_to_url() {
+        let (email_client, mock_server) = client_and_mock_server().await;
+
+        Mock::given(header_exists("Authorization"))
+            .and(header("Content-Type", "application/json"))
+            .and(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let _ = email_client
+            .send(&email(), &subject(), &content(), &content())
+            .await;
+    }
+
+    #[tokio::test]
+    async fn send_email_with_headers_includes_them_in_the_request() {
+        let (email_client, mock_server) = client_and_mock_server().await;
+
+        Mock::given(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .and(HeadersMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let _ = email_client
+            .send_with_headers(
+                &email(),
+                &subject(),
+                &content(),
+                &content(),
+                &[("List-Unsubscribe-Post", "List-Unsubscribe=One-Click")],
+            )
+            .await;
+    }
+
+    #[tokio::test]
+    async fn send_email_succeed_if_the_server_returns_200() {
+        let (email_client, mock_server) = client_and_mock_server().await;
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        assert_ok!(
+            email_client
+                .send(&email(), &subject(), &content(), &content())
+                .await
+        );
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_the_server_returns_500() {
+        let (email_client, mock_server) = client_and_mock_server().await;
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        assert_err!(
+            email_client
+                .send(&email(), &subject(), &content(), &content())
+                .await
+        );
+    }
+
+    #[tokio::test]
+    async fn send_email_times_out_if_the_server_takes_too_long() {
+        let (email_client, mock_server) = client_and_mock_server().await;
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(180)))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        assert_err!(
+            email_client
+                .send(&email(), &subject(), &content(), &content())
+                .await
+        );
+    }
+
+    #[tokio::test]
+    async fn send_batch_splits_messages_into_requests_of_at_most_500() {
+        let (email_client, mock_server) = client_and_mock_server().await;
+
+        Mock::given(path("/email/batch"))
+            .and(method("POST"))
+            .respond_with(accept_batch)
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        let messages: Vec<_> = (0..501).map(|_| message()).collect();
+        let results = email_client.send_batch(&messages).await;
+
+        assert_eq!(results.len(), 501);
+        assert!(results.iter().all(Result::is_ok));
+        let requests = mock_server.received_requests().await.unwrap();
+        let sizes: Vec<_> = requests
+            .iter()
+            .map(|r| {
+                let body: serde_json::Value = serde_json::from_slice(&r.body).unwrap();
+                body.as_array().unwrap().len()
+            })
+            .collect();
+        assert_eq!(sizes, vec![500, 1]);
+    }
+
+    #[tokio::test]
+    async fn send_batch_reports_a_result_per_recipient() {
+        let (email_client, mock_server) = client_and_mock_server().await;
+
+        Mock::given(path("/email/batch"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                { "ErrorCode": 0, "Message": "OK" },
+                { "ErrorCode": 406, "Message": "Inactive recipient" },
+            ])))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let results = email_client.send_batch(&[message(), message()]).await;
+
+        assert_ok!(&results[0]);
+        assert_err!(&results[1]);
+    }
+
+    #[tokio::test]
+    async fn send_batch_fails_every_message_if_the_server_returns_500() {
+        let (email_client, mock_server) = client_and_mock_server().await;
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let results = email_client.send_batch(&[message(), message()]).await;
+
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(Result::is_err));
+    }
+
+    #[tokio::test]
+    async fn send_batch_reports_the_status_and_retry_after_of_a_rejected_request() {
+        let (email_client, mock_server) = client_and_mock_server().await;
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "120"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let results = email_client.send_batch(&[message(), message()]).await;
+
+        for result in results {
+            let error = result.unwrap_err();
+            assert_eq!(error.status(), Some(429));
+            assert_eq!(error.retry_after(), Some(Duration::from_secs(120)));
+            assert!(!error.is_permanent());
+        }
+    }
+
+    #[tokio::test]
+    async fn send_batch_marks_rejected_recipients_as_permanent_failures() {
+        let (email_client, mock_server) = client_and_mock_server().await;
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                { "ErrorCode": 406, "Message": "Inactive recipient" },
+            ])))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let results = email_client.send_batch(&[message()]).await;
+
+        assert!(results[0].as_ref().unwrap_err().is_permanent());
+    }
+
+    fn accept_batch(request: &Request) -> ResponseTemplate {
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        let results: Vec<_> = body
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|_| serde_json::json!({ "ErrorCode": 0, "Message": "OK" }))
+            .collect();
+        ResponseTemplate::new(200).set_body_json(results)
+    }
+
+    fn message() -> EmailMessage {
+        EmailMessage {
+            recipient: email(),
+            subject: subject(),
+            html_body: content(),
+            text_body: content(),
+            headers: vec![],
+        }
+    }
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    fn subject() -> String {
+        Sentence(1..2).fake()
+    }
+
+    fn content() -> String {
+        Paragraph(1..10).fake()
+    }
+
+    async fn client_and_mock_server() -> (HttpEmailClient, MockServer) {
+        let mock_server = MockServer::start().await;
+        let email_client = HttpEmailClient::new(
+            Url::parse(&mock_server.uri()).unwrap(),
+            email(),
+            Secret::new(Faker.fake()),
+            Duration::from_millis(100),
+        );
+
+        (email_client, mock_server)
+    }
+
+    struct SendEmailBodyMatcher;
+    impl Match for SendEmailBodyMatcher {
+        fn matches(&self, request: &Request) -> bool {
+            // Don't rely on deserialize, check the raw value
+            if let Ok(serde_json::Value::Object(body)) = serde_json::from_slice(&request.body) {
+                body.get("From").is_some()
+                    && body.get("To").is_some()
+                    && body.get("Subject").is_some()
+                    && body.get("HtmlBody").is_some()
+                    && body.get("TextBody").is_some()
+            } else {
+                false
+            }
+        }
+    }
+
+    struct HeadersMatcher;
+    impl Match for HeadersMatcher {
+        fn matches(&self, request: &Request) -> bool {
+            let body: serde_json::Value = match serde_json::from_slice(&request.body) {
+                Ok(body) => body,
+                Err(_) => return false,
+            };
+            body["Headers"][0]["Name"] == "List-Unsubscribe-Post"
+                && body["Headers"][0]["Value"] == "List-Unsubscribe=One-Click"
+        }
+    }
+}
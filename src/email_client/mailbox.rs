@@ -0,0 +1,140 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::Utc;
+use lettre::message::{
+    header::{ContentTransferEncoding, ContentType},
+    Body, Mailbox, MultiPart, SinglePart,
+};
+use uuid::Uuid;
+
+use super::{format_message, EmailError, EmailTransport};
+use crate::domain::SubscriberEmail;
+
+/// Writes every outgoing message as an `.eml` file instead of delivering it.
+pub struct MailboxEmailClient {
+    directory: PathBuf,
+    sender: Mailbox,
+}
+
+impl MailboxEmailClient {
+    pub fn new(directory: PathBuf, sender: SubscriberEmail) -> Result<Self, anyhow::Error> {
+        std::fs::create_dir_all(&directory).with_context(|| {
+            format!("Failed to create mailbox directory {}", directory.display())
+        })?;
+        Ok(Self {
+            directory,
+            sender: sender.as_ref().parse().context("Invalid sender address")?,
+        })
+    }
+}
+
+#[async_trait]
+impl EmailTransport for MailboxEmailClient {
+    async fn send_with_headers(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_body: &str,
+        text_body: &str,
+        headers: &[(&str, &str)],
+    ) -> Result<(), EmailError> {
+        let body = MultiPart::alternative()
+            .singlepart(
+                SinglePart::builder()
+                    .header(ContentType::TEXT_PLAIN)
+                    .body(readable_body(text_body)),
+            )
+            .singlepart(
+                SinglePart::builder()
+                    .header(ContentType::TEXT_HTML)
+                    .body(readable_body(html_body)),
+            );
+        let (_, message) = format_message(&self.sender, recipient, subject, body, headers)?;
+
+        let file_name = format!(
+            "{}-{}.eml",
+            Utc::now().format("%Y%m%dT%H%M%S%.6f"),
+            Uuid::new_v4().simple()
+        );
+        let path = self.directory.join(file_name);
+        tokio::fs::write(&path, message)
+            .await
+            .with_context(|| format!("Failed to write email to {}", path.display()))?;
+
+        tracing::info!(
+            path = %path.display(),
+            recipient = recipient.as_ref(),
+            subject,
+            "Email written to dev mailbox"
+        );
+        Ok(())
+    }
+}
+
+/// The message never leaves the disk, so the body is stored unencoded and links
+/// can be copied straight out of the file.
+fn readable_body(body: &str) -> Body {
+    Body::new_with_encoding(body.to_string(), ContentTransferEncoding::Binary)
+        .unwrap_or_else(Body::new)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use fake::{faker::internet::en::SafeEmail, Fake};
+    use tokio_test::assert_ok;
+    use uuid::Uuid;
+
+    use crate::{
+        domain::SubscriberEmail,
+        email_client::{EmailTransport, MailboxEmailClient},
+    };
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    fn mailbox_dir() -> PathBuf {
+        std::env::temp_dir().join(Uuid::new_v4().to_string())
+    }
+
+    #[tokio::test]
+    async fn send_email_writes_an_eml_file_with_both_parts() {
+        let directory = mailbox_dir();
+        let email_client = MailboxEmailClient::new(directory.clone(), email()).unwrap();
+        let link = format!(
+            "http://127.0.0.1/subscriptions/confirm?subscription_token={}",
+            "a".repeat(80)
+        );
+
+        assert_ok!(
+            email_client
+                .send_with_headers(
+                    &email(),
+                    "Newsletter title",
+                    &format!("<p>Newsletter body as HTML {link}</p>"),
+                    &format!("Newsletter body as plain text {link}"),
+                    &[("List-Unsubscribe-Post", "List-Unsubscribe=One-Click")],
+                )
+                .await
+        );
+
+        let mut entries = std::fs::read_dir(&directory).unwrap();
+        let path = entries.next().unwrap().unwrap().path();
+        assert!(entries.next().is_none());
+        assert_eq!(path.extension().unwrap(), "eml");
+
+        let message = std::fs::read_to_string(&path).unwrap();
+        assert!(message.contains("Subject: Newsletter title\r\n"));
+        assert!(message.contains("List-Unsubscribe-Post: List-Unsubscribe=One-Click\r\n"));
+        assert!(message.contains("Content-Type: text/plain"));
+        assert!(message.contains("Content-Type: text/html"));
+        assert!(message.contains(&format!("Newsletter body as plain text {link}")));
+        assert!(message.contains(&format!("<p>Newsletter body as HTML {link}</p>")));
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,182 @@
+mod http;
+mod mailbox;
+mod smtp;
+
+use std::{fmt, sync::Arc, time::Duration};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use lettre::{
+    address::Envelope,
+    message::{
+        header::{HeaderName, HeaderValue, Headers},
+        Mailbox, MultiPart,
+    },
+    Message,
+};
+
+use crate::domain::SubscriberEmail;
+
+pub use http::HttpEmailClient;
+pub use mailbox::MailboxEmailClient;
+pub use smtp::SmtpEmailClient;
+
+pub type EmailClient = Arc<dyn EmailTransport>;
+
+/// Why a message was not sent, with what the provider told us about retrying it.
+#[derive(Debug)]
+pub struct EmailError {
+    error: anyhow::Error,
+    status: Option<u16>,
+    retry_after: Option<Duration>,
+    permanent: bool,
+}
+
+impl EmailError {
+    /// The HTTP status the provider answered with, if it answered at all.
+    pub fn status(&self) -> Option<u16> {
+        self.status
+    }
+
+    /// How long the provider asked us to wait before trying again.
+    pub fn retry_after(&self) -> Option<Duration> {
+        self.retry_after
+    }
+
+    /// Whether the provider refused the message itself, so sending it again cannot succeed.
+    pub fn is_permanent(&self) -> bool {
+        self.permanent
+    }
+
+    pub(crate) fn with_status(mut self, status: u16, retry_after: Option<Duration>) -> Self {
+        self.status = Some(status);
+        self.retry_after = retry_after;
+        self
+    }
+
+    pub(crate) fn permanent(mut self) -> Self {
+        self.permanent = true;
+        self
+    }
+
+    /// Copies the error for every message of a batch that failed as a whole.
+    fn replicate(&self) -> Self {
+        Self {
+            error: anyhow::anyhow!("{:#}", self.error),
+            ..*self
+        }
+    }
+}
+
+impl From<anyhow::Error> for EmailError {
+    fn from(error: anyhow::Error) -> Self {
+        Self {
+            error,
+            status: None,
+            retry_after: None,
+            permanent: false,
+        }
+    }
+}
+
+impl fmt::Display for EmailError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.error, f)
+    }
+}
+
+impl std::error::Error for EmailError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.error.source()
+    }
+}
+
+/// A fully rendered message, ready to be handed to [`EmailTransport::send_batch`].
+pub struct EmailMessage {
+    pub recipient: SubscriberEmail,
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
+    pub headers: Vec<(String, String)>,
+}
+
+impl EmailMessage {
+    fn headers(&self) -> Vec<(&str, &str)> {
+        self.headers
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect()
+    }
+}
+
+#[async_trait]
+pub trait EmailTransport: Send + Sync {
+    async fn send_with_headers(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_body: &str,
+        text_body: &str,
+        headers: &[(&str, &str)],
+    ) -> Result<(), EmailError>;
+
+    async fn send(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_body: &str,
+        text_body: &str,
+    ) -> Result<(), EmailError> {
+        self.send_with_headers(recipient, subject, html_body, text_body, &[])
+            .await
+    }
+
+    /// Sends every message and returns one result per message, in order.
+    async fn send_batch(&self, messages: &[EmailMessage]) -> Vec<Result<(), EmailError>> {
+        let mut results = Vec::with_capacity(messages.len());
+        for message in messages {
+            let result = self
+                .send_with_headers(
+                    &message.recipient,
+                    &message.subject,
+                    &message.html_body,
+                    &message.text_body,
+                    &message.headers(),
+                )
+                .await;
+            results.push(result);
+        }
+        results
+    }
+}
+
+/// Formats a MIME message. `lettre` only accepts typed headers when building a
+/// message, so any extra headers are prepended to the formatted output.
+fn format_message(
+    sender: &Mailbox,
+    recipient: &SubscriberEmail,
+    subject: &str,
+    body: MultiPart,
+    headers: &[(&str, &str)],
+) -> Result<(Envelope, Vec<u8>), anyhow::Error> {
+    let message = Message::builder()
+        .from(sender.clone())
+        .to(recipient
+            .as_ref()
+            .parse()
+            .context("Invalid recipient address")?)
+        .subject(subject)
+        .multipart(body)
+        .context("Failed to build email message")?;
+
+    let mut extra_headers = Headers::new();
+    for &(name, value) in headers {
+        let name = HeaderName::new_from_ascii(name.to_string())
+            .with_context(|| format!("Invalid email header name {name}"))?;
+        extra_headers.insert_raw(HeaderValue::new(name, value.to_string()));
+    }
+
+    let mut formatted = extra_headers.to_string().into_bytes();
+    formatted.extend(message.formatted());
+    Ok((message.envelope().clone(), formatted))
+}
@@ -0,0 +1,230 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use lettre::{
+    message::{Mailbox, MultiPart},
+    transport::smtp::authentication::{Credentials, Mechanism},
+    AsyncSmtpTransport, AsyncTransport, Tokio1Executor,
+};
+use secrecy::ExposeSecret;
+
+use super::{format_message, EmailError, EmailTransport};
+use crate::{
+    configuration::{SmtpAuthMechanism, SmtpSettings, SmtpTls},
+    domain::SubscriberEmail,
+};
+
+pub struct SmtpEmailClient {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    sender: Mailbox,
+}
+
+impl SmtpEmailClient {
+    pub fn new(
+        settings: &SmtpSettings,
+        sender: SubscriberEmail,
+        timeout: Duration,
+    ) -> Result<Self, anyhow::Error> {
+        let builder = match settings.tls {
+            SmtpTls::Implicit => AsyncSmtpTransport::<Tokio1Executor>::relay(&settings.host)?,
+            SmtpTls::StartTls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&settings.host)?
+            }
+            SmtpTls::Plaintext => {
+                AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&settings.host)
+            }
+        };
+        let mut builder = builder.port(settings.port).timeout(Some(timeout));
+        if let (Some(username), Some(password)) = (&settings.username, &settings.password) {
+            builder = builder.credentials(Credentials::new(
+                username.clone(),
+                password.expose_secret().clone(),
+            ));
+        }
+        if let Some(mechanism) = settings.auth_mechanism {
+            builder = builder.authentication(vec![mechanism.into()]);
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+            sender: sender.as_ref().parse().context("Invalid sender address")?,
+        })
+    }
+}
+
+#[async_trait]
+impl EmailTransport for SmtpEmailClient {
+    async fn send_with_headers(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_body: &str,
+        text_body: &str,
+        headers: &[(&str, &str)],
+    ) -> Result<(), EmailError> {
+        let body = MultiPart::alternative_plain_html(text_body.to_string(), html_body.to_string());
+        let (envelope, message) = format_message(&self.sender, recipient, subject, body, headers)?;
+        match self.transport.send_raw(&envelope, &message).await {
+            Ok(_) => Ok(()),
+            // 5xx replies mean the server refused the message itself
+            Err(e) if e.is_permanent() => Err(EmailError::from(
+                anyhow::Error::new(e).context("SMTP server rejected the email"),
+            )
+            .permanent()),
+            Err(e) => Err(anyhow::Error::new(e)
+                .context("Failed to send email over SMTP")
+                .into()),
+        }
+    }
+}
+
+impl From<SmtpAuthMechanism> for Mechanism {
+    fn from(mechanism: SmtpAuthMechanism) -> Self {
+        match mechanism {
+            SmtpAuthMechanism::Plain => Mechanism::Plain,
+            SmtpAuthMechanism::Login => Mechanism::Login,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, time::Duration};
+
+    use fake::{
+        faker::{internet::en::SafeEmail, lorem::en::Sentence},
+        Fake,
+    };
+    use secrecy::Secret;
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+        task::JoinHandle,
+    };
+    use tokio_test::assert_ok;
+
+    use crate::{
+        configuration::{SmtpAuthMechanism, SmtpSettings, SmtpTls},
+        domain::SubscriberEmail,
+        email_client::{EmailTransport, SmtpEmailClient},
+    };
+
+    #[tokio::test]
+    async fn send_email_delivers_the_message_to_the_smtp_server() {
+        let (address, sink) = smtp_sink().await;
+        let email_client = client(address, SmtpAuthMechanism::Plain);
+
+        assert_ok!(
+            email_client
+                .send_with_headers(
+                    &email(),
+                    "Newsletter title",
+                    "<p>Newsletter body as HTML</p>",
+                    "Newsletter body as plain text",
+                    &[("List-Unsubscribe-Post", "List-Unsubscribe=One-Click")],
+                )
+                .await
+        );
+
+        let transcript = sink.await.unwrap();
+        assert!(transcript.iter().any(|l| l.starts_with("AUTH PLAIN")));
+        assert!(transcript.iter().any(|l| l == "Subject: Newsletter title"));
+        assert!(transcript
+            .iter()
+            .any(|l| l == "List-Unsubscribe-Post: List-Unsubscribe=One-Click"));
+        assert!(transcript
+            .iter()
+            .any(|l| l == "Newsletter body as plain text"));
+        assert!(transcript
+            .iter()
+            .any(|l| l == "<p>Newsletter body as HTML</p>"));
+    }
+
+    #[tokio::test]
+    async fn send_email_authenticates_with_the_configured_mechanism() {
+        let (address, sink) = smtp_sink().await;
+        let email_client = client(address, SmtpAuthMechanism::Login);
+
+        assert_ok!(
+            email_client
+                .send(&email(), &subject(), "<p>content</p>", "content")
+                .await
+        );
+
+        let transcript = sink.await.unwrap();
+        assert!(transcript.iter().any(|l| l == "AUTH LOGIN"));
+    }
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    fn subject() -> String {
+        Sentence(1..2).fake()
+    }
+
+    fn client(address: SocketAddr, auth_mechanism: SmtpAuthMechanism) -> SmtpEmailClient {
+        let settings = SmtpSettings {
+            host: address.ip().to_string(),
+            port: address.port(),
+            tls: SmtpTls::Plaintext,
+            username: Some("user".into()),
+            password: Some(Secret::new("password".into())),
+            auth_mechanism: Some(auth_mechanism),
+        };
+        SmtpEmailClient::new(&settings, email(), Duration::from_secs(5)).unwrap()
+    }
+
+    /// Accepts a single SMTP session and returns every line the client sent.
+    async fn smtp_sink() -> (SocketAddr, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let sink = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            let mut transcript = Vec::new();
+            let mut in_data = false;
+            let mut login_step = 0;
+
+            writer.write_all(b"220 localhost\r\n").await.unwrap();
+            while let Some(line) = lines.next_line().await.unwrap() {
+                transcript.push(line.clone());
+                let reply: &[u8] = if in_data {
+                    if line != "." {
+                        continue;
+                    }
+                    in_data = false;
+                    b"250 Queued\r\n"
+                } else if login_step == 1 {
+                    login_step = 2;
+                    b"334 UGFzc3dvcmQ6\r\n"
+                } else if login_step == 2 {
+                    login_step = 0;
+                    b"235 Authenticated\r\n"
+                } else if line.starts_with("EHLO") {
+                    b"250-localhost\r\n250 AUTH PLAIN LOGIN\r\n"
+                } else if line == "AUTH LOGIN" {
+                    login_step = 1;
+                    b"334 VXNlcm5hbWU6\r\n"
+                } else if line.starts_with("AUTH PLAIN") {
+                    b"235 Authenticated\r\n"
+                } else if line == "DATA" {
+                    in_data = true;
+                    b"354 Start mail input\r\n"
+                } else if line == "QUIT" {
+                    writer.write_all(b"221 Bye\r\n").await.unwrap();
+                    break;
+                } else {
+                    b"250 OK\r\n"
+                };
+                writer.write_all(reply).await.unwrap();
+            }
+            transcript
+        });
+
+        (address, sink)
+    }
+}
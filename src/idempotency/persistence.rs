@@ -10,6 +10,7 @@ use uuid::Uuid;
 
 use super::IdempotencyKey;
 
+#[allow(clippy::large_enum_variant)]
 pub enum NextAction {
     StartProcessing(Transaction<'static, Postgres>),
     ReturnSavedResponse(Response),
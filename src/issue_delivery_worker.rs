@@ -1,11 +1,24 @@
-use std::time::Duration;
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
-use sqlx::{query, query_as, PgPool, Postgres, Transaction};
-use tracing::{error, field::display, Span};
+use chrono::Utc;
+use rand::{thread_rng, Rng};
+use secrecy::Secret;
+use sqlx::{postgres::PgListener, query, query_as, PgExecutor, PgPool, Postgres, Transaction};
+use tokio::{sync::Notify, task::JoinSet};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn, Instrument, Span};
+use url::Url;
 use uuid::Uuid;
 
 use crate::{
-    configuration::Settings, domain::SubscriberEmail, email_client::EmailClient,
+    configuration::{ApplicationSettings, RetrySettings, Settings, WorkerSettings},
+    domain::{SubscriberEmail, UnsubscribeToken},
+    email_client::{EmailClient, EmailError, EmailMessage},
+    session_store::prune_expired_sessions,
     startup::get_connection_pool,
 };
 
@@ -17,35 +30,179 @@ struct NewsletterIssue {
     html_content: String,
 }
 
+struct Task {
+    issue_id: Uuid,
+    email: String,
+    n_retries: i16,
+}
+
+enum PreparedTask {
+    Ready(EmailMessage),
+    Skipped,
+    Invalid(String),
+}
+
 pub enum ExecutionOutcome {
     TaskCompleted,
     EmptyQueue,
+    RateLimited,
+}
+
+/// Records when a delivery worker last went round its loop, for the health
+/// check of processes that only run workers.
+#[derive(Clone)]
+pub struct Heartbeat {
+    last_beat: Arc<Mutex<Instant>>,
+    max_silence: Duration,
 }
 
-const MAX_RETRIES: i16 = 3;
+impl Heartbeat {
+    /// Workers may stay silent while they sleep, or for as long as they hold
+    /// the claim on the batch they are sending.
+    pub fn new(settings: &WorkerSettings) -> Self {
+        Self {
+            last_beat: Arc::new(Mutex::new(Instant::now())),
+            max_silence: settings.claim_timeout
+                + settings.empty_queue_sleep.max(settings.error_sleep),
+        }
+    }
 
-pub async fn run_worker_until_stopped(config: Settings) -> ! {
+    fn beat(&self) {
+        *self.last_beat.lock().unwrap() = Instant::now();
+    }
+
+    pub fn is_stale(&self) -> bool {
+        self.last_beat.lock().unwrap().elapsed() > self.max_silence
+    }
+}
+
+const NEW_TASKS_CHANNEL: &str = "issue_delivery_queue";
+/// Keeps a tiny `claim_timeout` from flooding the database with renewals.
+const MIN_CLAIM_RENEWAL_PERIOD: Duration = Duration::from_millis(100);
+/// Keeps idle workers from spinning when the next task is due any moment.
+const MIN_WAIT_FOR_NEXT_TASK: Duration = Duration::from_millis(50);
+
+/// Delivers queued emails until `shutdown` is cancelled. Each worker finishes the
+/// batch it is sending before it stops, so no claimed batch is abandoned mid-send.
+pub async fn run_worker_until_stopped(
+    config: Settings,
+    heartbeat: Heartbeat,
+    shutdown: CancellationToken,
+) {
     let db_pool = get_connection_pool(&config.database);
     let email_client = config.email_client.client();
+    let new_tasks = Arc::new(Notify::new());
+
+    let mut workers = JoinSet::new();
+    for _ in 0..config.worker.concurrency.max(1) {
+        workers.spawn(
+            execute_task_loop(
+                db_pool.clone(),
+                email_client.clone(),
+                config.application.clone(),
+                config.worker.clone(),
+                new_tasks.clone(),
+                heartbeat.clone(),
+                shutdown.clone(),
+            )
+            .in_current_span(),
+        );
+    }
+    let workers_stopped = async {
+        while let Some(result) = workers.join_next().await {
+            if let Err(e) = result {
+                error!(error = %e, "Delivery worker crashed.");
+                shutdown.cancel();
+            }
+        }
+    };
 
     tokio::select! {
-        _ = execute_task_loop(&db_pool, &email_client) => {},
+        _ = workers_stopped => {},
+        _ = listen_for_new_tasks_loop(&db_pool, &new_tasks, config.worker.error_sleep) => {},
         _ = prune_idempotency_table_loop(&db_pool) => {},
+        _ = prune_expired_sessions_loop(&db_pool) => {},
     };
+    info!("Delivery workers stopped.");
+}
 
-    unreachable!()
+async fn execute_task_loop(
+    db_pool: PgPool,
+    email_client: EmailClient,
+    application: ApplicationSettings,
+    settings: WorkerSettings,
+    new_tasks: Arc<Notify>,
+    heartbeat: Heartbeat,
+    shutdown: CancellationToken,
+) {
+    while !shutdown.is_cancelled() {
+        heartbeat.beat();
+        match try_execute_task(
+            &db_pool,
+            &email_client,
+            &application.base_url,
+            &application.hmac_secret,
+            &settings,
+        )
+        .await
+        {
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                // Polling remains as a fallback in case a notification is missed
+                let sleep = match time_until_next_task(&db_pool).await {
+                    Ok(Some(wait)) => wait
+                        .max(MIN_WAIT_FOR_NEXT_TASK)
+                        .min(settings.empty_queue_sleep),
+                    Ok(None) => settings.empty_queue_sleep,
+                    Err(_) => settings.error_sleep,
+                };
+                tokio::select! {
+                    _ = tokio::time::sleep(sleep) => {},
+                    _ = new_tasks.notified() => {},
+                    _ = shutdown.cancelled() => {},
+                }
+            }
+            Ok(ExecutionOutcome::RateLimited) => {
+                sleep_unless_stopped(until_next_second(), &shutdown).await
+            }
+            Err(_) => sleep_unless_stopped(settings.error_sleep, &shutdown).await,
+        }
+    }
+}
+
+async fn sleep_unless_stopped(duration: Duration, shutdown: &CancellationToken) {
+    tokio::select! {
+        _ = tokio::time::sleep(duration) => {},
+        _ = shutdown.cancelled() => {},
+    }
 }
 
-async fn execute_task_loop(db_pool: &PgPool, email_client: &EmailClient) -> ! {
+async fn listen_for_new_tasks_loop(
+    db_pool: &PgPool,
+    new_tasks: &Notify,
+    error_sleep: Duration,
+) -> ! {
     loop {
-        match try_execute_task(db_pool, email_client).await {
-            Ok(ExecutionOutcome::TaskCompleted) => {}
-            Ok(ExecutionOutcome::EmptyQueue) => tokio::time::sleep(Duration::from_secs(10)).await,
-            Err(_) => tokio::time::sleep(Duration::from_secs(1)).await,
+        if let Err(e) = listen_for_new_tasks(db_pool, new_tasks).await {
+            error!(error = %e, "Failed to listen for new delivery tasks.");
         }
+        tokio::time::sleep(error_sleep).await;
+    }
+}
+
+async fn listen_for_new_tasks(db_pool: &PgPool, new_tasks: &Notify) -> Result<(), sqlx::Error> {
+    let mut listener = PgListener::connect_with(db_pool).await?;
+    listener.listen(NEW_TASKS_CHANNEL).await?;
+    loop {
+        listener.recv().await?;
+        new_tasks.notify_waiters();
     }
 }
 
+fn until_next_second() -> Duration {
+    Duration::from_millis(1000 - u64::from(Utc::now().timestamp_subsec_millis().min(999)))
+}
+
 async fn prune_idempotency_table_loop(db_pool: &PgPool) -> ! {
     loop {
         match prune_idempotency_table(db_pool).await {
@@ -56,101 +213,341 @@ async fn prune_idempotency_table_loop(db_pool: &PgPool) -> ! {
     }
 }
 
-#[tracing::instrument(skip_all, fields(newsletter_issue_id, subscriber_email))]
+/// Only the Postgres session store needs this, the others expire sessions
+/// themselves.
+async fn prune_expired_sessions_loop(db_pool: &PgPool) -> ! {
+    loop {
+        match prune_expired_sessions(db_pool).await {
+            Ok(_) => {}
+            Err(e) => error!(error = %e, "Failed to prune expired sessions."),
+        }
+        tokio::time::sleep(Duration::from_secs(1000)).await;
+    }
+}
+
+#[tracing::instrument(skip_all, fields(n_tasks))]
 pub async fn try_execute_task(
     db_pool: &PgPool,
     email_client: &EmailClient,
+    base_url: &Url,
+    hmac_secret: &Secret<String>,
+    settings: &WorkerSettings,
 ) -> Result<ExecutionOutcome, sqlx::Error> {
-    if let Some((transaction, issue_id, email, retries)) = dequeue_task(db_pool).await? {
-        Span::current()
-            .record("newsletter_issue_id", display(issue_id))
-            .record("subscriber_email", display(&email));
+    let (mut transaction, mut tasks) = dequeue_tasks(db_pool, settings.batch_size).await?;
+    if tasks.is_empty() {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    }
+    if let Some(max_messages_per_second) = settings.max_messages_per_second {
+        let quota = reserve_send_quota(db_pool, tasks.len(), max_messages_per_second).await?;
+        if quota == 0 {
+            return Ok(ExecutionOutcome::RateLimited);
+        }
+        // Tasks over the quota stay in the queue once the transaction ends
+        tasks.truncate(quota);
+    }
+    Span::current().record("n_tasks", tasks.len());
+    // The claim is committed before sending, so that no rollback can put
+    // messages that were already sent back in the queue. Each result is then
+    // recorded on its own.
+    claim_tasks(&mut transaction, &tasks, settings.claim_timeout).await?;
+    transaction.commit().await?;
 
-        let email = match SubscriberEmail::parse(email.clone()) {
-            Ok(email) => email,
-            Err(e) => {
-                error!(
-                    error = %e,
-                    "Subscriber contact details are invalid. Skipping."
-                );
-                delete_task(transaction, issue_id, &email).await?;
-                return Ok(ExecutionOutcome::TaskCompleted);
+    let subscriber_ids = get_confirmed_subscriber_ids(db_pool, &tasks).await?;
+    let mut issues = HashMap::new();
+    let mut deliveries = Vec::with_capacity(tasks.len());
+    let mut messages = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        let prepared = prepare_message(
+            db_pool,
+            &mut issues,
+            &subscriber_ids,
+            &task,
+            base_url,
+            hmac_secret,
+        )
+        .await?;
+        match prepared {
+            PreparedTask::Ready(message) => {
+                deliveries.push(task);
+                messages.push(message);
             }
-        };
+            PreparedTask::Skipped => delete_task(db_pool, &task).await?,
+            PreparedTask::Invalid(e) => {
+                dead_letter_task(db_pool, &task, task.n_retries, &e).await?
+            }
+        }
+    }
 
-        let issue = get_issue(db_pool, issue_id).await?;
-        match email_client
-            .send(
-                &email,
-                &issue.title,
-                &issue.html_content,
-                &issue.text_content,
-            )
-            .await
-        {
+    let results =
+        send_batch_while_claimed(db_pool, email_client, &deliveries, &messages, settings).await;
+    for (task, result) in deliveries.iter().zip(results) {
+        match result {
             Err(e) => {
+                let n_attempts = task.n_retries + 1;
                 error!(
                     error = %e,
-                    "Failed to deliver issue to confirmed subscriber. Retries {retries}/{MAX_RETRIES}."
+                    newsletter_issue_id = %task.issue_id,
+                    subscriber_email = %task.email,
+                    "Failed to deliver issue to confirmed subscriber. Attempt {n_attempts}/{}.",
+                    settings.retry.max_attempts
                 );
-                retry_or_delete_task(transaction, issue_id, email.as_ref(), retries).await?;
+                match retry_delay(&settings.retry, n_attempts, &e) {
+                    Some(delay) => update_task_retries(db_pool, task, delay).await?,
+                    None => dead_letter_task(db_pool, task, n_attempts, &e.to_string()).await?,
+                }
             }
-            Ok(()) => {
-                delete_task(transaction, issue_id, email.as_ref()).await?;
+            Ok(()) => delete_task(db_pool, task).await?,
+        }
+    }
+
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+/// Sends the batch, renewing the claim on its tasks halfway through each
+/// `claim_timeout`. Transports that send one message at a time could
+/// otherwise outlast the claim and have another worker send the batch again.
+async fn send_batch_while_claimed(
+    db_pool: &PgPool,
+    email_client: &EmailClient,
+    tasks: &[Task],
+    messages: &[EmailMessage],
+    settings: &WorkerSettings,
+) -> Vec<Result<(), EmailError>> {
+    let send = email_client.send_batch(messages);
+    tokio::pin!(send);
+    let renewal_period = (settings.claim_timeout / 2).max(MIN_CLAIM_RENEWAL_PERIOD);
+    let mut renewal =
+        tokio::time::interval_at(tokio::time::Instant::now() + renewal_period, renewal_period);
+    loop {
+        tokio::select! {
+            results = &mut send => return results,
+            _ = renewal.tick() => {
+                if let Err(e) = claim_tasks(db_pool, tasks, settings.claim_timeout).await {
+                    error!(error = %e, "Failed to renew the claim on a batch being sent.");
+                }
             }
         }
-        Ok(ExecutionOutcome::TaskCompleted)
-    } else {
-        Ok(ExecutionOutcome::EmptyQueue)
     }
 }
 
-async fn retry_or_delete_task(
-    transaction: PgTransaction,
-    issue_id: Uuid,
-    email: &str,
-    retries: i16,
-) -> Result<(), sqlx::Error> {
-    if retries <= MAX_RETRIES {
-        update_task_retries(transaction, issue_id, email).await
-    } else {
-        delete_task(transaction, issue_id, email).await
+/// Renders the issue for the task's subscriber, unless it should not be delivered.
+#[tracing::instrument(
+    skip_all,
+    fields(newsletter_issue_id=%task.issue_id, subscriber_email=%task.email)
+)]
+async fn prepare_message(
+    db_pool: &PgPool,
+    issues: &mut HashMap<Uuid, NewsletterIssue>,
+    subscriber_ids: &HashMap<String, Uuid>,
+    task: &Task,
+    base_url: &Url,
+    hmac_secret: &Secret<String>,
+) -> Result<PreparedTask, sqlx::Error> {
+    let email = match SubscriberEmail::parse(task.email.clone()) {
+        Ok(email) => email,
+        Err(e) => {
+            error!(
+                error = %e,
+                "Subscriber contact details are invalid. Recording the failure."
+            );
+            return Ok(PreparedTask::Invalid(e));
+        }
+    };
+
+    let subscriber_id = match subscriber_ids.get(email.as_ref()) {
+        Some(subscriber_id) => *subscriber_id,
+        None => {
+            warn!("Subscriber is no longer confirmed. Skipping.");
+            return Ok(PreparedTask::Skipped);
+        }
+    };
+    let unsubscribe_link = unsubscribe_link(base_url, subscriber_id, hmac_secret);
+
+    let issue = match issues.entry(task.issue_id) {
+        Entry::Occupied(entry) => entry.into_mut(),
+        Entry::Vacant(entry) => entry.insert(get_issue(db_pool, task.issue_id).await?),
+    };
+    let html_body = format!(
+        "{}<p><a href=\"{unsubscribe_link}\">Unsubscribe</a> from this newsletter.</p>",
+        issue.html_content
+    );
+    let text_body = format!(
+        "{}\n\nTo unsubscribe from this newsletter visit {unsubscribe_link}",
+        issue.text_content
+    );
+
+    Ok(PreparedTask::Ready(EmailMessage {
+        recipient: email,
+        subject: issue.title.clone(),
+        html_body,
+        text_body,
+        headers: vec![
+            ("List-Unsubscribe".into(), format!("<{unsubscribe_link}>")),
+            (
+                "List-Unsubscribe-Post".into(),
+                "List-Unsubscribe=One-Click".into(),
+            ),
+        ],
+    }))
+}
+
+fn unsubscribe_link(base_url: &Url, subscriber_id: Uuid, hmac_secret: &Secret<String>) -> Url {
+    let token = UnsubscribeToken::generate(subscriber_id, hmac_secret);
+    base_url
+        .join(&format!("subscriptions/unsubscribe?token={token}"))
+        .unwrap()
+}
+
+/// How long to wait before retrying a failed delivery, or `None` if it should
+/// not be retried at all.
+fn retry_delay(policy: &RetrySettings, n_attempts: i16, error: &EmailError) -> Option<Duration> {
+    if error.is_permanent() || n_attempts >= policy.max_attempts {
+        return None;
+    }
+    // Network errors and timeouts have no status and are always worth retrying
+    if let Some(status) = error.status() {
+        if !policy.retryable_status_codes.contains(&status) {
+            return None;
+        }
     }
+
+    let exponent = u32::try_from(n_attempts - 1).unwrap_or(0).min(31);
+    let delay = policy.base_delay.saturating_mul(1 << exponent);
+    let jitter = policy.jitter.clamp(0.0, 1.0);
+    let factor = 1.0 + thread_rng().gen_range(-jitter..=jitter);
+    // Capped after the jitter, which could push it past `max_delay` otherwise
+    let delay = Duration::try_from_secs_f64(delay.as_secs_f64() * factor)
+        .unwrap_or(policy.max_delay)
+        .min(policy.max_delay);
+
+    // The provider may ask for any wait, which is not worth honouring past `max_delay`
+    Some(match error.retry_after() {
+        Some(retry_after) => delay.max(retry_after.min(policy.max_delay)),
+        None => delay,
+    })
 }
 
+/// Wakes up idle workers once the transaction commits.
 #[tracing::instrument(skip_all)]
-async fn dequeue_task(
+pub async fn notify_new_tasks(transaction: &mut PgTransaction) -> Result<(), sqlx::Error> {
+    query!(r#"SELECT pg_notify($1, '')"#, NEW_TASKS_CHANNEL)
+        .execute(transaction)
+        .await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn dequeue_tasks(
     db_pool: &PgPool,
-) -> Result<Option<(PgTransaction, Uuid, String, i16)>, sqlx::Error> {
+    batch_size: i64,
+) -> Result<(PgTransaction, Vec<Task>), sqlx::Error> {
     let mut transaction = db_pool.begin().await?;
-    Ok(query!(
+    let tasks = query_as!(
+        Task,
         r#"
-    SELECT newsletter_issue_id, subscriber_email, n_retries
+    SELECT
+        newsletter_issue_id AS issue_id,
+        subscriber_email AS email,
+        n_retries
     FROM issue_delivery_queue
     WHERE execute_after <= now()
     FOR UPDATE
     SKIP LOCKED
-    LIMIT 1
-    "#
+    LIMIT $1
+    "#,
+        batch_size
+    )
+    .fetch_all(&mut transaction)
+    .await?;
+    Ok((transaction, tasks))
+}
+
+/// Pushes the tasks back by `claim_timeout`, so that other workers leave them
+/// alone while they are sent but pick them up if this one never reports back.
+#[tracing::instrument(skip_all)]
+async fn claim_tasks(
+    executor: impl PgExecutor<'_>,
+    tasks: &[Task],
+    claim_timeout: Duration,
+) -> Result<(), sqlx::Error> {
+    let issue_ids: Vec<_> = tasks.iter().map(|task| task.issue_id).collect();
+    let emails: Vec<_> = tasks.iter().map(|task| task.email.clone()).collect();
+    query!(
+        r#"
+    UPDATE issue_delivery_queue
+    SET execute_after = now() + make_interval(secs => $3)
+    WHERE (newsletter_issue_id, subscriber_email) IN (
+        SELECT * FROM UNNEST($1::uuid[], $2::text[])
+    )
+        "#,
+        &issue_ids,
+        &emails,
+        claim_timeout.as_secs_f64()
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Reserves up to `requested` sends in the current one-second window, shared by every
+/// worker connected to the database, and returns how many were granted.
+#[tracing::instrument(skip(db_pool))]
+async fn reserve_send_quota(
+    db_pool: &PgPool,
+    requested: usize,
+    max_messages_per_second: i32,
+) -> Result<usize, sqlx::Error> {
+    let mut transaction = db_pool.begin().await?;
+    query!(
+        r#"
+    INSERT INTO email_send_rate (window_start, n_sent)
+    VALUES (date_trunc('second', now()), 0)
+    ON CONFLICT DO NOTHING
+        "#
     )
-    .fetch_optional(&mut transaction)
+    .execute(&mut transaction)
+    .await?;
+    let n_sent = query!(
+        r#"
+    SELECT n_sent
+    FROM email_send_rate
+    WHERE window_start = date_trunc('second', now())
+    FOR UPDATE
+        "#
+    )
+    .fetch_one(&mut transaction)
     .await?
-    .map(|r| {
-        (
-            transaction,
-            r.newsletter_issue_id,
-            r.subscriber_email,
-            r.n_retries,
+    .n_sent;
+
+    let available = usize::try_from(max_messages_per_second - n_sent).unwrap_or(0);
+    let granted = requested.min(available);
+    if granted > 0 {
+        query!(
+            r#"
+    UPDATE email_send_rate
+    SET n_sent = n_sent + $1
+    WHERE window_start = date_trunc('second', now())
+        "#,
+            granted as i32
         )
-    }))
+        .execute(&mut transaction)
+        .await?;
+    }
+    query!(
+        r#"
+    DELETE FROM email_send_rate
+    WHERE window_start < now() - interval '1 minute'
+        "#
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+
+    Ok(granted)
 }
 
 #[tracing::instrument(skip_all)]
-async fn delete_task(
-    mut transaction: PgTransaction,
-    issue_id: Uuid,
-    email: &str,
-) -> Result<(), sqlx::Error> {
+async fn delete_task(db_pool: &PgPool, task: &Task) -> Result<(), sqlx::Error> {
     query!(
         r#"
     DELETE FROM issue_delivery_queue
@@ -158,39 +555,125 @@ async fn delete_task(
         newsletter_issue_id = $1 AND
         subscriber_email = $2
         "#,
-        issue_id,
-        email
+        task.issue_id,
+        task.email
     )
-    .execute(&mut transaction)
+    .execute(db_pool)
+    .await?;
+    Ok(())
+}
+
+/// Moves the task into `issue_delivery_failures`, where it waits to be re-enqueued by an admin.
+#[tracing::instrument(skip_all)]
+async fn dead_letter_task(
+    db_pool: &PgPool,
+    task: &Task,
+    n_attempts: i16,
+    error: &str,
+) -> Result<(), sqlx::Error> {
+    query!(
+        r#"
+    WITH failed AS (
+        DELETE FROM issue_delivery_queue
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = $2
+        RETURNING newsletter_issue_id, subscriber_email, created_at
+    )
+    INSERT INTO issue_delivery_failures (
+        failure_id,
+        newsletter_issue_id,
+        subscriber_email,
+        n_attempts,
+        last_error,
+        enqueued_at,
+        failed_at
+    )
+    SELECT $3, newsletter_issue_id, subscriber_email, $4, $5, created_at, now()
+    FROM failed
+    ON CONFLICT (newsletter_issue_id, subscriber_email) DO UPDATE
+    SET n_attempts = EXCLUDED.n_attempts,
+        last_error = EXCLUDED.last_error,
+        enqueued_at = EXCLUDED.enqueued_at,
+        failed_at = EXCLUDED.failed_at
+        "#,
+        task.issue_id,
+        task.email,
+        Uuid::new_v4(),
+        n_attempts,
+        error
+    )
+    .execute(db_pool)
     .await?;
-    transaction.commit().await?;
     Ok(())
 }
 
 #[tracing::instrument(skip_all)]
 async fn update_task_retries(
-    mut transaction: PgTransaction,
-    issue_id: Uuid,
-    email: &str,
+    db_pool: &PgPool,
+    task: &Task,
+    delay: Duration,
 ) -> Result<(), sqlx::Error> {
     query!(
         r#"
     UPDATE issue_delivery_queue
     SET n_retries = n_retries + 1,
-        execute_after = now() + interval '1 second' * n_retries ^ 2
+        execute_after = now() + make_interval(secs => $3)
     WHERE
         newsletter_issue_id = $1 AND
         subscriber_email = $2
         "#,
-        issue_id,
-        email
+        task.issue_id,
+        task.email,
+        delay.as_secs_f64()
     )
-    .execute(&mut transaction)
+    .execute(db_pool)
     .await?;
-    transaction.commit().await?;
     Ok(())
 }
 
+/// How long until the earliest queued task, such as a retry, becomes due.
+/// Tasks that are already due are being sent by another worker, as there
+/// were none left to dequeue.
+#[tracing::instrument(skip_all)]
+async fn time_until_next_task(db_pool: &PgPool) -> Result<Option<Duration>, sqlx::Error> {
+    let next_execute_after = query!(
+        r#"
+    SELECT min(execute_after) AS next_execute_after
+    FROM issue_delivery_queue
+    WHERE execute_after > now()
+        "#
+    )
+    .fetch_one(db_pool)
+    .await?
+    .next_execute_after;
+
+    Ok(next_execute_after.map(|next| (next - Utc::now()).to_std().unwrap_or_default()))
+}
+
+/// The ids of the confirmed subscribers among the recipients, by email.
+#[tracing::instrument(skip_all)]
+async fn get_confirmed_subscriber_ids(
+    db_pool: &PgPool,
+    tasks: &[Task],
+) -> Result<HashMap<String, Uuid>, sqlx::Error> {
+    let emails: Vec<_> = tasks.iter().map(|task| task.email.clone()).collect();
+    let subscribers = query!(
+        r#"
+    SELECT id, email
+    FROM subscriptions
+    WHERE
+        email = ANY($1) AND
+        status = 'confirmed'
+        "#,
+        &emails
+    )
+    .fetch_all(db_pool)
+    .await?;
+
+    Ok(subscribers.into_iter().map(|r| (r.email, r.id)).collect())
+}
+
 #[tracing::instrument(skip_all)]
 async fn get_issue(db_pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, sqlx::Error> {
     query_as!(
@@ -218,3 +701,121 @@ pub async fn prune_idempotency_table(db_pool: &PgPool) -> Result<u64, sqlx::Erro
     .await?
     .rows_affected())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{configuration::RetrySettings, email_client::EmailError};
+
+    use super::retry_delay;
+
+    fn policy() -> RetrySettings {
+        RetrySettings {
+            max_attempts: 5,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            jitter: 0.2,
+            retryable_status_codes: vec![429, 500, 503],
+        }
+    }
+
+    fn error_with_status(status: u16, retry_after: Option<Duration>) -> EmailError {
+        EmailError::from(anyhow::anyhow!("Provider error")).with_status(status, retry_after)
+    }
+
+    #[test]
+    fn client_errors_are_not_retried() {
+        assert_eq!(
+            retry_delay(&policy(), 1, &error_with_status(400, None)),
+            None
+        );
+    }
+
+    #[test]
+    fn permanent_errors_are_not_retried() {
+        let error = error_with_status(500, None).permanent();
+        assert_eq!(retry_delay(&policy(), 1, &error), None);
+    }
+
+    #[test]
+    fn network_errors_are_retried() {
+        let error = EmailError::from(anyhow::anyhow!("Connection reset"));
+        assert!(retry_delay(&policy(), 1, &error).is_some());
+    }
+
+    #[test]
+    fn delay_grows_exponentially_within_the_jitter_bounds() {
+        let error = error_with_status(503, None);
+        for (n_attempts, expected) in [(1, 1.0), (2, 2.0), (3, 4.0), (4, 8.0)] {
+            let delay = retry_delay(&policy(), n_attempts, &error).unwrap();
+            let delay = delay.as_secs_f64();
+            assert!(
+                (expected * 0.8..=expected * 1.2).contains(&delay),
+                "attempt {n_attempts} waited {delay}s"
+            );
+        }
+    }
+
+    #[test]
+    fn delay_is_capped() {
+        let policy = RetrySettings {
+            max_attempts: 100,
+            jitter: 0.0,
+            ..policy()
+        };
+        let delay = retry_delay(&policy, 50, &error_with_status(500, None));
+        assert_eq!(delay, Some(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn jitter_never_pushes_the_delay_past_the_cap() {
+        let policy = RetrySettings {
+            max_attempts: 100,
+            jitter: 1.0,
+            ..policy()
+        };
+        let error = error_with_status(500, None);
+        for n_attempts in 1..100 {
+            let delay = retry_delay(&policy, n_attempts, &error).unwrap();
+            assert!(
+                delay <= policy.max_delay,
+                "attempt {n_attempts} waited {delay:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn huge_delays_do_not_overflow() {
+        let policy = RetrySettings {
+            max_attempts: 100,
+            base_delay: Duration::MAX,
+            max_delay: Duration::MAX,
+            jitter: 1.0,
+            ..policy()
+        };
+        let delay = retry_delay(&policy, 50, &error_with_status(500, None));
+        assert!(delay.is_some());
+    }
+
+    #[test]
+    fn retry_after_is_honoured() {
+        let error = error_with_status(429, Some(Duration::from_secs(30)));
+        let delay = retry_delay(&policy(), 1, &error).unwrap();
+        assert_eq!(delay, Duration::from_secs(30));
+    }
+
+    #[test]
+    fn retry_after_is_capped() {
+        let error = error_with_status(429, Some(Duration::from_secs(u64::MAX)));
+        let delay = retry_delay(&policy(), 1, &error).unwrap();
+        assert_eq!(delay, Duration::from_secs(60));
+    }
+
+    #[test]
+    fn deliveries_are_not_retried_after_max_attempts() {
+        let error = error_with_status(500, None);
+        assert!(retry_delay(&policy(), 4, &error).is_some());
+        assert_eq!(retry_delay(&policy(), 5, &error), None);
+    }
+}
@@ -1,11 +1,18 @@
 pub mod authentication;
+pub mod client_ip;
 pub mod configuration;
+pub mod csrf;
 pub mod domain;
 pub mod email_client;
 pub mod idempotency;
 pub mod issue_delivery_worker;
 pub mod log;
+pub mod login_throttle;
 pub mod routes;
+pub mod session_store;
+pub mod shutdown;
 pub mod startup;
 pub mod telemetry;
+pub mod two_factor;
 pub mod user_session;
+pub mod users;
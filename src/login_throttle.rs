@@ -0,0 +1,396 @@
+use std::{net::IpAddr, time::Duration};
+
+use anyhow::Context;
+use chrono::{DateTime, TimeZone, Utc};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+
+use crate::configuration::{LoginThrottleSettings, ThrottleStoreKind};
+
+/// Counts failed logins per username and per client IP. Past
+/// `free_failures`, every failure delays the next attempt for the username a
+/// little longer, until `max_failures` locks it out. An IP is only locked
+/// out, at `max_failures_per_ip`, as many people may share it.
+#[derive(Clone)]
+pub struct LoginThrottle {
+    store: Store,
+    settings: LoginThrottleSettings,
+}
+
+#[derive(Clone)]
+enum Store {
+    Redis(redis::Client),
+    Postgres(PgPool),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ThrottleError {
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+    #[error("Too many failed login attempts - try again in {}", describe_wait(*.0))]
+    Throttled(Duration),
+}
+
+struct Failures {
+    count: i32,
+    last_failure: DateTime<Utc>,
+}
+
+impl LoginThrottle {
+    pub fn new(
+        settings: LoginThrottleSettings,
+        redis_uri: &Secret<String>,
+        db_pool: PgPool,
+    ) -> Result<Self, anyhow::Error> {
+        let store = match settings.store {
+            ThrottleStoreKind::Redis => Store::Redis(
+                redis::Client::open(redis_uri.expose_secret().as_str())
+                    .context("Invalid Redis URI")?,
+            ),
+            ThrottleStoreKind::Postgres => Store::Postgres(db_pool),
+        };
+
+        Ok(Self { store, settings })
+    }
+
+    /// Counts an attempt by `username` from `ip` as failed before the
+    /// password is even checked, so that parallel attempts cannot all get in
+    /// ahead of the first failure. Fails with the time left to wait if they
+    /// may not try to log in yet, in which case the attempt is not counted.
+    #[tracing::instrument(skip(self))]
+    pub async fn reserve(&self, username: &str, ip: IpAddr) -> Result<(), ThrottleError> {
+        let username_key = self.username_key(username);
+        let ip_key = self.ip_key(ip);
+        let mut wait = Duration::ZERO;
+        if let Some(failures) = self.increment(&username_key).await? {
+            wait = wait.max(failures.remaining(username_delay(&self.settings, failures.count)));
+        }
+        if let Some(failures) = self.increment(&ip_key).await? {
+            if failures.count >= self.settings.max_failures_per_ip as i32 {
+                wait = wait.max(failures.remaining(self.settings.lockout));
+            }
+        }
+
+        if wait.is_zero() {
+            return Ok(());
+        }
+        self.decrement(&username_key).await?;
+        self.decrement(&ip_key).await?;
+        Err(ThrottleError::Throttled(wait))
+    }
+
+    /// Restarts the delays from the failure of an attempt reserved by
+    /// [`Self::reserve`], rather than from when it was made.
+    #[tracing::instrument(skip(self))]
+    pub async fn record_failure(&self, username: &str, ip: IpAddr) -> Result<(), anyhow::Error> {
+        self.touch(&self.username_key(username)).await?;
+        self.touch(&self.ip_key(ip)).await
+    }
+
+    /// Takes back an attempt reserved by [`Self::reserve`] that did not fail,
+    /// for instance a right password still waiting for its second factor.
+    #[tracing::instrument(skip(self))]
+    pub async fn release(&self, username: &str, ip: IpAddr) -> Result<(), anyhow::Error> {
+        self.decrement(&self.username_key(username)).await?;
+        self.decrement(&self.ip_key(ip)).await
+    }
+
+    /// Forgets the failures of `username`. Those of the IP are kept, or an
+    /// attacker with an account of their own could reset them, but the
+    /// attempt reserved for this login is taken back.
+    #[tracing::instrument(skip(self))]
+    pub async fn record_success(&self, username: &str, ip: IpAddr) -> Result<(), anyhow::Error> {
+        let key = self.username_key(username);
+        match &self.store {
+            Store::Redis(client) => {
+                let mut connection = client.get_async_connection().await?;
+                redis::cmd("DEL")
+                    .arg(&key)
+                    .query_async::<_, ()>(&mut connection)
+                    .await
+                    .context("Failed to delete login failures from Redis")?;
+            }
+            Store::Postgres(db_pool) => {
+                sqlx::query!(r#"DELETE FROM login_failures WHERE throttle_key = $1"#, key)
+                    .execute(db_pool)
+                    .await
+                    .context("Failed to delete login failures")?;
+            }
+        }
+        self.decrement(&self.ip_key(ip)).await
+    }
+
+    fn username_key(&self, username: &str) -> String {
+        format!("{}:username:{username}", self.settings.key_prefix)
+    }
+
+    fn ip_key(&self, ip: IpAddr) -> String {
+        format!("{}:ip:{ip}", self.settings.key_prefix)
+    }
+
+    /// Failures are forgotten once `lockout` has passed since the last one.
+    /// Returns those counted before this one.
+    async fn increment(&self, key: &str) -> Result<Option<Failures>, anyhow::Error> {
+        let previous = match &self.store {
+            Store::Redis(client) => {
+                let mut connection = client.get_async_connection().await?;
+                let ((count, last_failure_millis),): ((Option<i32>, Option<i64>),) = redis::pipe()
+                    .atomic()
+                    .cmd("HMGET")
+                    .arg(key)
+                    .arg("failures")
+                    .arg("last_failure_millis")
+                    .cmd("HINCRBY")
+                    .arg(key)
+                    .arg("failures")
+                    .arg(1)
+                    .ignore()
+                    .cmd("HSET")
+                    .arg(key)
+                    .arg("last_failure_millis")
+                    .arg(Utc::now().timestamp_millis())
+                    .ignore()
+                    .cmd("EXPIRE")
+                    .arg(key)
+                    .arg(self.settings.lockout.as_secs().max(1))
+                    .ignore()
+                    .query_async(&mut connection)
+                    .await
+                    .context("Failed to record login failure in Redis")?;
+
+                count.zip(last_failure_millis).and_then(|(count, millis)| {
+                    let last_failure = Utc.timestamp_millis_opt(millis).single()?;
+                    Some(Failures {
+                        count,
+                        last_failure,
+                    })
+                })
+            }
+            Store::Postgres(db_pool) => {
+                let lockout = self.settings.lockout.as_secs_f64();
+                let mut transaction = db_pool
+                    .begin()
+                    .await
+                    .context("Failed to start a transaction")?;
+                // A row to lock, so that concurrent attempts are counted one at a time
+                sqlx::query!(
+                    r#"
+                    INSERT INTO login_failures (throttle_key, failures, last_failure_at)
+                    VALUES ($1, 0, to_timestamp(0))
+                    ON CONFLICT (throttle_key) DO NOTHING
+                    "#,
+                    key
+                )
+                .execute(&mut transaction)
+                .await
+                .context("Failed to record login failure")?;
+                let previous = sqlx::query_as!(
+                    Failures,
+                    r#"
+                    SELECT failures AS count, last_failure_at AS last_failure
+                    FROM login_failures
+                    WHERE throttle_key = $1
+                    FOR UPDATE
+                    "#,
+                    key
+                )
+                .fetch_one(&mut transaction)
+                .await
+                .context("Failed to read login failures")?;
+                sqlx::query!(
+                    r#"
+                    UPDATE login_failures
+                    SET failures = CASE
+                            WHEN last_failure_at > now() - make_interval(secs => $2)
+                            THEN failures + 1
+                            ELSE 1
+                        END,
+                        last_failure_at = now()
+                    WHERE throttle_key = $1
+                    "#,
+                    key,
+                    lockout
+                )
+                .execute(&mut transaction)
+                .await
+                .context("Failed to record login failure")?;
+                transaction
+                    .commit()
+                    .await
+                    .context("Failed to commit login failure")?;
+                // Redis expires its counters, here they are cleaned up as we go
+                sqlx::query!(
+                    r#"
+                    DELETE FROM login_failures
+                    WHERE last_failure_at < now() - make_interval(secs => $1)
+                    "#,
+                    lockout
+                )
+                .execute(db_pool)
+                .await
+                .context("Failed to delete expired login failures")?;
+                Some(previous)
+            }
+        };
+
+        Ok(previous.filter(|failures| !failures.remaining(self.settings.lockout).is_zero()))
+    }
+
+    async fn touch(&self, key: &str) -> Result<(), anyhow::Error> {
+        match &self.store {
+            Store::Redis(client) => {
+                let mut connection = client.get_async_connection().await?;
+                redis::pipe()
+                    .atomic()
+                    .cmd("HSET")
+                    .arg(key)
+                    .arg("last_failure_millis")
+                    .arg(Utc::now().timestamp_millis())
+                    .ignore()
+                    .cmd("EXPIRE")
+                    .arg(key)
+                    .arg(self.settings.lockout.as_secs().max(1))
+                    .ignore()
+                    .query_async(&mut connection)
+                    .await
+                    .context("Failed to record login failure in Redis")
+            }
+            Store::Postgres(db_pool) => {
+                sqlx::query!(
+                    r#"UPDATE login_failures SET last_failure_at = now() WHERE throttle_key = $1"#,
+                    key
+                )
+                .execute(db_pool)
+                .await
+                .context("Failed to record login failure")?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Takes back the failure counted by [`Self::increment`]. Its time is
+    /// kept, as the one before it is gone.
+    async fn decrement(&self, key: &str) -> Result<(), anyhow::Error> {
+        match &self.store {
+            Store::Redis(client) => {
+                let mut connection = client.get_async_connection().await?;
+                redis::pipe()
+                    .atomic()
+                    .cmd("HINCRBY")
+                    .arg(key)
+                    .arg("failures")
+                    .arg(-1)
+                    .ignore()
+                    .cmd("EXPIRE")
+                    .arg(key)
+                    .arg(self.settings.lockout.as_secs().max(1))
+                    .ignore()
+                    .query_async(&mut connection)
+                    .await
+                    .context("Failed to take back login failure in Redis")
+            }
+            Store::Postgres(db_pool) => {
+                sqlx::query!(
+                    r#"
+                    UPDATE login_failures
+                    SET failures = failures - 1
+                    WHERE throttle_key = $1 AND failures > 0
+                    "#,
+                    key
+                )
+                .execute(db_pool)
+                .await
+                .context("Failed to take back login failure")?;
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Failures {
+    fn remaining(&self, delay: Duration) -> Duration {
+        let elapsed = (Utc::now() - self.last_failure)
+            .to_std()
+            .unwrap_or(Duration::ZERO);
+        delay.saturating_sub(elapsed)
+    }
+}
+
+fn username_delay(settings: &LoginThrottleSettings, failures: i32) -> Duration {
+    let failures = failures.max(0) as u32;
+    if failures >= settings.max_failures {
+        return settings.lockout;
+    }
+    let Some(past_free) = failures.checked_sub(settings.free_failures) else {
+        return Duration::ZERO;
+    };
+    2u32.checked_pow(past_free)
+        .map_or(settings.lockout, |factor| {
+            settings.base_delay.saturating_mul(factor)
+        })
+        .min(settings.lockout)
+}
+
+fn describe_wait(wait: Duration) -> String {
+    let secs = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+    match secs {
+        0..=1 => "1 second".into(),
+        2..=90 => format!("{secs} seconds"),
+        _ => format!("{} minutes", secs.div_ceil(60)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{describe_wait, username_delay};
+    use crate::configuration::{LoginThrottleSettings, ThrottleStoreKind};
+
+    fn settings() -> LoginThrottleSettings {
+        LoginThrottleSettings {
+            store: ThrottleStoreKind::Postgres,
+            key_prefix: "login_failures".into(),
+            free_failures: 3,
+            base_delay: Duration::from_secs(1),
+            max_failures: 10,
+            max_failures_per_ip: 100,
+            lockout: Duration::from_secs(900),
+        }
+    }
+
+    #[test]
+    fn the_first_failures_are_free() {
+        for failures in 0..3 {
+            assert_eq!(username_delay(&settings(), failures), Duration::ZERO);
+        }
+    }
+
+    #[test]
+    fn delays_double_with_each_failure_until_the_lockout() {
+        let delays: Vec<_> = (3..=10)
+            .map(|failures| username_delay(&settings(), failures).as_secs())
+            .collect();
+
+        assert_eq!(delays, [1, 2, 4, 8, 16, 32, 64, 900]);
+    }
+
+    #[test]
+    fn delays_never_exceed_the_lockout() {
+        let settings = LoginThrottleSettings {
+            max_failures: 100,
+            ..settings()
+        };
+
+        assert_eq!(username_delay(&settings, 50), settings.lockout);
+    }
+
+    #[test]
+    fn waits_are_rounded_up() {
+        assert_eq!(describe_wait(Duration::from_millis(300)), "1 second");
+        assert_eq!(describe_wait(Duration::from_millis(1500)), "2 seconds");
+        assert_eq!(describe_wait(Duration::from_secs(90)), "90 seconds");
+        assert_eq!(describe_wait(Duration::from_secs(91)), "2 minutes");
+        assert_eq!(describe_wait(Duration::from_secs(900)), "15 minutes");
+    }
+}
@@ -1,20 +1,242 @@
-use anyhow::Result;
+use std::{
+    future::Future,
+    io::{stdin, IsTerminal},
+};
+
+use anyhow::{bail, Context, Result};
+use clap::{Parser, Subcommand};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, info_span, Instrument};
 use zero2prod::{
-    configuration::get_configuration, issue_delivery_worker::run_worker_until_stopped,
-    startup::App, telemetry::init_telemetry,
+    configuration::{get_configuration, PasswordHashingSettings},
+    domain::{Role, SubscriberEmail},
+    issue_delivery_worker::{run_worker_until_stopped, Heartbeat},
+    routes::WorkerHealth,
+    shutdown::cancel_on_signal,
+    startup::{get_connection_pool, migrate, App, HealthCheckServer},
+    telemetry::init_telemetry,
+    users::{create_user, delete_user, list_users, reset_password, reset_two_factor},
 };
 
+#[derive(Parser)]
+#[command(about = "Newsletter web application and delivery worker")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Serve the web application
+    Serve,
+    /// Deliver queued newsletter issues
+    Worker,
+    /// Apply pending database migrations and exit
+    Migrate,
+    /// Run the web application and the delivery worker in one process (default)
+    All,
+    /// Manage admin users
+    Users {
+        #[command(subcommand)]
+        command: UsersCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum UsersCommand {
+    /// Create an admin user, reading the password from stdin
+    Create {
+        username: String,
+        /// One of owner, editor or viewer
+        #[arg(long, default_value = "owner", value_parser = parse_role)]
+        role: Role,
+        /// Where to send password reset links
+        #[arg(long, value_parser = parse_email)]
+        email: Option<SubscriberEmail>,
+    },
+    /// Set a new password for an admin user, reading it from stdin
+    ResetPassword { username: String },
+    /// Turn off two-factor authentication for an admin user who lost their authenticator
+    #[command(name = "reset-2fa")]
+    ResetTwoFactor { username: String },
+    /// List every admin user
+    List,
+    /// Delete an admin user, such as the seeded `admin` account
+    Delete { username: String },
+}
+
+impl Command {
+    fn name(&self) -> &'static str {
+        match self {
+            Command::Serve => "serve",
+            Command::Worker => "worker",
+            Command::Migrate => "migrate",
+            Command::All => "all",
+            Command::Users { .. } => "users",
+        }
+    }
+
+    fn default_log_filter(&self) -> &'static str {
+        match self {
+            // Applied migrations are worth seeing in the deploy logs
+            Command::Migrate => "sqlx=info,info",
+            // Only the command's own output should reach the terminal
+            Command::Users { .. } => "warn",
+            Command::Serve | Command::Worker | Command::All => "sqlx=error,info",
+        }
+    }
+
+    fn runs_server(&self) -> bool {
+        matches!(self, Command::Serve | Command::All)
+    }
+
+    fn runs_worker(&self) -> bool {
+        matches!(self, Command::Worker | Command::All)
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
-    init_telemetry("sqlx=error,info".into());
+    let command = Cli::parse().command.unwrap_or(Command::All);
+    init_telemetry(command.default_log_filter().into());
+    info!(role = command.name(), "Starting up.");
 
     let config = get_configuration().expect("Failed to read configuration");
-    let app = tokio::spawn(App::build(&config).await?.run());
-    let worker = tokio::spawn(run_worker_until_stopped(config));
-    tokio::select! {
-        _ = app => {},
-        _ = worker => {},
-    };
+    match command {
+        Command::Migrate => return migrate(&config.database).await,
+        Command::Users { command } => {
+            let db_pool = get_connection_pool(&config.database);
+            return manage_users(&db_pool, config.password_hashing, command).await;
+        }
+        _ => {}
+    }
+
+    let shutdown = CancellationToken::new();
+    tokio::spawn(cancel_on_signal(shutdown.clone()));
+
+    // Each role logs within a span of its own, so `all` keeps them apart
+    let mut roles = JoinSet::new();
+    if command.runs_server() {
+        let app = App::build(&config).await?;
+        roles.spawn(
+            stop_all_on_exit(app.run_until_stopped(shutdown.clone()), shutdown.clone())
+                .instrument(info_span!("server")),
+        );
+    }
+    if command.runs_worker() {
+        let heartbeat = Heartbeat::new(&config.worker);
+        // The web server already answers health checks when it runs alongside the worker
+        if let (false, Some(port)) = (command.runs_server(), config.worker.health_check_port) {
+            let health = WorkerHealth {
+                db_pool: get_connection_pool(&config.database),
+                heartbeat: heartbeat.clone(),
+            };
+            let health_check = HealthCheckServer::build(&config.application.host, port, health)?;
+            roles.spawn(
+                stop_all_on_exit(
+                    health_check.run_until_stopped(shutdown.clone()),
+                    shutdown.clone(),
+                )
+                .instrument(info_span!("worker")),
+            );
+        }
+        let worker = run_worker_until_stopped(config, heartbeat, shutdown.clone());
+        roles.spawn(
+            stop_all_on_exit(
+                async {
+                    worker.await;
+                    Ok(())
+                },
+                shutdown,
+            )
+            .instrument(info_span!("worker")),
+        );
+    }
+    // Every role gets to drain before the first failure is reported
+    let mut outcome = Ok(());
+    while let Some(result) = roles.join_next().await {
+        let result = result
+            .map_err(anyhow::Error::from)
+            .and_then(|result| result);
+        if outcome.is_ok() {
+            outcome = result;
+        }
+    }
+
+    outcome
+}
+
+async fn manage_users(
+    db_pool: &PgPool,
+    hashing: PasswordHashingSettings,
+    command: UsersCommand,
+) -> Result<()> {
+    match command {
+        UsersCommand::Create {
+            username,
+            role,
+            email,
+        } => {
+            let password = read_password()?;
+            let user_id =
+                create_user(db_pool, &username, password, role, email.as_ref(), hashing).await?;
+            println!("Created {role} {username} ({user_id}).");
+        }
+        UsersCommand::ResetPassword { username } => {
+            reset_password(db_pool, &username, read_password()?, hashing).await?;
+            println!("Reset the password of {username}.");
+        }
+        UsersCommand::ResetTwoFactor { username } => {
+            reset_two_factor(db_pool, &username).await?;
+            println!("Reset two-factor authentication for {username}.");
+        }
+        UsersCommand::List => {
+            for user in list_users(db_pool).await? {
+                println!("{}\t{}\t{}", user.user_id, user.username, user.role);
+            }
+        }
+        UsersCommand::Delete { username } => {
+            delete_user(db_pool, &username).await?;
+            println!("Deleted user {username}.");
+        }
+    }
 
     Ok(())
 }
+
+fn parse_role(role: &str) -> Result<Role, String> {
+    Role::parse(role.into())
+}
+
+fn parse_email(email: &str) -> Result<SubscriberEmail, String> {
+    SubscriberEmail::parse(email.into())
+}
+
+/// Prompts for the password twice without echoing it, or reads the first line
+/// of stdin when it is piped in.
+fn read_password() -> Result<Secret<String>> {
+    if !stdin().is_terminal() {
+        let mut password = String::new();
+        stdin()
+            .read_line(&mut password)
+            .context("Failed to read password from stdin")?;
+        return Ok(Secret::new(password.trim_end_matches(['\r', '\n']).into()));
+    }
+
+    let password = Secret::new(rpassword::prompt_password("Password: ")?);
+    let password_check = Secret::new(rpassword::prompt_password("Repeat password: ")?);
+    if password.expose_secret() != password_check.expose_secret() {
+        bail!("The passwords do not match");
+    }
+
+    Ok(password)
+}
+
+/// Whichever role exits first takes the others down with it.
+async fn stop_all_on_exit<T>(task: impl Future<Output = T>, shutdown: CancellationToken) -> T {
+    let _stop_all = shutdown.drop_guard();
+    task.await
+}
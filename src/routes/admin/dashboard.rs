@@ -1,13 +1,15 @@
 use axum::response::{IntoResponse, Response};
 use axum::{extract::State, response::Html};
+use htmlescape::encode_minimal;
 use hyper::StatusCode;
 use sqlx::PgPool;
 use tracing::instrument;
 use uuid::Uuid;
 
+use crate::domain::Role;
 use crate::log::WrapAndLogErr;
 use crate::startup::AppState;
-use crate::user_session::UserId;
+use crate::user_session::{Authorized, CanView, UserSession};
 
 #[derive(thiserror::Error, Debug)]
 #[error("Something went wrong")]
@@ -22,11 +24,37 @@ impl IntoResponse for DashboardError {
 #[instrument(skip_all, fields(uuid=?*user_id))]
 pub async fn admin_dashboard(
     state: State<AppState>,
-    user_id: UserId,
+    Authorized { user_id, role, .. }: Authorized<CanView>,
+    mut session: UserSession,
 ) -> Result<Html<String>, DashboardError> {
+    let csrf_token = session.csrf_token();
     let username = get_username(*user_id, &state.db_pool)
         .await
         .wrap_and_log_err("Failed to query username")?;
+    let username = encode_minimal(&username);
+    let publish_action = if role >= Role::Editor {
+        r#"<li>
+            <form name="sendNewsletterForm" action="/admin/newsletters" method="get">
+                <input type="submit" value="Send a Newsletter">
+            </form>
+        </li>"#
+    } else {
+        ""
+    };
+    let owner_actions = if role >= Role::Owner {
+        r#"<li>
+            <form name="inviteUserForm" action="/admin/users/invite" method="get">
+                <input type="submit" value="Invite a colleague">
+            </form>
+        </li>
+        <li>
+            <form name="resetTwoFactorForm" action="/admin/users/2fa" method="get">
+                <input type="submit" value="Reset a colleague's two-factor authentication">
+            </form>
+        </li>"#
+    } else {
+        ""
+    };
 
     Ok(Html(format!(
         r#"<!DOCTYPE html>
@@ -37,20 +65,34 @@ pub async fn admin_dashboard(
 </head>
 <body>
     <p>Welcome {username}!</p>
+    <p>You are signed in as {role}.</p>
     <p>Available actions:</p>
     <ol>
+        {publish_action}
         <li>
-            <form name="sendNewsletterForm" action="/admin/newsletters" method="get">
-                <input type="submit" value="Send a Newsletter">
-            </form>    
+            <form name="deliveryFailuresForm" action="/admin/deliveries/failures" method="get">
+                <input type="submit" value="Failed deliveries">
+            </form>
         </li>
+        {owner_actions}
         <li>
             <form name="changePasswordForm" action="/admin/password" method="get">
                 <input type="submit" value="Change password">
             </form>    
         </li>
+        <li>
+            <form name="sessionsForm" action="/admin/sessions" method="get">
+                <input type="submit" value="Active sessions">
+            </form>
+        </li>
+        <li>
+            <form name="twoFactorForm" action="/admin/2fa" method="get">
+                <input type="submit" value="Two-factor authentication">
+            </form>
+        </li>
         <li>
             <form name="logoutForm" action="/admin/logout" method="post">
+                <input hidden type="text" name="csrf_token" value="{csrf_token}">
                 <input type="submit" value="Logout">
             </form>
         </li>
@@ -0,0 +1,147 @@
+use std::fmt::Write;
+
+use axum::{
+    extract::State,
+    response::{Html, IntoResponse, Response},
+};
+use axum_flash::IncomingFlashes;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use hyper::StatusCode;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    log::WrapAndLogErr,
+    startup::AppState,
+    user_session::{Authorized, CanView, UserSession},
+};
+
+struct DeliveryFailure {
+    failure_id: Uuid,
+    newsletter_issue_id: Uuid,
+    title: String,
+    subscriber_email: String,
+    n_attempts: i16,
+    last_error: String,
+    enqueued_at: DateTime<Utc>,
+    failed_at: DateTime<Utc>,
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("Something went wrong")]
+pub struct DeliveryFailuresError(#[from] anyhow::Error);
+
+impl IntoResponse for DeliveryFailuresError {
+    fn into_response(self) -> Response {
+        (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()).into_response()
+    }
+}
+
+#[tracing::instrument(skip_all, fields(uuid=?*user_id))]
+pub async fn delivery_failures(
+    state: State<AppState>,
+    Authorized { user_id, .. }: Authorized<CanView>,
+    mut session: UserSession,
+    flashes: IncomingFlashes,
+) -> Result<(IncomingFlashes, Html<String>), DeliveryFailuresError> {
+    let csrf_token = session.csrf_token();
+    let mut msg_html = String::new();
+    for (_, msg) in flashes.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(msg)).unwrap();
+    }
+
+    let failures = get_delivery_failures(&state.db_pool)
+        .await
+        .wrap_and_log_err("Failed to query delivery failures")?;
+
+    let mut failures_html = String::new();
+    let mut current_issue_id = None;
+    for failure in &failures {
+        if current_issue_id != Some(failure.newsletter_issue_id) {
+            if current_issue_id.is_some() {
+                writeln!(failures_html, "    </table>").unwrap();
+            }
+            current_issue_id = Some(failure.newsletter_issue_id);
+            writeln!(
+                failures_html,
+                r#"    <h2>{}</h2>
+    <table>
+        <tr><th></th><th>Subscriber</th><th>Attempts</th><th>Last error</th><th>Enqueued at</th><th>Failed at</th></tr>"#,
+                encode_minimal(&failure.title)
+            )
+            .unwrap();
+        }
+        writeln!(
+            failures_html,
+            r#"        <tr>
+            <td><input type="checkbox" name="failure_id" value="{}"></td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+        </tr>"#,
+            failure.failure_id,
+            encode_minimal(&failure.subscriber_email),
+            failure.n_attempts,
+            encode_minimal(&failure.last_error),
+            failure.enqueued_at.to_rfc3339(),
+            failure.failed_at.to_rfc3339(),
+        )
+        .unwrap();
+    }
+    if failures.is_empty() {
+        writeln!(failures_html, "    <p>There are no failed deliveries.</p>").unwrap();
+    } else {
+        writeln!(
+            failures_html,
+            r#"    </table>
+    <button type="submit">Re-enqueue selected deliveries</button>"#
+        )
+        .unwrap();
+    }
+
+    let html = Html(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Failed deliveries</title>
+</head>
+<body>
+    {msg_html}
+    <form action="/admin/deliveries/failures" method="post">
+        <input hidden type="text" name="csrf_token" value="{csrf_token}">
+{failures_html}
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#
+    ));
+
+    Ok((flashes, html))
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_delivery_failures(db_pool: &PgPool) -> Result<Vec<DeliveryFailure>, sqlx::Error> {
+    sqlx::query_as!(
+        DeliveryFailure,
+        r#"
+    SELECT
+        f.failure_id,
+        f.newsletter_issue_id,
+        i.title,
+        f.subscriber_email,
+        f.n_attempts,
+        f.last_error,
+        f.enqueued_at,
+        f.failed_at
+    FROM issue_delivery_failures f
+    JOIN newsletter_issues i USING (newsletter_issue_id)
+    ORDER BY i.published_at DESC, f.newsletter_issue_id, f.failed_at
+        "#
+    )
+    .fetch_all(db_pool)
+    .await
+}
@@ -0,0 +1,109 @@
+use axum::{
+    extract::State,
+    response::{IntoResponse, Redirect, Response},
+};
+use axum_extra::extract::Form;
+use axum_flash::Flash;
+use hyper::StatusCode;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+    issue_delivery_worker::notify_new_tasks,
+    log::WrapAndLogErr,
+    startup::AppState,
+    user_session::{Authorized, CanPublish},
+};
+
+#[derive(Deserialize)]
+pub struct FormData {
+    #[serde(default)]
+    failure_id: Vec<Uuid>,
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error(transparent)]
+pub struct ReenqueueError(#[from] anyhow::Error);
+
+impl IntoResponse for ReenqueueError {
+    fn into_response(self) -> Response {
+        (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()).into_response()
+    }
+}
+
+#[tracing::instrument(skip_all, fields(uuid=?*user_id))]
+pub async fn reenqueue_delivery_failures(
+    state: State<AppState>,
+    Authorized { user_id, .. }: Authorized<CanPublish>,
+    flash: Flash,
+    form: Form<FormData>,
+) -> Result<(Flash, Redirect), ReenqueueError> {
+    let redirect = Redirect::to("/admin/deliveries/failures");
+    if form.failure_id.is_empty() {
+        return Ok((
+            flash.error("Select at least one delivery to re-enqueue."),
+            redirect,
+        ));
+    }
+
+    let mut transaction = state
+        .db_pool
+        .begin()
+        .await
+        .wrap_and_log_err("Failed to begin transaction")?;
+    // A failure is only forgotten once its delivery is back in the queue
+    let n_reenqueued = sqlx::query!(
+        r#"
+    WITH reenqueued AS (
+        INSERT INTO issue_delivery_queue (
+            newsletter_issue_id,
+            subscriber_email,
+            n_retries,
+            execute_after
+        )
+        SELECT newsletter_issue_id, subscriber_email, 0, now()
+        FROM issue_delivery_failures
+        WHERE failure_id = ANY($1)
+        ON CONFLICT DO NOTHING
+        RETURNING newsletter_issue_id, subscriber_email
+    )
+    DELETE FROM issue_delivery_failures
+    USING reenqueued
+    WHERE issue_delivery_failures.failure_id = ANY($1)
+        AND issue_delivery_failures.newsletter_issue_id = reenqueued.newsletter_issue_id
+        AND issue_delivery_failures.subscriber_email = reenqueued.subscriber_email
+        "#,
+        &form.failure_id
+    )
+    .execute(&mut transaction)
+    .await
+    .wrap_and_log_err("Failed to re-enqueue delivery failures")?
+    .rows_affected();
+    let n_already_queued = sqlx::query!(
+        r#"
+        SELECT count(*) AS "n!"
+        FROM issue_delivery_failures
+        WHERE failure_id = ANY($1)
+        "#,
+        &form.failure_id
+    )
+    .fetch_one(&mut transaction)
+    .await
+    .wrap_and_log_err("Failed to count delivery failures left")?
+    .n;
+    notify_new_tasks(&mut transaction)
+        .await
+        .wrap_and_log_err("Failed to notify delivery workers")?;
+    transaction
+        .commit()
+        .await
+        .wrap_and_log_err("Failed to commit transaction")?;
+
+    let mut flash = flash.success(format!("{n_reenqueued} deliveries have been re-enqueued."));
+    if n_already_queued > 0 {
+        flash = flash.error(format!(
+            "{n_already_queued} deliveries are still queued, their failures have been kept."
+        ));
+    }
+    Ok((flash, redirect))
+}
@@ -1,9 +1,17 @@
 mod dashboard;
+mod deliveries;
 mod logout;
 mod newsletter;
 mod password;
+mod sessions;
+mod two_factor;
+mod users;
 
-pub use dashboard::admin_dashboard;
+pub use dashboard::{admin_dashboard, get_username};
+pub use deliveries::*;
 pub use logout::logout;
 pub use newsletter::*;
 pub use password::*;
+pub use sessions::*;
+pub use two_factor::*;
+pub use users::*;
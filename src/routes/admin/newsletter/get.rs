@@ -1,17 +1,20 @@
 use axum::response::Html;
 use axum_flash::IncomingFlashes;
+use htmlescape::encode_minimal;
 use std::fmt::Write;
 
-use crate::user_session::UserId;
+use crate::user_session::{Authorized, CanPublish, UserSession};
 
 #[tracing::instrument(skip_all, fields(uuid=?*user_id))]
 pub async fn publish_newsletter_form(
-    user_id: UserId,
+    Authorized { user_id, .. }: Authorized<CanPublish>,
+    mut session: UserSession,
     flashes: IncomingFlashes,
 ) -> (IncomingFlashes, Html<String>) {
+    let csrf_token = session.csrf_token();
     let mut msg_html = String::new();
     for (_, msg) in flashes.iter() {
-        writeln!(msg_html, "<p><i>{msg}</i></p>").unwrap();
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(msg)).unwrap();
     }
     let idempotency_key = uuid::Uuid::new_v4();
     let html = Html(format!(
@@ -24,6 +27,7 @@ pub async fn publish_newsletter_form(
 <body>
     {msg_html}
     <form action="/admin/newsletters" method="post">
+        <input hidden type="text" name="csrf_token" value="{csrf_token}">
         <label>Title:<br>
             <input
                 type="text"
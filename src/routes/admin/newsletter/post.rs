@@ -11,9 +11,10 @@ use uuid::Uuid;
 
 use crate::{
     idempotency::{save_response, try_processing, IdempotencyKey, NextAction},
+    issue_delivery_worker::notify_new_tasks,
     log::WrapAndLogErr,
     startup::AppState,
-    user_session::UserId,
+    user_session::{Authorized, CanPublish},
 };
 
 #[derive(Deserialize)]
@@ -37,7 +38,7 @@ impl IntoResponse for PublishError {
 #[tracing::instrument(skip_all, fields(uuid=?*user_id))]
 pub async fn publish_newsletter(
     state: State<AppState>,
-    user_id: UserId,
+    Authorized { user_id, .. }: Authorized<CanPublish>,
     flash: Flash,
     form: Form<FormData>,
 ) -> Result<Response, PublishError> {
@@ -120,8 +121,9 @@ async fn enqueue_delivery_tasks(
     "#,
         newsletter_issue_id
     )
-    .execute(transaction)
+    .execute(&mut *transaction)
     .await?;
+    notify_new_tasks(transaction).await?;
 
     Ok(())
 }
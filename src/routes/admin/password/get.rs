@@ -1,18 +1,21 @@
 use axum::response::{Html, Redirect};
 use axum_flash::IncomingFlashes;
+use htmlescape::encode_minimal;
 use std::fmt::Write;
 use tracing::instrument;
 
-use crate::user_session::UserId;
+use crate::user_session::{UserId, UserSession};
 
 #[instrument(skip_all, fields(uuid=?*user_id))]
 pub async fn change_password_form(
     flashes: IncomingFlashes,
     user_id: UserId,
+    mut session: UserSession,
 ) -> Result<(IncomingFlashes, Html<String>), Redirect> {
+    let csrf_token = session.csrf_token();
     let mut msg_html = String::new();
     for (_, msg) in flashes.iter() {
-        writeln!(msg_html, "<p><i>{msg}</i></p>").unwrap();
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(msg)).unwrap();
     }
 
     Ok((
@@ -27,6 +30,7 @@ pub async fn change_password_form(
 <body>
     {msg_html}
     <form action="/admin/password" method="post">
+        <input hidden type="text" name="csrf_token" value="{csrf_token}">
         <label>Current password
             <input
                 type="password"
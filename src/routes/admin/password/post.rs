@@ -10,11 +10,11 @@ use serde::Deserialize;
 use tracing::instrument;
 
 use crate::{
-    authentication::{validate_credentials, AuthError, Credentials},
+    authentication::{check_password_rules, validate_credentials, AuthError, Credentials},
     log::{LogErr, WrapAndLogErr},
     routes::admin::dashboard::get_username,
     startup::AppState,
-    user_session::UserId,
+    user_session::{revoke_other_sessions, UserId, UserSession},
 };
 
 #[derive(Deserialize)]
@@ -61,6 +61,7 @@ impl IntoResponse for ChangePasswordError {
 pub async fn change_password(
     state: State<AppState>,
     user_id: UserId,
+    session: UserSession,
     flash: Flash,
     form: Form<ChangePassword>,
 ) -> Result<(Flash, Redirect), ChangePasswordError> {
@@ -70,18 +71,8 @@ pub async fn change_password(
         )));
     }
 
-    const PASSWORD_LOWER_BOUND: usize = 13;
-    if form.new_password.expose_secret().len() <= PASSWORD_LOWER_BOUND {
-        return Err(ChangePasswordError::Invalid(
-            flash.error("The new password is too short."),
-        ));
-    }
-
-    const PASSWORD_UPPER_BOUND: usize = 128;
-    if form.new_password.expose_secret().len() >= PASSWORD_UPPER_BOUND {
-        return Err(ChangePasswordError::Invalid(
-            flash.error("The new password is too long."),
-        ));
+    if let Err(e) = check_password_rules(&form.new_password) {
+        return Err(ChangePasswordError::Invalid(flash.error(e.to_string())));
     }
 
     let username = get_username(*user_id, &state.db_pool)
@@ -93,7 +84,7 @@ pub async fn change_password(
         password: form.0.current_password,
     };
 
-    if validate_credentials(credentials, &state.db_pool)
+    if validate_credentials(credentials, state.password_hashing, &state.db_pool)
         .await
         .log_err()
         .is_err()
@@ -103,9 +94,17 @@ pub async fn change_password(
         ));
     };
 
-    crate::authentication::change_password(*user_id, form.0.new_password, &state.db_pool)
+    crate::authentication::change_password(
+        *user_id,
+        form.0.new_password,
+        state.password_hashing,
+        &state.db_pool,
+    )
+    .await
+    .wrap_and_log_err("Failed to change password.")?;
+    revoke_other_sessions(&state.db_pool, *user_id, session.id())
         .await
-        .wrap_and_log_err("Failed to change password.")?;
+        .wrap_and_log_err("Failed to revoke other sessions")?;
 
     Ok((
         flash.success("Your password has been changed."),
@@ -0,0 +1,90 @@
+use std::fmt::Write;
+
+use axum::{extract::State, response::Html};
+use axum_flash::IncomingFlashes;
+use htmlescape::encode_minimal;
+
+use super::SessionsError;
+use crate::{
+    log::WrapAndLogErr,
+    startup::AppState,
+    user_session::{list_sessions, Authorized, CanView, UserSession},
+};
+
+#[tracing::instrument(skip_all, fields(uuid=?*user_id))]
+pub async fn sessions(
+    state: State<AppState>,
+    Authorized { user_id, .. }: Authorized<CanView>,
+    mut session: UserSession,
+    flashes: IncomingFlashes,
+) -> Result<(IncomingFlashes, Html<String>), SessionsError> {
+    let csrf_token = session.csrf_token();
+    let mut msg_html = String::new();
+    for (_, msg) in flashes.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(msg)).unwrap();
+    }
+
+    let active_sessions = list_sessions(
+        &state.db_pool,
+        *user_id,
+        session.id(),
+        &state.session_settings,
+    )
+    .await
+    .wrap_and_log_err("Failed to list sessions")?;
+
+    let mut sessions_html = String::new();
+    for active_session in &active_sessions {
+        let action = if active_session.is_current {
+            "This session".to_string()
+        } else {
+            format!(
+                r#"<form action="/admin/sessions/revoke" method="post">
+                <input hidden type="text" name="csrf_token" value="{csrf_token}">
+                <input hidden type="text" name="session_id" value="{}">
+                <button type="submit">Revoke</button>
+            </form>"#,
+                active_session.public_id
+            )
+        };
+        writeln!(
+            sessions_html,
+            r#"        <tr>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{action}</td>
+        </tr>"#,
+            active_session.created_at.to_rfc3339(),
+            active_session.last_seen_at.to_rfc3339(),
+            encode_minimal(active_session.ip.as_deref().unwrap_or("unknown")),
+            encode_minimal(active_session.user_agent.as_deref().unwrap_or("unknown")),
+        )
+        .unwrap();
+    }
+
+    let html = Html(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Active sessions</title>
+</head>
+<body>
+    {msg_html}
+    <table>
+        <tr><th>Logged in at</th><th>Last seen at</th><th>IP</th><th>Browser</th><th></th></tr>
+{sessions_html}
+    </table>
+    <form action="/admin/sessions/revoke_all" method="post">
+        <input hidden type="text" name="csrf_token" value="{csrf_token}">
+        <button type="submit">Log out everywhere</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#
+    ));
+
+    Ok((flashes, html))
+}
@@ -0,0 +1,18 @@
+mod get;
+mod post;
+
+use axum::response::{IntoResponse, Response};
+use hyper::StatusCode;
+
+pub use get::sessions;
+pub use post::{log_out_everywhere, revoke_session};
+
+#[derive(thiserror::Error, Debug)]
+#[error("Something went wrong")]
+pub struct SessionsError(#[from] anyhow::Error);
+
+impl IntoResponse for SessionsError {
+    fn into_response(self) -> Response {
+        (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()).into_response()
+    }
+}
@@ -0,0 +1,54 @@
+use axum::{extract::State, response::Redirect, Form};
+use axum_flash::Flash;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use super::SessionsError;
+use crate::{
+    log::WrapAndLogErr,
+    startup::AppState,
+    user_session::{self, revoke_sessions, Authorized, CanView, UserSession},
+};
+
+#[derive(Deserialize)]
+pub struct FormData {
+    session_id: Uuid,
+}
+
+#[tracing::instrument(skip_all, fields(uuid=?*user_id, session=%form.session_id))]
+pub async fn revoke_session(
+    state: State<AppState>,
+    Authorized { user_id, .. }: Authorized<CanView>,
+    flash: Flash,
+    form: Form<FormData>,
+) -> Result<(Flash, Redirect), SessionsError> {
+    let revoked = user_session::revoke_session(&state.db_pool, *user_id, form.0.session_id)
+        .await
+        .wrap_and_log_err("Failed to revoke session")?;
+    let flash = if revoked {
+        flash.success("The session has been revoked.")
+    } else {
+        flash.error("The session had already ended.")
+    };
+
+    Ok((flash, Redirect::to("/admin/sessions")))
+}
+
+#[tracing::instrument(skip_all, fields(uuid=?*user_id))]
+pub async fn log_out_everywhere(
+    state: State<AppState>,
+    Authorized { user_id, .. }: Authorized<CanView>,
+    mut session: UserSession,
+    flash: Flash,
+) -> Result<(UserSession, Flash, Redirect), SessionsError> {
+    revoke_sessions(&state.db_pool, *user_id)
+        .await
+        .wrap_and_log_err("Failed to revoke sessions")?;
+    session.logout();
+
+    Ok((
+        session,
+        flash.success("You have been logged out of every session."),
+        Redirect::to("/login"),
+    ))
+}
@@ -0,0 +1,96 @@
+use axum::{extract::State, response::Html};
+use axum_flash::IncomingFlashes;
+use htmlescape::encode_minimal;
+use qrcode::{render::svg, QrCode};
+use secrecy::ExposeSecret;
+use std::fmt::Write;
+
+use super::TwoFactorError;
+use crate::{
+    domain::TotpSecret,
+    log::WrapAndLogErr,
+    routes::admin::dashboard::get_username,
+    startup::AppState,
+    two_factor,
+    user_session::{Authorized, CanView, UserSession},
+};
+
+#[tracing::instrument(skip_all, fields(uuid=?*user_id))]
+pub async fn two_factor_form(
+    state: State<AppState>,
+    Authorized { user_id, .. }: Authorized<CanView>,
+    mut session: UserSession,
+    flashes: IncomingFlashes,
+) -> Result<(UserSession, IncomingFlashes, Html<String>), TwoFactorError> {
+    let csrf_token = session.csrf_token();
+    let mut msg_html = String::new();
+    for (_, msg) in flashes.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(msg)).unwrap();
+    }
+
+    let content = if two_factor::is_enabled(&state.db_pool, *user_id)
+        .await
+        .wrap_and_log_err("Failed to query two-factor status")?
+    {
+        "<p>Two-factor authentication is on for your account.</p>\n    \
+        <p>If you lose your authenticator, log in with one of your recovery codes \
+        or ask an owner to reset it.</p>"
+            .to_string()
+    } else {
+        // Reloading the page keeps the secret that may already be scanned
+        let secret = session.totp_enrollment().unwrap_or_else(|| {
+            let secret = TotpSecret::generate();
+            session.set_totp_enrollment(&secret);
+            secret
+        });
+        let username = get_username(*user_id, &state.db_pool)
+            .await
+            .wrap_and_log_err("Failed to query username")?;
+        let issuer = state.base_url.host_str().unwrap_or("zero2prod");
+        let otpauth_uri = secret.otpauth_uri(issuer, &username);
+        let qr_code = QrCode::new(otpauth_uri.as_bytes())
+            .wrap_and_log_err("Failed to encode QR code")?
+            .render::<svg::Color>()
+            .min_dimensions(200, 200)
+            .build();
+
+        format!(
+            r#"<p>Scan this QR code with your authenticator app:</p>
+    {qr_code}
+    <p>Or enter the key <code id="totp-secret">{}</code> by hand, or open
+        <a href="{}">this link</a> on the device running the app.</p>
+    <form action="/admin/2fa" method="post">
+        <input hidden type="text" name="csrf_token" value="{csrf_token}">
+        <label>Code
+            <input
+                type="text"
+                autocomplete="one-time-code"
+                placeholder="Enter the code shown by the app"
+                name="code"
+            >
+        </label>
+        <br>
+        <button type="submit">Turn on two-factor authentication</button>
+    </form>"#,
+            encode_minimal(secret.expose_secret()),
+            encode_minimal(&otpauth_uri),
+        )
+    };
+
+    let html = Html(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Two-factor authentication</title>
+</head>
+<body>
+    {msg_html}
+    {content}
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+    ));
+
+    Ok((session, flashes, html))
+}
@@ -0,0 +1,18 @@
+mod get;
+mod post;
+
+use axum::response::{IntoResponse, Response};
+use hyper::StatusCode;
+
+pub use get::two_factor_form;
+pub use post::enable_two_factor;
+
+#[derive(thiserror::Error, Debug)]
+#[error("Something went wrong")]
+pub struct TwoFactorError(#[from] anyhow::Error);
+
+impl IntoResponse for TwoFactorError {
+    fn into_response(self) -> Response {
+        (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()).into_response()
+    }
+}
@@ -0,0 +1,92 @@
+use axum::{
+    extract::State,
+    response::{Html, IntoResponse, Redirect, Response},
+    Form,
+};
+use axum_flash::Flash;
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+use std::fmt::Write;
+
+use super::TwoFactorError;
+use crate::{
+    log::WrapAndLogErr,
+    startup::AppState,
+    two_factor,
+    user_session::{Authorized, CanView, UserSession},
+};
+
+#[derive(Deserialize)]
+pub struct FormData {
+    code: Secret<String>,
+}
+
+#[tracing::instrument(skip_all, fields(uuid=?*user_id))]
+pub async fn enable_two_factor(
+    state: State<AppState>,
+    Authorized { user_id, .. }: Authorized<CanView>,
+    mut session: UserSession,
+    flash: Flash,
+    form: Form<FormData>,
+) -> Result<Response, TwoFactorError> {
+    let redirect = Redirect::to("/admin/2fa");
+    let Some(secret) = session.totp_enrollment() else {
+        return Ok(redirect.into_response());
+    };
+
+    let mut transaction = state
+        .db_pool
+        .begin()
+        .await
+        .wrap_and_log_err("Failed to begin transaction")?;
+    let Some(recovery_codes) = two_factor::enable(
+        &mut transaction,
+        *user_id,
+        &secret,
+        form.0.code.expose_secret(),
+    )
+    .await
+    .wrap_and_log_err("Failed to enable two-factor authentication")?
+    else {
+        return Ok((
+            flash.error("The code is not valid - check the clock of your device and try again."),
+            redirect,
+        )
+            .into_response());
+    };
+    transaction
+        .commit()
+        .await
+        .wrap_and_log_err("Failed to commit transaction")?;
+    session.clear_totp_enrollment();
+
+    let mut codes_html = String::new();
+    for recovery_code in &recovery_codes {
+        writeln!(
+            codes_html,
+            "<li><code>{}</code></li>",
+            recovery_code.as_ref()
+        )
+        .unwrap();
+    }
+    let html = Html(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Recovery codes</title>
+</head>
+<body>
+    <p>Two-factor authentication is now on.</p>
+    <p>Keep these recovery codes somewhere safe. Each of them lets you log in
+        once without your authenticator, and they will not be shown again.</p>
+    <ul>
+        {codes_html}
+    </ul>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+    ));
+
+    Ok((session, html).into_response())
+}
@@ -0,0 +1,50 @@
+use axum::response::Html;
+use axum_flash::IncomingFlashes;
+use htmlescape::encode_minimal;
+use std::fmt::Write;
+
+use crate::user_session::{Authorized, CanManageUsers, UserSession};
+
+#[tracing::instrument(skip_all, fields(uuid=?*user_id))]
+pub async fn invite_user_form(
+    Authorized { user_id, .. }: Authorized<CanManageUsers>,
+    mut session: UserSession,
+    flashes: IncomingFlashes,
+) -> (IncomingFlashes, Html<String>) {
+    let csrf_token = session.csrf_token();
+    let mut msg_html = String::new();
+    for (_, msg) in flashes.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(msg)).unwrap();
+    }
+    let html = Html(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Invite a colleague</title>
+</head>
+<body>
+    {msg_html}
+    <form action="/admin/users/invite" method="post">
+        <input hidden type="text" name="csrf_token" value="{csrf_token}">
+        <label>Email
+            <input type="email" placeholder="Enter their email" name="email">
+        </label>
+        <br>
+        <label>Role
+            <select name="role">
+                <option value="viewer">Viewer - can read stats</option>
+                <option value="editor">Editor - can also publish newsletters</option>
+                <option value="owner">Owner - can also invite colleagues</option>
+            </select>
+        </label>
+        <br>
+        <button type="submit">Send invite</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+    ));
+
+    (flashes, html)
+}
@@ -0,0 +1,7 @@
+mod get;
+mod post;
+mod two_factor;
+
+pub use get::invite_user_form;
+pub use post::invite_user;
+pub use two_factor::*;
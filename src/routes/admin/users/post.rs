@@ -0,0 +1,183 @@
+use std::time::Duration;
+
+use axum::{
+    extract::State,
+    response::{IntoResponse, Redirect, Response},
+    Form,
+};
+use axum_flash::Flash;
+use hyper::StatusCode;
+use serde::Deserialize;
+use sqlx::{PgPool, Postgres, Transaction};
+use url::Url;
+use uuid::Uuid;
+
+use crate::{
+    domain::{OneTimeToken, Role, SubscriberEmail},
+    email_client::EmailClient,
+    log::WrapAndLogErr,
+    startup::AppState,
+    user_session::{Authorized, CanManageUsers},
+};
+
+#[derive(Deserialize)]
+pub struct FormData {
+    email: String,
+    role: String,
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error(transparent)]
+pub struct InviteError(#[from] anyhow::Error);
+
+impl IntoResponse for InviteError {
+    fn into_response(self) -> Response {
+        (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()).into_response()
+    }
+}
+
+#[tracing::instrument(skip_all, fields(uuid=?*user_id, invitee=form.email))]
+pub async fn invite_user(
+    state: State<AppState>,
+    Authorized { user_id, .. }: Authorized<CanManageUsers>,
+    flash: Flash,
+    form: Form<FormData>,
+) -> Result<(Flash, Redirect), InviteError> {
+    let redirect = Redirect::to("/admin/users/invite");
+    let (email, role) = match (
+        SubscriberEmail::parse(form.0.email),
+        Role::parse(form.0.role),
+    ) {
+        (Ok(email), Ok(role)) => (email, role),
+        (Err(e), _) | (_, Err(e)) => return Ok((flash.error(e), redirect)),
+    };
+
+    if has_account(&state.db_pool, &email)
+        .await
+        .wrap_and_log_err("Failed to look up existing users")?
+    {
+        return Ok((
+            flash.error(format!("{email} already has an account.")),
+            redirect,
+        ));
+    }
+    // Only one of them could be accepted, as every account has its own address
+    if has_pending_invite(&state.db_pool, &email, state.invite_ttl)
+        .await
+        .wrap_and_log_err("Failed to look up pending invites")?
+    {
+        return Ok((
+            flash.error(format!("{email} has already been invited.")),
+            redirect,
+        ));
+    }
+
+    // The invite is only kept once it is sent, or it would block a retry
+    let mut transaction = state
+        .db_pool
+        .begin()
+        .await
+        .wrap_and_log_err("Failed to begin transaction")?;
+    let invite_token = OneTimeToken::generate();
+    store_invite(&mut transaction, &invite_token, &email, role, *user_id)
+        .await
+        .wrap_and_log_err("Failed to store invite")?;
+    send_invite_email(
+        &state.email_client,
+        &email,
+        &state.base_url,
+        &invite_token,
+        role,
+    )
+    .await?;
+    transaction
+        .commit()
+        .await
+        .wrap_and_log_err("Failed to commit transaction")?;
+
+    Ok((
+        flash.success(format!("An invite has been sent to {email}.")),
+        redirect,
+    ))
+}
+
+async fn has_account(db_pool: &PgPool, email: &SubscriberEmail) -> Result<bool, sqlx::Error> {
+    let user = sqlx::query!(
+        r#"SELECT user_id FROM users WHERE email = $1"#,
+        email.as_ref()
+    )
+    .fetch_optional(db_pool)
+    .await?;
+
+    Ok(user.is_some())
+}
+
+async fn has_pending_invite(
+    db_pool: &PgPool,
+    email: &SubscriberEmail,
+    invite_ttl: Duration,
+) -> Result<bool, sqlx::Error> {
+    let invite = sqlx::query!(
+        r#"
+        SELECT invite_token_hash FROM user_invites
+        WHERE email = $1 AND created_at > now() - make_interval(secs => $2)
+        "#,
+        email.as_ref(),
+        invite_ttl.as_secs_f64()
+    )
+    .fetch_optional(db_pool)
+    .await?;
+
+    Ok(invite.is_some())
+}
+
+#[tracing::instrument(skip_all)]
+async fn store_invite(
+    transaction: &mut Transaction<'_, Postgres>,
+    invite_token: &OneTimeToken,
+    email: &SubscriberEmail,
+    role: Role,
+    invited_by: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO user_invites (invite_token_hash, email, role, invited_by)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        invite_token.hash(),
+        email.as_ref(),
+        role.as_ref(),
+        invited_by
+    )
+    .execute(transaction)
+    .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn send_invite_email(
+    email_client: &EmailClient,
+    recipient: &SubscriberEmail,
+    base_url: &Url,
+    invite_token: &OneTimeToken,
+    role: Role,
+) -> Result<(), InviteError> {
+    let invite_link = base_url
+        .join(&format!("invites/accept?token={}", invite_token.as_ref()))
+        .unwrap();
+    let html_body = format!(
+        "You have been invited to help run our newsletter as {role}.<br />\
+        Click <a href=\"{invite_link}\">here</a> to choose your username and password.",
+    );
+    let text_body = format!(
+        "You have been invited to help run our newsletter as {role}.\n\
+        Visit {invite_link} to choose your username and password.",
+    );
+    email_client
+        .send(recipient, "You are invited!", &html_body, &text_body)
+        .await
+        .wrap_and_log_err("Failed to send invite email")?;
+
+    Ok(())
+}
@@ -0,0 +1,45 @@
+use axum::response::Html;
+use axum_flash::IncomingFlashes;
+use htmlescape::encode_minimal;
+use std::fmt::Write;
+
+use crate::user_session::{Authorized, CanManageUsers, UserSession};
+
+#[tracing::instrument(skip_all, fields(uuid=?*user_id))]
+pub async fn reset_two_factor_form(
+    Authorized { user_id, .. }: Authorized<CanManageUsers>,
+    mut session: UserSession,
+    flashes: IncomingFlashes,
+) -> (IncomingFlashes, Html<String>) {
+    let csrf_token = session.csrf_token();
+    let mut msg_html = String::new();
+    for (_, msg) in flashes.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(msg)).unwrap();
+    }
+    let html = Html(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Reset two-factor authentication</title>
+</head>
+<body>
+    {msg_html}
+    <p>Turns off two-factor authentication for a colleague who lost their
+        authenticator and recovery codes, so that they can log in with their
+        password and set it up again.</p>
+    <form action="/admin/users/2fa" method="post">
+        <input hidden type="text" name="csrf_token" value="{csrf_token}">
+        <label>Username
+            <input type="text" placeholder="Enter their username" name="username">
+        </label>
+        <br>
+        <button type="submit">Reset two-factor authentication</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+    ));
+
+    (flashes, html)
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::reset_two_factor_form;
+pub use post::reset_two_factor;
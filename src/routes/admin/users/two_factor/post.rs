@@ -0,0 +1,50 @@
+use axum::{
+    extract::State,
+    response::{IntoResponse, Redirect, Response},
+    Form,
+};
+use axum_flash::Flash;
+use hyper::StatusCode;
+use serde::Deserialize;
+
+use crate::{
+    log::WrapAndLogErr,
+    startup::AppState,
+    user_session::{Authorized, CanManageUsers},
+    users::{self, UserError},
+};
+
+#[derive(Deserialize)]
+pub struct FormData {
+    username: String,
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error(transparent)]
+pub struct ResetTwoFactorError(#[from] anyhow::Error);
+
+impl IntoResponse for ResetTwoFactorError {
+    fn into_response(self) -> Response {
+        (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()).into_response()
+    }
+}
+
+#[tracing::instrument(skip_all, fields(uuid=?*user_id, user=form.username))]
+pub async fn reset_two_factor(
+    state: State<AppState>,
+    Authorized { user_id, .. }: Authorized<CanManageUsers>,
+    flash: Flash,
+    form: Form<FormData>,
+) -> Result<(Flash, Redirect), ResetTwoFactorError> {
+    let redirect = Redirect::to("/admin/users/2fa");
+    let username = form.0.username;
+    let flash = match users::reset_two_factor(&state.db_pool, &username).await {
+        Ok(()) => flash.success(format!(
+            "Two-factor authentication has been reset for {username}."
+        )),
+        Err(e @ UserError::UnknownUser(_)) => flash.error(e.to_string()),
+        Err(e) => Err(e).wrap_and_log_err("Failed to reset two-factor authentication")?,
+    };
+
+    Ok((flash, redirect))
+}
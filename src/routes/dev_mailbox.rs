@@ -0,0 +1,148 @@
+use std::{fmt::Write, path::PathBuf};
+
+use axum::{
+    extract::{Path, State},
+    response::{Html, IntoResponse, Response},
+};
+use hyper::StatusCode;
+
+use crate::{
+    log::{LogErr, WrapAndLogErr},
+    startup::AppState,
+};
+
+#[derive(thiserror::Error, Debug)]
+pub enum MailboxError {
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+    #[error("There is no such message in the mailbox")]
+    UnknownMessage,
+}
+
+impl IntoResponse for MailboxError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            MailboxError::UnknownMessage => StatusCode::NOT_FOUND,
+            MailboxError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        (status, self.to_string()).into_response()
+    }
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn dev_mailbox(state: State<AppState>) -> Result<Html<String>, MailboxError> {
+    let directory = mailbox_directory(&state)?;
+
+    let mut file_names = Vec::new();
+    let mut entries = tokio::fs::read_dir(directory)
+        .await
+        .wrap_and_log_err("Failed to read the mailbox directory")?;
+    while let Some(entry) = entries
+        .next_entry()
+        .await
+        .wrap_and_log_err("Failed to read the mailbox directory")?
+    {
+        if let Some(file_name) = entry.file_name().to_str() {
+            if file_name.ends_with(".eml") {
+                file_names.push(file_name.to_owned());
+            }
+        }
+    }
+    // File names start with a timestamp, so the newest messages come first
+    file_names.sort_unstable_by(|a, b| b.cmp(a));
+
+    let mut messages_html = String::new();
+    for file_name in file_names {
+        let message = read_message(directory, &file_name).await?;
+        let to = htmlescape::encode_minimal(header(&message, "To").unwrap_or_default());
+        let subject = htmlescape::encode_minimal(header(&message, "Subject").unwrap_or_default());
+        let mut link = state.base_url.clone();
+        link.set_path("/dev/mailbox");
+        link.path_segments_mut().unwrap().push(&file_name);
+        writeln!(
+            messages_html,
+            r#"<li><a href="{}">{subject}</a> to {to}</li>"#,
+            htmlescape::encode_minimal(link.path())
+        )
+        .unwrap();
+    }
+
+    Ok(Html(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Dev mailbox</title>
+</head>
+<body>
+    <p>Messages written to {}:</p>
+    <ol>
+        {messages_html}
+    </ol>
+</body>
+</html>"#,
+        htmlescape::encode_minimal(&directory.display().to_string())
+    )))
+}
+
+#[tracing::instrument(skip(state))]
+pub async fn dev_mailbox_message(
+    state: State<AppState>,
+    Path(file_name): Path<String>,
+) -> Result<Html<String>, MailboxError> {
+    let directory = mailbox_directory(&state)?;
+    let is_plain_file_name = std::path::Path::new(&file_name)
+        .file_name()
+        .is_some_and(|name| name == file_name.as_str());
+    if !is_plain_file_name || !file_name.ends_with(".eml") {
+        return Err(MailboxError::UnknownMessage).log_err();
+    }
+
+    let message = read_message(directory, &file_name).await?;
+
+    Ok(Html(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>{}</title>
+</head>
+<body>
+    <p><a href="/dev/mailbox">&lt;- Back to the mailbox</a></p>
+    <pre>{}</pre>
+</body>
+</html>"#,
+        htmlescape::encode_minimal(&file_name),
+        htmlescape::encode_minimal(&message)
+    )))
+}
+
+fn mailbox_directory(state: &AppState) -> Result<&PathBuf, MailboxError> {
+    state
+        .dev_mailbox
+        .as_ref()
+        .ok_or(MailboxError::UnknownMessage)
+        .log_err()
+}
+
+async fn read_message(
+    directory: &std::path::Path,
+    file_name: &str,
+) -> Result<String, MailboxError> {
+    match tokio::fs::read(directory.join(file_name)).await {
+        Ok(message) => Ok(String::from_utf8_lossy(&message).into_owned()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            Err(MailboxError::UnknownMessage).log_err()
+        }
+        Err(e) => Err(e)
+            .wrap_and_log_err("Failed to read message")
+            .map_err(MailboxError::from),
+    }
+}
+
+fn header<'a>(message: &'a str, name: &str) -> Option<&'a str> {
+    message
+        .lines()
+        .take_while(|line| !line.is_empty())
+        .find_map(|line| line.strip_prefix(name)?.strip_prefix(": "))
+}
@@ -1,4 +1,31 @@
-use tracing::instrument;
+use axum::extract::State;
+use hyper::StatusCode;
+use sqlx::PgPool;
+use tracing::{error, instrument};
+
+use crate::issue_delivery_worker::Heartbeat;
 
 #[instrument]
 pub async fn health_check() {}
+
+#[derive(Clone)]
+pub struct WorkerHealth {
+    pub db_pool: PgPool,
+    pub heartbeat: Heartbeat,
+}
+
+/// Fails once the database cannot be reached, or the delivery workers have
+/// stopped going round their loop.
+#[instrument(skip_all)]
+pub async fn worker_health_check(State(health): State<WorkerHealth>) -> StatusCode {
+    if health.heartbeat.is_stale() {
+        error!("Delivery workers have stopped.");
+        return StatusCode::SERVICE_UNAVAILABLE;
+    }
+    if let Err(e) = health.db_pool.acquire().await {
+        error!(error = %e, "Failed to connect to the database.");
+        return StatusCode::SERVICE_UNAVAILABLE;
+    }
+
+    StatusCode::OK
+}
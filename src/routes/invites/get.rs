@@ -0,0 +1,69 @@
+use axum::{
+    extract::{Query, State},
+    response::Html,
+};
+use axum_flash::IncomingFlashes;
+use htmlescape::encode_minimal;
+use serde::Deserialize;
+use std::fmt::Write;
+
+use super::{get_invite, InviteLinkError};
+use crate::{domain::OneTimeToken, startup::AppState};
+
+#[derive(Deserialize)]
+pub struct Params {
+    token: String,
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn accept_invite_form(
+    state: State<AppState>,
+    params: Query<Params>,
+    flashes: IncomingFlashes,
+) -> Result<(IncomingFlashes, Html<String>), InviteLinkError> {
+    let invite_token = OneTimeToken::from(params.0.token);
+    let invite = get_invite(&state.db_pool, &invite_token, state.invite_ttl).await?;
+
+    let mut msg_html = String::new();
+    for (_, msg) in flashes.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(msg)).unwrap();
+    }
+    let html = Html(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Accept invite</title>
+</head>
+<body>
+    {msg_html}
+    <p>You have been invited as {} with {}.</p>
+    <form action="/invites/accept" method="post">
+        <label>Username
+            <input type="text" placeholder="Choose a username" name="username">
+        </label>
+        <br>
+        <label>Password
+            <input type="password" placeholder="Choose a password" name="password">
+        </label>
+        <br>
+        <label>Confirm password
+            <input
+                type="password"
+                placeholder="Type the password again"
+                name="password_check"
+            >
+        </label>
+        <br>
+        <input hidden type="text" name="token" value="{}">
+        <button type="submit">Create account</button>
+    </form>
+</body>
+</html>"#,
+        encode_minimal(&invite.role),
+        encode_minimal(&invite.email),
+        encode_minimal(invite_token.as_ref()),
+    ));
+
+    Ok((flashes, html))
+}
@@ -0,0 +1,110 @@
+mod get;
+mod post;
+
+use std::time::Duration;
+
+use axum::response::{Html, IntoResponse, Response};
+use chrono::{DateTime, Utc};
+use hyper::StatusCode;
+use sqlx::{PgExecutor, PgPool};
+
+use crate::{
+    domain::{is_expired, OneTimeToken},
+    log::{LogErr, WrapAndLogErr},
+};
+
+pub use get::accept_invite_form;
+pub use post::accept_invite;
+
+struct Invite {
+    email: String,
+    role: String,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum InviteLinkError {
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+    #[error("This invite link is invalid or has already been used")]
+    UnknownToken,
+    #[error("This invite link has expired")]
+    ExpiredToken,
+}
+
+impl IntoResponse for InviteLinkError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            InviteLinkError::UnknownToken => StatusCode::UNAUTHORIZED,
+            InviteLinkError::ExpiredToken => StatusCode::GONE,
+            InviteLinkError::UnexpectedError(_) => {
+                return (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()).into_response()
+            }
+        };
+        (
+            status,
+            Html(format!(
+                r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Invite unavailable</title>
+</head>
+<body>
+    <p>{self}. Ask the colleague who invited you for a new invite.</p>
+</body>
+</html>"#
+            )),
+        )
+            .into_response()
+    }
+}
+
+async fn get_invite(
+    db_pool: &PgPool,
+    invite_token: &OneTimeToken,
+    ttl: Duration,
+) -> Result<Invite, InviteLinkError> {
+    let invite = sqlx::query_as!(
+        Invite,
+        r#"SELECT email, role, created_at FROM user_invites WHERE invite_token_hash = $1"#,
+        invite_token.hash()
+    )
+    .fetch_optional(db_pool)
+    .await
+    .wrap_and_log_err("Failed to query invite")?;
+
+    check_invite(invite, ttl)
+}
+
+/// Deletes the invite so that the link cannot be used again. This only sticks
+/// if the surrounding transaction commits.
+async fn take_invite(
+    executor: impl PgExecutor<'_>,
+    invite_token: &OneTimeToken,
+    ttl: Duration,
+) -> Result<Invite, InviteLinkError> {
+    let invite = sqlx::query_as!(
+        Invite,
+        r#"
+        DELETE FROM user_invites
+        WHERE invite_token_hash = $1
+        RETURNING email, role, created_at
+        "#,
+        invite_token.hash()
+    )
+    .fetch_optional(executor)
+    .await
+    .wrap_and_log_err("Failed to take invite")?;
+
+    check_invite(invite, ttl)
+}
+
+fn check_invite(invite: Option<Invite>, ttl: Duration) -> Result<Invite, InviteLinkError> {
+    let invite = invite.ok_or(InviteLinkError::UnknownToken).log_err()?;
+    if is_expired(invite.created_at, ttl) {
+        return Err(InviteLinkError::ExpiredToken).log_err();
+    }
+
+    Ok(invite)
+}
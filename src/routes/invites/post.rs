@@ -0,0 +1,96 @@
+use axum::{
+    extract::State,
+    response::{IntoResponse, Redirect, Response},
+    Form,
+};
+use axum_flash::Flash;
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+use url::form_urlencoded;
+
+use super::{take_invite, InviteLinkError};
+use crate::{
+    domain::{OneTimeToken, Role, SubscriberEmail},
+    log::WrapAndLogErr,
+    startup::AppState,
+    users::{create_user, UserError},
+};
+
+#[derive(Deserialize)]
+pub struct FormData {
+    token: String,
+    username: String,
+    password: Secret<String>,
+    password_check: Secret<String>,
+}
+
+#[tracing::instrument(skip_all, fields(user=form.username))]
+pub async fn accept_invite(
+    state: State<AppState>,
+    flash: Flash,
+    form: Form<FormData>,
+) -> Result<Response, InviteLinkError> {
+    let form = form.0;
+    let retry = Redirect::to(&format!(
+        "/invites/accept?{}",
+        form_urlencoded::Serializer::new(String::new())
+            .append_pair("token", &form.token)
+            .finish()
+    ));
+    if form.password.expose_secret() != form.password_check.expose_secret() {
+        return Ok((
+            flash.error("You entered two different passwords - the field values must match."),
+            retry,
+        )
+            .into_response());
+    }
+
+    let mut transaction = state
+        .db_pool
+        .begin()
+        .await
+        .wrap_and_log_err("Failed to begin transaction")?;
+    let invite = take_invite(
+        &mut transaction,
+        &OneTimeToken::from(form.token),
+        state.invite_ttl,
+    )
+    .await?;
+    let email = SubscriberEmail::parse(invite.email)
+        .map_err(anyhow::Error::msg)
+        .wrap_and_log_err("Stored invite email is invalid")?;
+    let role = Role::parse(invite.role)
+        .map_err(anyhow::Error::msg)
+        .wrap_and_log_err("Stored invite role is invalid")?;
+
+    match create_user(
+        &mut transaction,
+        &form.username,
+        form.password,
+        role,
+        Some(&email),
+        state.password_hashing,
+    )
+    .await
+    {
+        Ok(_) => {}
+        // The invite stays valid, as the transaction is rolled back
+        Err(
+            e @ (UserError::InvalidUsername
+            | UserError::WeakPassword(_)
+            | UserError::DuplicateUsername(_)
+            | UserError::DuplicateEmail(_)),
+        ) => return Ok((flash.error(e.to_string()), retry).into_response()),
+        Err(e) => Err(e).wrap_and_log_err("Failed to create user")?,
+    }
+    transaction
+        .commit()
+        .await
+        .wrap_and_log_err("Failed to commit transaction")?;
+
+    Ok((
+        flash.success("Your account has been created - you can now log in."),
+        Redirect::to("/login"),
+    )
+        .into_response())
+}
@@ -1,13 +1,20 @@
 use axum::response::Html;
 use axum_flash::IncomingFlashes;
+use htmlescape::encode_minimal;
 use std::fmt::Write;
 use tracing::instrument;
 
+use crate::user_session::UserSession;
+
 #[instrument(skip_all)]
-pub async fn login_form(flashes: IncomingFlashes) -> (IncomingFlashes, Html<String>) {
+pub async fn login_form(
+    mut session: UserSession,
+    flashes: IncomingFlashes,
+) -> (IncomingFlashes, Html<String>) {
+    let csrf_token = session.csrf_token();
     let mut error_html = String::new();
     for (_, msg) in flashes.iter() {
-        writeln!(error_html, "<p><i>{msg}</i></p>").unwrap();
+        writeln!(error_html, "<p><i>{}</i></p>", encode_minimal(msg)).unwrap();
     }
     let html = Html(format!(
         r#"<!DOCTYPE html>
@@ -21,6 +28,7 @@ pub async fn login_form(flashes: IncomingFlashes) -> (IncomingFlashes, Html<Stri
 <body>
     {error_html}
     <form action="/login" method="post">
+        <input hidden type="text" name="csrf_token" value="{csrf_token}">
         <label>Username
             <input type="text" placeholder="Enter Username" name="username">
         </label>
@@ -31,6 +39,7 @@ pub async fn login_form(flashes: IncomingFlashes) -> (IncomingFlashes, Html<Stri
 
         <button type="submit">Login</button>
     </form>
+    <p><a href="/password/forgot">Forgot your password?</a></p>
 </body>
 
 </html>
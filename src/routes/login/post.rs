@@ -1,5 +1,8 @@
+use std::net::SocketAddr;
+
 use axum::{
-    extract::State,
+    extract::{ConnectInfo, State},
+    http::HeaderMap,
     response::{IntoResponse, Redirect, Response},
     Form,
 };
@@ -11,8 +14,11 @@ use tracing::{field::debug, instrument, Span};
 
 use crate::{
     authentication::{validate_credentials, AuthError, Credentials},
+    client_ip::client_ip,
     log::LogErr,
+    login_throttle::ThrottleError,
     startup::AppState,
+    two_factor,
     user_session::UserSession,
 };
 
@@ -26,6 +32,8 @@ pub struct LoginForm {
 pub enum LoginError {
     #[error("Authentication failed")]
     AuthError,
+    #[error(transparent)]
+    Throttled(ThrottleError),
     #[error("Something went wrong")]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -39,10 +47,21 @@ impl From<AuthError> for LoginError {
     }
 }
 
+impl From<ThrottleError> for LoginError {
+    fn from(error: ThrottleError) -> Self {
+        match error {
+            ThrottleError::UnexpectedError(err) => LoginError::UnexpectedError(err),
+            err @ ThrottleError::Throttled(_) => LoginError::Throttled(err),
+        }
+    }
+}
+
 impl IntoResponse for LoginError {
     fn into_response(self) -> Response {
         match self {
-            LoginError::AuthError => Redirect::to("/login").into_response(),
+            LoginError::AuthError | LoginError::Throttled(_) => {
+                Redirect::to("/login").into_response()
+            }
             LoginError::UnexpectedError(_) => {
                 (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()).into_response()
             }
@@ -53,16 +72,26 @@ impl IntoResponse for LoginError {
 #[instrument(skip_all, fields(user=form.username, uuid))]
 pub async fn login(
     state: State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     mut session: UserSession,
     flash: Flash,
     form: Form<LoginForm>,
 ) -> Result<(UserSession, Redirect), (Flash, LoginError)> {
+    let throttle = &state.login_throttle;
+    let ip = client_ip(&headers, peer, state.client_ip_header.as_deref());
+    let username = form.0.username;
+    // The attempt counts as failed until the password turns out to be right
+    if let Err(err) = throttle.reserve(&username, ip).await.log_err() {
+        let err = LoginError::from(err);
+        return Err((flash.error(err.to_string()), err));
+    }
+
     let credentials = Credentials {
-        username: form.0.username,
+        username: username.clone(),
         password: form.0.password,
     };
-
-    match validate_credentials(credentials, &state.db_pool)
+    match validate_credentials(credentials, state.password_hashing, &state.db_pool)
         .await
         .log_err()
     {
@@ -70,12 +99,38 @@ pub async fn login(
             Span::current().record("uuid", debug(&user_id));
 
             session.renew();
+            let two_factor_enabled = two_factor::is_enabled(&state.db_pool, user_id)
+                .await
+                .log_err()
+                .map_err(|e| (flash.clone(), LoginError::UnexpectedError(e)))?;
+            // The failures are only forgotten once the second factor is checked too
+            if two_factor_enabled {
+                throttle
+                    .release(&username, ip)
+                    .await
+                    .log_err()
+                    .map_err(|e| (flash.clone(), LoginError::UnexpectedError(e)))?;
+                session.start_two_factor(user_id);
+                return Ok((session, Redirect::to("/login/2fa")));
+            }
+            throttle
+                .record_success(&username, ip)
+                .await
+                .log_err()
+                .map_err(|e| (flash.clone(), LoginError::UnexpectedError(e)))?;
             session.login(user_id);
 
             Ok((session, Redirect::to("/admin/dashboard")))
         }
         Err(err) => {
-            let err = LoginError::from(err);
+            let mut err = LoginError::from(err);
+            let recorded = match err {
+                LoginError::AuthError => throttle.record_failure(&username, ip).await,
+                _ => throttle.release(&username, ip).await,
+            };
+            if let Err(e) = recorded.log_err() {
+                err = LoginError::UnexpectedError(e);
+            }
             Err((flash.error(err.to_string()), err))
         }
     }
@@ -0,0 +1,51 @@
+use axum::response::{Html, IntoResponse, Redirect, Response};
+use axum_flash::IncomingFlashes;
+use htmlescape::encode_minimal;
+use std::fmt::Write;
+use tracing::instrument;
+
+use crate::user_session::UserSession;
+
+#[instrument(skip_all)]
+pub async fn login_two_factor_form(mut session: UserSession, flashes: IncomingFlashes) -> Response {
+    if session.pending_two_factor().is_none() {
+        return Redirect::to("/login").into_response();
+    }
+    let csrf_token = session.csrf_token();
+
+    let mut error_html = String::new();
+    for (_, msg) in flashes.iter() {
+        writeln!(error_html, "<p><i>{}</i></p>", encode_minimal(msg)).unwrap();
+    }
+    let html = Html(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Two-factor authentication</title>
+</head>
+
+<body>
+    {error_html}
+    <form action="/login/2fa" method="post">
+        <input hidden type="text" name="csrf_token" value="{csrf_token}">
+        <label>Code
+            <input
+                type="text"
+                autocomplete="one-time-code"
+                placeholder="Code from your authenticator or a recovery code"
+                name="code"
+            >
+        </label>
+
+        <button type="submit">Verify</button>
+    </form>
+</body>
+
+</html>
+"#,
+    ));
+
+    (flashes, html).into_response()
+}
@@ -0,0 +1,75 @@
+use std::net::SocketAddr;
+
+use axum::{
+    extract::{ConnectInfo, State},
+    http::HeaderMap,
+    response::{IntoResponse, Redirect, Response},
+    Form,
+};
+use axum_flash::Flash;
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+use tracing::{field::debug, instrument, Span};
+
+use crate::{
+    client_ip::client_ip,
+    log::{LogErr, WrapAndLogErr},
+    routes::{get_username, login::post::LoginError},
+    startup::AppState,
+    two_factor,
+    user_session::UserSession,
+};
+
+#[derive(Deserialize)]
+pub struct FormData {
+    code: Secret<String>,
+}
+
+/// Failed codes count against the same limits as failed passwords, as six
+/// digits would not take long to guess otherwise.
+#[instrument(skip_all, fields(uuid))]
+pub async fn login_two_factor(
+    state: State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    mut session: UserSession,
+    flash: Flash,
+    form: Form<FormData>,
+) -> Result<Response, LoginError> {
+    let Some(user_id) = session.pending_two_factor() else {
+        return Ok(Redirect::to("/login").into_response());
+    };
+    Span::current().record("uuid", debug(&user_id));
+    let retry = Redirect::to("/login/2fa");
+
+    let throttle = &state.login_throttle;
+    let ip = client_ip(&headers, peer, state.client_ip_header.as_deref());
+    let username = get_username(user_id, &state.db_pool)
+        .await
+        .wrap_and_log_err("Failed to query username")?;
+    if let Err(err) = throttle.reserve(&username, ip).await.log_err() {
+        let err = LoginError::from(err);
+        return Ok((flash.error(err.to_string()), retry).into_response());
+    }
+
+    let verified = match two_factor::verify(&state.db_pool, user_id, form.0.code.expose_secret())
+        .await
+        .log_err()
+    {
+        Ok(verified) => verified,
+        Err(e) => {
+            throttle.release(&username, ip).await.log_err()?;
+            return Err(e.into());
+        }
+    };
+    if !verified {
+        throttle.record_failure(&username, ip).await.log_err()?;
+        let err = LoginError::AuthError;
+        return Ok((flash.error(err.to_string()), retry).into_response());
+    }
+    throttle.record_success(&username, ip).await.log_err()?;
+    session.renew();
+    session.login(user_id);
+
+    Ok((session, Redirect::to("/admin/dashboard")).into_response())
+}
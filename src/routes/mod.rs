@@ -1,13 +1,23 @@
 mod admin;
+mod dev_mailbox;
 mod health_check;
 mod home;
+mod invites;
 mod login;
+mod password_forgot;
+mod password_reset;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_unsubscribe;
 
 pub use admin::*;
+pub use dev_mailbox::*;
 pub use health_check::*;
 pub use home::*;
+pub use invites::*;
 pub use login::*;
+pub use password_forgot::*;
+pub use password_reset::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_unsubscribe::{unsubscribe, unsubscribe_form};
@@ -0,0 +1,34 @@
+use axum::response::Html;
+use axum_flash::IncomingFlashes;
+use htmlescape::encode_minimal;
+use std::fmt::Write;
+use tracing::instrument;
+
+#[instrument(skip_all)]
+pub async fn forgot_password_form(flashes: IncomingFlashes) -> (IncomingFlashes, Html<String>) {
+    let mut msg_html = String::new();
+    for (_, msg) in flashes.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(msg)).unwrap();
+    }
+    let html = Html(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Forgot password</title>
+</head>
+<body>
+    {msg_html}
+    <form action="/password/forgot" method="post">
+        <label>Email
+            <input type="email" placeholder="Enter the email of your account" name="email">
+        </label>
+        <button type="submit">Send reset link</button>
+    </form>
+    <p><a href="/login">&lt;- Back to login</a></p>
+</body>
+</html>"#,
+    ));
+
+    (flashes, html)
+}
@@ -0,0 +1,135 @@
+use axum::{
+    extract::State,
+    response::{IntoResponse, Redirect, Response},
+    Form,
+};
+use axum_flash::Flash;
+use hyper::StatusCode;
+use serde::Deserialize;
+use sqlx::PgPool;
+use tracing::Instrument;
+use url::Url;
+use uuid::Uuid;
+
+use crate::{
+    domain::{OneTimeToken, SubscriberEmail},
+    email_client::EmailClient,
+    log::WrapAndLogErr,
+    startup::AppState,
+};
+
+#[derive(Deserialize)]
+pub struct FormData {
+    email: String,
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error(transparent)]
+pub struct ForgotPasswordError(#[from] anyhow::Error);
+
+impl IntoResponse for ForgotPasswordError {
+    fn into_response(self) -> Response {
+        (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()).into_response()
+    }
+}
+
+/// Responds the same way whether or not an account uses the email address,
+/// so that the form cannot be used to find out who the admins are.
+#[tracing::instrument(skip_all)]
+pub async fn forgot_password(
+    state: State<AppState>,
+    flash: Flash,
+    form: Form<FormData>,
+) -> Result<(Flash, Redirect), ForgotPasswordError> {
+    if let Ok(email) = SubscriberEmail::parse(form.0.email) {
+        if let Some(user_id) = get_user_id(&state.db_pool, &email)
+            .await
+            .wrap_and_log_err("Failed to look up user by email")?
+        {
+            let reset_token = OneTimeToken::generate();
+            store_reset_token(&state.db_pool, &reset_token, user_id)
+                .await
+                .wrap_and_log_err("Failed to store password reset token")?;
+            // Sent in the background, as waiting for the email API would
+            // make the response slower only when the account exists
+            let email_client = state.email_client.clone();
+            let base_url = state.base_url.clone();
+            tokio::spawn(
+                async move {
+                    let _ = send_reset_email(&email_client, &email, &base_url, &reset_token).await;
+                }
+                .in_current_span(),
+            );
+        }
+    }
+
+    Ok((
+        flash.info(
+            "If an account uses that email address, \
+            a link to reset its password is on its way.",
+        ),
+        Redirect::to("/password/forgot"),
+    ))
+}
+
+async fn get_user_id(
+    db_pool: &PgPool,
+    email: &SubscriberEmail,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let user = sqlx::query!(
+        r#"SELECT user_id FROM users WHERE email = $1"#,
+        email.as_ref()
+    )
+    .fetch_optional(db_pool)
+    .await?;
+
+    Ok(user.map(|user| user.user_id))
+}
+
+#[tracing::instrument(skip_all)]
+async fn store_reset_token(
+    db_pool: &PgPool,
+    reset_token: &OneTimeToken,
+    user_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO password_reset_tokens (reset_token_hash, user_id)
+        VALUES ($1, $2)
+        "#,
+        reset_token.hash(),
+        user_id
+    )
+    .execute(db_pool)
+    .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn send_reset_email(
+    email_client: &EmailClient,
+    recipient: &SubscriberEmail,
+    base_url: &Url,
+    reset_token: &OneTimeToken,
+) -> Result<(), anyhow::Error> {
+    let reset_link = base_url
+        .join(&format!("password/reset?token={}", reset_token.as_ref()))
+        .unwrap();
+    let html_body = format!(
+        "Someone asked to reset the password of your newsletter admin account.<br />\
+        Click <a href=\"{reset_link}\">here</a> to choose a new password.<br />\
+        If it was not you, you can ignore this email.",
+    );
+    let text_body = format!(
+        "Someone asked to reset the password of your newsletter admin account.\n\
+        Visit {reset_link} to choose a new password.\n\
+        If it was not you, you can ignore this email.",
+    );
+    email_client
+        .send(recipient, "Reset your password", &html_body, &text_body)
+        .await
+        .wrap_and_log_err("Failed to send password reset email")?;
+
+    Ok(())
+}
@@ -0,0 +1,62 @@
+use axum::{
+    extract::{Query, State},
+    response::Html,
+};
+use axum_flash::IncomingFlashes;
+use htmlescape::encode_minimal;
+use serde::Deserialize;
+use std::fmt::Write;
+
+use super::{get_reset_request, ResetLinkError};
+use crate::{domain::OneTimeToken, startup::AppState};
+
+#[derive(Deserialize)]
+pub struct Params {
+    token: String,
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn reset_password_form(
+    state: State<AppState>,
+    params: Query<Params>,
+    flashes: IncomingFlashes,
+) -> Result<(IncomingFlashes, Html<String>), ResetLinkError> {
+    let reset_token = OneTimeToken::from(params.0.token);
+    get_reset_request(&state.db_pool, &reset_token, state.password_reset_ttl).await?;
+
+    let mut msg_html = String::new();
+    for (_, msg) in flashes.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(msg)).unwrap();
+    }
+    let html = Html(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Reset password</title>
+</head>
+<body>
+    {msg_html}
+    <form action="/password/reset" method="post">
+        <label>New password
+            <input type="password" placeholder="Enter new password" name="new_password">
+        </label>
+        <br>
+        <label>Confirm new password
+            <input
+                type="password"
+                placeholder="Type the new password again"
+                name="new_password_check"
+            >
+        </label>
+        <br>
+        <input hidden type="text" name="token" value="{}">
+        <button type="submit">Reset password</button>
+    </form>
+</body>
+</html>"#,
+        encode_minimal(reset_token.as_ref()),
+    ));
+
+    Ok((flashes, html))
+}
@@ -0,0 +1,113 @@
+mod get;
+mod post;
+
+use std::time::Duration;
+
+use axum::response::{Html, IntoResponse, Response};
+use chrono::{DateTime, Utc};
+use hyper::StatusCode;
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+use crate::{
+    domain::{is_expired, OneTimeToken},
+    log::{LogErr, WrapAndLogErr},
+};
+
+pub use get::reset_password_form;
+pub use post::reset_password;
+
+struct ResetRequest {
+    user_id: Uuid,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ResetLinkError {
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+    #[error("This password reset link is invalid or has already been used")]
+    UnknownToken,
+    #[error("This password reset link has expired")]
+    ExpiredToken,
+}
+
+impl IntoResponse for ResetLinkError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            ResetLinkError::UnknownToken => StatusCode::UNAUTHORIZED,
+            ResetLinkError::ExpiredToken => StatusCode::GONE,
+            ResetLinkError::UnexpectedError(_) => {
+                return (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()).into_response()
+            }
+        };
+        (
+            status,
+            Html(format!(
+                r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Reset link unavailable</title>
+</head>
+<body>
+    <p>{self}. You can <a href="/password/forgot">ask for a new one</a>.</p>
+</body>
+</html>"#
+            )),
+        )
+            .into_response()
+    }
+}
+
+async fn get_reset_request(
+    db_pool: &PgPool,
+    reset_token: &OneTimeToken,
+    ttl: Duration,
+) -> Result<ResetRequest, ResetLinkError> {
+    let request = sqlx::query_as!(
+        ResetRequest,
+        r#"SELECT user_id, created_at FROM password_reset_tokens WHERE reset_token_hash = $1"#,
+        reset_token.hash()
+    )
+    .fetch_optional(db_pool)
+    .await
+    .wrap_and_log_err("Failed to query password reset token")?;
+
+    check_reset_request(request, ttl)
+}
+
+/// Deletes the token so that the link cannot be used again. This only sticks
+/// if the surrounding transaction commits.
+async fn take_reset_request(
+    executor: impl PgExecutor<'_>,
+    reset_token: &OneTimeToken,
+    ttl: Duration,
+) -> Result<ResetRequest, ResetLinkError> {
+    let request = sqlx::query_as!(
+        ResetRequest,
+        r#"
+        DELETE FROM password_reset_tokens
+        WHERE reset_token_hash = $1
+        RETURNING user_id, created_at
+        "#,
+        reset_token.hash()
+    )
+    .fetch_optional(executor)
+    .await
+    .wrap_and_log_err("Failed to take password reset token")?;
+
+    check_reset_request(request, ttl)
+}
+
+fn check_reset_request(
+    request: Option<ResetRequest>,
+    ttl: Duration,
+) -> Result<ResetRequest, ResetLinkError> {
+    let request = request.ok_or(ResetLinkError::UnknownToken).log_err()?;
+    if is_expired(request.created_at, ttl) {
+        return Err(ResetLinkError::ExpiredToken).log_err();
+    }
+
+    Ok(request)
+}
@@ -0,0 +1,95 @@
+use axum::{
+    extract::State,
+    response::{IntoResponse, Redirect, Response},
+    Form,
+};
+use axum_flash::Flash;
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+use tracing::{field::debug, Span};
+use url::form_urlencoded;
+
+use super::{take_reset_request, ResetLinkError};
+use crate::{
+    authentication::{change_password, check_password_rules},
+    domain::OneTimeToken,
+    log::WrapAndLogErr,
+    startup::AppState,
+    user_session::revoke_sessions,
+};
+
+#[derive(Deserialize)]
+pub struct FormData {
+    token: String,
+    new_password: Secret<String>,
+    new_password_check: Secret<String>,
+}
+
+#[tracing::instrument(skip_all, fields(uuid))]
+pub async fn reset_password(
+    state: State<AppState>,
+    flash: Flash,
+    form: Form<FormData>,
+) -> Result<Response, ResetLinkError> {
+    let form = form.0;
+    let retry = Redirect::to(&format!(
+        "/password/reset?{}",
+        form_urlencoded::Serializer::new(String::new())
+            .append_pair("token", &form.token)
+            .finish()
+    ));
+    if form.new_password.expose_secret() != form.new_password_check.expose_secret() {
+        return Ok((
+            flash.error("You entered two different new passwords - the field values must match."),
+            retry,
+        )
+            .into_response());
+    }
+    if let Err(e) = check_password_rules(&form.new_password) {
+        return Ok((flash.error(e.to_string()), retry).into_response());
+    }
+
+    let mut transaction = state
+        .db_pool
+        .begin()
+        .await
+        .wrap_and_log_err("Failed to begin transaction")?;
+    let request = take_reset_request(
+        &mut transaction,
+        &OneTimeToken::from(form.token),
+        state.password_reset_ttl,
+    )
+    .await?;
+    Span::current().record("uuid", debug(&request.user_id));
+
+    change_password(
+        request.user_id,
+        form.new_password,
+        state.password_hashing,
+        &mut transaction,
+    )
+    .await
+    .wrap_and_log_err("Failed to change password")?;
+    // Whoever asked for the reset may have lost control of the account, so
+    // neither the other reset links nor the existing sessions stay valid
+    sqlx::query!(
+        r#"DELETE FROM password_reset_tokens WHERE user_id = $1"#,
+        request.user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .wrap_and_log_err("Failed to delete other password reset tokens")?;
+    revoke_sessions(&mut transaction, request.user_id)
+        .await
+        .wrap_and_log_err("Failed to revoke user sessions")?;
+    transaction
+        .commit()
+        .await
+        .wrap_and_log_err("Failed to commit transaction")?;
+
+    Ok((
+        flash.success("Your password has been reset - you can now log in."),
+        Redirect::to("/login"),
+    )
+        .into_response())
+}
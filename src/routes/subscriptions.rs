@@ -9,7 +9,7 @@ use hyper::StatusCode;
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use serde::Deserialize;
 use sqlx::{types::chrono::Utc, PgPool, Postgres, Transaction};
-use tracing::{error, field::debug, instrument, Span};
+use tracing::{field::debug, instrument, Span};
 use url::Url;
 use uuid::Uuid;
 
@@ -54,27 +54,44 @@ impl IntoResponse for SubscribeError {
     }
 }
 
+enum NextStep {
+    ConfirmSubscription {
+        subscriber_id: Uuid,
+        subscription_token: String,
+    },
+    AlreadySubscribed {
+        subscriber_id: Uuid,
+    },
+}
+
 #[instrument(skip_all, fields(name=form.name, email=form.email, uuid))]
 pub async fn subscribe(
     state: State<AppState>,
     form: Form<NewSubscriberForm>,
 ) -> Result<(), SubscribeError> {
-    let subscriber_id = Uuid::new_v4();
-    Span::current().record("uuid", debug(subscriber_id));
-
     let new_subscriber = NewSubscriber::try_from(form.0)
         .map_err(SubscribeError::ValidationError)
         .log_err()?;
-    let subscription_token =
-        add_subscriber(&state.db_pool, &new_subscriber, &subscriber_id).await?;
-
-    send_confirmation_email(
-        &state.email_client,
-        &new_subscriber,
-        &state.base_url,
-        &subscription_token,
-    )
-    .await?;
+
+    match add_subscriber(&state.db_pool, &new_subscriber).await? {
+        NextStep::ConfirmSubscription {
+            subscriber_id,
+            subscription_token,
+        } => {
+            Span::current().record("uuid", debug(subscriber_id));
+            send_confirmation_email(
+                &state.email_client,
+                &new_subscriber.email,
+                &state.base_url,
+                &subscription_token,
+            )
+            .await?;
+        }
+        NextStep::AlreadySubscribed { subscriber_id } => {
+            Span::current().record("uuid", debug(subscriber_id));
+            send_already_subscribed_email(&state.email_client, &new_subscriber.email).await?;
+        }
+    }
 
     Ok(())
 }
@@ -83,19 +100,47 @@ pub async fn subscribe(
 async fn add_subscriber(
     db_pool: &PgPool,
     new_subscriber: &NewSubscriber,
-    subscriber_id: &Uuid,
-) -> Result<String, SubscribeError> {
+) -> Result<NextStep, SubscribeError> {
     let mut transaction = db_pool
         .begin()
         .await
         .wrap_and_log_err("Failed to begin transaction")?;
 
-    store_subscriber(&mut transaction, subscriber_id, new_subscriber)
+    // Inserting first lets concurrent first subscriptions fall through to the
+    // existing subscriber rather than fail on the unique email
+    let subscriber_id = Uuid::new_v4();
+    let is_new = store_subscriber(&mut transaction, &subscriber_id, new_subscriber)
         .await
         .wrap_and_log_err("Failed to execute query")?;
+    let subscriber_id = if is_new {
+        subscriber_id
+    } else {
+        let existing_subscriber = get_subscriber(&mut transaction, &new_subscriber.email)
+            .await
+            .wrap_and_log_err("Failed to query existing subscriber")?;
+        match existing_subscriber {
+            None => {
+                return Err(anyhow::anyhow!(
+                    "The subscriber was deleted while subscribing"
+                ))
+                .log_err()
+                .map_err(SubscribeError::from);
+            }
+            Some((subscriber_id, status)) if status == "confirmed" => {
+                return Ok(NextStep::AlreadySubscribed { subscriber_id });
+            }
+            Some((subscriber_id, status)) if status == "unsubscribed" => {
+                restart_subscription(&mut transaction, &subscriber_id, new_subscriber)
+                    .await
+                    .wrap_and_log_err("Failed to restart subscription")?;
+                subscriber_id
+            }
+            Some((subscriber_id, _)) => subscriber_id,
+        }
+    };
 
     let subscription_token = generate_subscription_token();
-    store_token(&mut transaction, subscriber_id, &subscription_token)
+    store_token(&mut transaction, &subscriber_id, &subscription_token)
         .await
         .wrap_and_log_err("Failed to store subscription token")?;
 
@@ -104,13 +149,16 @@ async fn add_subscriber(
         .await
         .wrap_and_log_err("Failed to commit transaction")?;
 
-    Ok(subscription_token)
+    Ok(NextStep::ConfirmSubscription {
+        subscriber_id,
+        subscription_token,
+    })
 }
 
 #[instrument(skip_all)]
-async fn send_confirmation_email(
+pub async fn send_confirmation_email(
     email_client: &EmailClient,
-    new_subscriber: &NewSubscriber,
+    recipient: &SubscriberEmail,
     base_url: &Url,
     subscription_token: &str,
 ) -> Result<(), SubscribeError> {
@@ -126,21 +174,79 @@ async fn send_confirmation_email(
         "Welcome to out newsletter!\nVisit {confirmation_link} to confirm your subscription.",
     );
     email_client
-        .send(&new_subscriber.email, "Welcome!", &html_body, &text_body)
+        .send(recipient, "Welcome!", &html_body, &text_body)
         .await
         .wrap_and_log_err("Failed to send confirmation email")?;
     Ok(())
 }
 
-async fn store_subscriber(
+#[instrument(skip_all)]
+async fn send_already_subscribed_email(
+    email_client: &EmailClient,
+    recipient: &SubscriberEmail,
+) -> Result<(), SubscribeError> {
+    let html_body = "You are already subscribed to our newsletter!<br />\
+        If you did not try to subscribe again, you can safely ignore this email.";
+    let text_body = "You are already subscribed to our newsletter!\n\
+        If you did not try to subscribe again, you can safely ignore this email.";
+    email_client
+        .send(
+            recipient,
+            "You are already subscribed",
+            html_body,
+            text_body,
+        )
+        .await
+        .wrap_and_log_err("Failed to send already subscribed email")?;
+    Ok(())
+}
+
+async fn get_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &SubscriberEmail,
+) -> Result<Option<(Uuid, String)>, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"SELECT id, status FROM subscriptions WHERE email = $1 FOR UPDATE"#,
+        email.as_ref()
+    )
+    .fetch_optional(transaction)
+    .await?;
+
+    Ok(result.map(|r| (r.id, r.status)))
+}
+
+async fn restart_subscription(
     transaction: &mut Transaction<'_, Postgres>,
     subscriber_id: &Uuid,
     new_subscriber: &NewSubscriber,
 ) -> Result<(), sqlx::Error> {
     sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET name = $2, subscribed_at = $3, status = 'pending_confirmation'
+        WHERE id = $1
+        "#,
+        subscriber_id,
+        new_subscriber.name.as_ref(),
+        Utc::now()
+    )
+    .execute(transaction)
+    .await?;
+
+    Ok(())
+}
+
+/// Returns `false` if a subscriber with the same email already exists.
+async fn store_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: &Uuid,
+    new_subscriber: &NewSubscriber,
+) -> Result<bool, sqlx::Error> {
+    let inserted = sqlx::query!(
         r#"
         INSERT INTO subscriptions (id, email, name, subscribed_at, status)
         VALUES ($1, $2, $3, $4, 'pending_confirmation')
+        ON CONFLICT (email) DO NOTHING
         "#,
         subscriber_id,
         new_subscriber.email.as_ref(),
@@ -150,7 +256,7 @@ async fn store_subscriber(
     .execute(transaction)
     .await?;
 
-    Ok(())
+    Ok(inserted.rows_affected() == 1)
 }
 
 pub async fn store_token(
@@ -169,7 +275,7 @@ pub async fn store_token(
     Ok(())
 }
 
-fn generate_subscription_token() -> String {
+pub fn generate_subscription_token() -> String {
     let mut rng = thread_rng();
     iter::repeat_with(|| rng.sample(Alphanumeric))
         .map(char::from)
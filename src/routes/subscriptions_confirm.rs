@@ -1,15 +1,19 @@
 use axum::{
     extract::{Query, State},
-    response::{IntoResponse, Response},
+    response::{Html, IntoResponse, Response},
+    Form,
 };
+use chrono::{DateTime, Utc};
 use hyper::StatusCode;
 use serde::Deserialize;
-use sqlx::PgPool;
-use tracing::{error, field::debug, Span};
+use sqlx::{PgPool, Postgres, Transaction};
+use tracing::{field::debug, Span};
 use uuid::Uuid;
 
 use crate::{
+    domain::{is_expired, SubscriberEmail},
     log::{LogErr, WrapAndLogErr},
+    routes::{generate_subscription_token, send_confirmation_email, store_token},
     startup::AppState,
 };
 
@@ -24,15 +28,40 @@ pub enum ConfirmationError {
     UnexpectedError(#[from] anyhow::Error),
     #[error("There is no subscriber associated with the provided token")]
     UnknownToken,
+    #[error("The confirmation link has expired")]
+    ExpiredToken(String),
 }
 
 impl IntoResponse for ConfirmationError {
     fn into_response(self) -> Response {
-        let status = match self {
-            ConfirmationError::UnknownToken => StatusCode::UNAUTHORIZED,
-            ConfirmationError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
-        };
-        (status, self.to_string()).into_response()
+        match self {
+            ConfirmationError::ExpiredToken(subscription_token) => (
+                StatusCode::GONE,
+                Html(format!(
+                    r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Confirmation link expired</title>
+</head>
+<body>
+    <p>This confirmation link has expired.</p>
+    <form action="/subscriptions/confirm/resend" method="post">
+        <input hidden type="text" name="subscription_token" value="{subscription_token}">
+        <button type="submit">Send me a new link</button>
+    </form>
+</body>
+</html>"#
+                )),
+            )
+                .into_response(),
+            ConfirmationError::UnknownToken => {
+                (StatusCode::UNAUTHORIZED, self.to_string()).into_response()
+            }
+            ConfirmationError::UnexpectedError(_) => {
+                (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()).into_response()
+            }
+        }
     }
 }
 
@@ -41,42 +70,152 @@ pub async fn confirm(
     state: State<AppState>,
     params: Query<Params>,
 ) -> Result<(), ConfirmationError> {
-    let subscriber_id = get_subscriber_id(&state.db_pool, &params.subscription_token).await?;
+    let (subscriber_id, created_at) =
+        get_subscriber_id(&state.db_pool, &params.subscription_token).await?;
     Span::current().record("uuid", debug(subscriber_id));
 
+    if is_expired(created_at, state.subscription_token_ttl) {
+        return Err(ConfirmationError::ExpiredToken(params.0.subscription_token)).log_err();
+    }
+
     confirm_subscriber(&state.db_pool, subscriber_id).await?;
 
     Ok(())
 }
 
+#[tracing::instrument(skip_all, fields(token=form.subscription_token, uuid))]
+pub async fn resend_confirmation(
+    state: State<AppState>,
+    form: Form<Params>,
+) -> Result<Html<&'static str>, ConfirmationError> {
+    let (subscriber_id, _) = get_subscriber_id(&state.db_pool, &form.subscription_token).await?;
+    Span::current().record("uuid", debug(subscriber_id));
+
+    let email = match get_pending_subscriber_email(&state.db_pool, subscriber_id).await? {
+        Some(email) => SubscriberEmail::parse(email)
+            .map_err(anyhow::Error::msg)
+            .wrap_and_log_err("Stored subscriber email is invalid")?,
+        None => {
+            return Ok(Html(
+                r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Subscription confirmed</title>
+</head>
+<body>
+    <p>Your subscription does not need to be confirmed.</p>
+</body>
+</html>"#,
+            ))
+        }
+    };
+
+    let subscription_token = generate_subscription_token();
+    let mut transaction = state
+        .db_pool
+        .begin()
+        .await
+        .wrap_and_log_err("Failed to begin transaction")?;
+    delete_tokens(&mut transaction, subscriber_id)
+        .await
+        .wrap_and_log_err("Failed to delete old subscription tokens")?;
+    store_token(&mut transaction, &subscriber_id, &subscription_token)
+        .await
+        .wrap_and_log_err("Failed to store subscription token")?;
+    transaction
+        .commit()
+        .await
+        .wrap_and_log_err("Failed to commit transaction")?;
+
+    send_confirmation_email(
+        &state.email_client,
+        &email,
+        &state.base_url,
+        &subscription_token,
+    )
+    .await
+    .map_err(anyhow::Error::from)?;
+
+    Ok(Html(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Confirmation link sent</title>
+</head>
+<body>
+    <p>We have sent you a new confirmation link - check your inbox.</p>
+</body>
+</html>"#,
+    ))
+}
+
 #[tracing::instrument(skip_all)]
 async fn get_subscriber_id(
     db_pool: &PgPool,
     subscription_token: &str,
-) -> Result<Uuid, ConfirmationError> {
+) -> Result<(Uuid, DateTime<Utc>), ConfirmationError> {
     let result = sqlx::query!(
-        r#"SELECT subscriber_id FROM subscription_tokens WHERE subscription_token = $1"#,
+        r#"SELECT subscriber_id, created_at FROM subscription_tokens WHERE subscription_token = $1"#,
         subscription_token
     )
     .fetch_optional(db_pool)
     .await
     .wrap_and_log_err("Failed to retrieve subscriber id with the provided token")?;
 
-    let subscriber_id = result
-        .map(|r| r.subscriber_id)
+    let subscriber = result
+        .map(|r| (r.subscriber_id, r.created_at))
         .ok_or(ConfirmationError::UnknownToken)
         .log_err()?;
 
-    Ok(subscriber_id)
+    Ok(subscriber)
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_pending_subscriber_email(
+    db_pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<String>, ConfirmationError> {
+    let result = sqlx::query!(
+        r#"SELECT email FROM subscriptions WHERE id = $1 AND status = 'pending_confirmation'"#,
+        subscriber_id
+    )
+    .fetch_optional(db_pool)
+    .await
+    .wrap_and_log_err("Failed to retrieve pending subscriber")?;
+
+    Ok(result.map(|r| r.email))
+}
+
+/// Sending a new link invalidates the old ones, so only the latest link works.
+#[tracing::instrument(skip_all)]
+async fn delete_tokens(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(transaction)
+    .await?;
+
+    Ok(())
 }
 
+/// Only pending subscribers are confirmed, so that an old confirmation link
+/// cannot undo unsubscribing.
 #[tracing::instrument(skip_all)]
 async fn confirm_subscriber(
     db_pool: &PgPool,
     subscriber_id: Uuid,
 ) -> Result<(), ConfirmationError> {
     sqlx::query!(
-        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
+        r#"
+        UPDATE subscriptions SET status = 'confirmed'
+        WHERE id = $1 AND status = 'pending_confirmation'
+        "#,
         subscriber_id
     )
     .execute(db_pool)
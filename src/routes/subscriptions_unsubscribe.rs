@@ -0,0 +1,131 @@
+use axum::{
+    extract::{Query, State},
+    response::{Html, IntoResponse, Response},
+};
+use hyper::StatusCode;
+use serde::Deserialize;
+use sqlx::PgPool;
+use tracing::{field::debug, Span};
+use uuid::Uuid;
+
+use crate::{
+    domain::UnsubscribeToken,
+    log::{LogErr, WrapAndLogErr},
+    startup::AppState,
+};
+
+#[derive(Deserialize)]
+pub struct Params {
+    token: String,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum UnsubscribeError {
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+    #[error("There is no subscriber associated with the provided token")]
+    UnknownToken,
+}
+
+impl IntoResponse for UnsubscribeError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            UnsubscribeError::UnknownToken => StatusCode::UNAUTHORIZED,
+            UnsubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        (status, self.to_string()).into_response()
+    }
+}
+
+#[tracing::instrument(skip_all, fields(uuid))]
+pub async fn unsubscribe_form(
+    state: State<AppState>,
+    params: Query<Params>,
+) -> Result<Html<String>, UnsubscribeError> {
+    let token = parse_token(&state, params.0)?;
+    Span::current().record("uuid", debug(token.subscriber_id()));
+
+    if !subscriber_exists(&state.db_pool, token.subscriber_id()).await? {
+        return Err(UnsubscribeError::UnknownToken).log_err();
+    }
+
+    Ok(Html(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Unsubscribe</title>
+</head>
+<body>
+    <p>Do you want to stop receiving our newsletter?</p>
+    <form action="/subscriptions/unsubscribe?token={token}" method="post">
+        <button type="submit">Unsubscribe</button>
+    </form>
+</body>
+</html>"#
+    )))
+}
+
+#[tracing::instrument(skip_all, fields(uuid))]
+pub async fn unsubscribe(
+    state: State<AppState>,
+    params: Query<Params>,
+) -> Result<Html<&'static str>, UnsubscribeError> {
+    let token = parse_token(&state, params.0)?;
+    Span::current().record("uuid", debug(token.subscriber_id()));
+
+    if !unsubscribe_subscriber(&state.db_pool, token.subscriber_id()).await? {
+        return Err(UnsubscribeError::UnknownToken).log_err();
+    }
+
+    Ok(Html(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Unsubscribed</title>
+</head>
+<body>
+    <p>You have been unsubscribed. You will not receive any further issues.</p>
+</body>
+</html>"#,
+    ))
+}
+
+fn parse_token(state: &AppState, params: Params) -> Result<UnsubscribeToken, UnsubscribeError> {
+    UnsubscribeToken::parse(params.token, &state.hmac_secret)
+        .log_err()
+        .map_err(|_| UnsubscribeError::UnknownToken)
+}
+
+#[tracing::instrument(skip_all)]
+async fn subscriber_exists(
+    db_pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<bool, UnsubscribeError> {
+    let result = sqlx::query!(
+        r#"SELECT id FROM subscriptions WHERE id = $1"#,
+        subscriber_id
+    )
+    .fetch_optional(db_pool)
+    .await
+    .wrap_and_log_err("Failed to retrieve subscriber with the provided token")?;
+
+    Ok(result.is_some())
+}
+
+#[tracing::instrument(skip_all)]
+async fn unsubscribe_subscriber(
+    db_pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<bool, UnsubscribeError> {
+    let result = sqlx::query!(
+        r#"UPDATE subscriptions SET status = 'unsubscribed' WHERE id = $1"#,
+        subscriber_id
+    )
+    .execute(db_pool)
+    .await
+    .wrap_and_log_err("Failed to update the subscriber status to `unsubscribed`")?;
+
+    Ok(result.rows_affected() > 0)
+}
@@ -0,0 +1,225 @@
+use std::time::Duration;
+
+use async_redis_session::RedisSessionStore;
+use async_session::{MemoryStore, Session, SessionStore};
+use async_trait::async_trait;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+
+use crate::configuration::{SessionSettings, SessionStoreKind};
+
+/// The store picked by `session.store` in the configuration.
+#[derive(Clone, Debug)]
+pub enum AppSessionStore {
+    /// The client is shared with the store, to refresh expiries.
+    Redis(RedisSessionStore, redis::Client),
+    Postgres(PostgresSessionStore),
+    /// Sessions are lost on restart and cannot be shared between instances.
+    Memory(MemoryStore),
+}
+
+impl AppSessionStore {
+    pub fn new(
+        settings: &SessionSettings,
+        redis_uri: &Secret<String>,
+        db_pool: PgPool,
+    ) -> Result<Self, anyhow::Error> {
+        Ok(match settings.store {
+            SessionStoreKind::Redis => {
+                let client = redis::Client::open(redis_uri.expose_secret().as_str())?;
+                Self::Redis(RedisSessionStore::from_client(client.clone()), client)
+            }
+            SessionStoreKind::Postgres => Self::Postgres(PostgresSessionStore { db_pool }),
+            SessionStoreKind::Memory => Self::Memory(MemoryStore::new()),
+        })
+    }
+
+    /// Pushes back the expiry of a stored session to that of `session`,
+    /// without writing back its data. Concurrent requests would otherwise
+    /// overwrite each other's changes with the copy they loaded.
+    pub async fn refresh_expiry(&self, session: &Session) -> async_session::Result {
+        match self {
+            Self::Redis(_, client) => {
+                let mut connection = client.get_async_connection().await?;
+                let ttl = session.expires_in().unwrap_or_default().as_secs().max(1);
+                redis::cmd("EXPIRE")
+                    .arg(session.id())
+                    .arg(ttl)
+                    .query_async::<_, ()>(&mut connection)
+                    .await?;
+            }
+            Self::Postgres(store) => {
+                sqlx::query!(
+                    r#"UPDATE sessions SET expires_at = $2 WHERE session_id = $1"#,
+                    session.id(),
+                    session.expiry()
+                )
+                .execute(&store.db_pool)
+                .await?;
+            }
+            // Loaded sessions share their data with the stored copy, so
+            // storing one again cannot write back stale data
+            Self::Memory(store) => {
+                store.store_session(session.clone()).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Frees the expired sessions of the memory store every minute, as
+    /// nothing else would. Redis expires sessions itself, and the worker
+    /// prunes the Postgres store with [`prune_expired_sessions`].
+    pub async fn prune_memory_loop(&self) -> ! {
+        loop {
+            if let Self::Memory(store) = self {
+                if let Err(e) = store.cleanup().await {
+                    tracing::error!(error = %e, "Failed to prune expired sessions from memory.");
+                }
+            }
+            tokio::time::sleep(Duration::from_secs(60)).await;
+        }
+    }
+}
+
+#[async_trait]
+impl SessionStore for AppSessionStore {
+    async fn load_session(&self, cookie_value: String) -> async_session::Result<Option<Session>> {
+        match self {
+            Self::Redis(store, _) => store.load_session(cookie_value).await,
+            Self::Postgres(store) => store.load_session(cookie_value).await,
+            Self::Memory(store) => store.load_session(cookie_value).await,
+        }
+    }
+
+    async fn store_session(&self, session: Session) -> async_session::Result<Option<String>> {
+        match self {
+            Self::Redis(store, _) => store.store_session(session).await,
+            Self::Postgres(store) => store.store_session(session).await,
+            Self::Memory(store) => store.store_session(session).await,
+        }
+    }
+
+    async fn destroy_session(&self, session: Session) -> async_session::Result {
+        match self {
+            Self::Redis(store, _) => store.destroy_session(session).await,
+            Self::Postgres(store) => store.destroy_session(session).await,
+            Self::Memory(store) => store.destroy_session(session).await,
+        }
+    }
+
+    async fn clear_store(&self) -> async_session::Result {
+        match self {
+            Self::Redis(store, _) => store.clear_store().await,
+            Self::Postgres(store) => store.clear_store().await,
+            Self::Memory(store) => store.clear_store().await,
+        }
+    }
+}
+
+/// Keeps sessions in the `sessions` table. Expired ones are ignored, and
+/// deleted by the background worker with [`prune_expired_sessions`].
+#[derive(Clone, Debug)]
+pub struct PostgresSessionStore {
+    db_pool: PgPool,
+}
+
+#[async_trait]
+impl SessionStore for PostgresSessionStore {
+    async fn load_session(&self, cookie_value: String) -> async_session::Result<Option<Session>> {
+        let session_id = Session::id_from_cookie_value(&cookie_value)?;
+        let record = sqlx::query!(
+            r#"
+            SELECT session, expires_at
+            FROM sessions
+            WHERE session_id = $1 AND (expires_at IS NULL OR expires_at > now())
+            "#,
+            session_id
+        )
+        .fetch_optional(&self.db_pool)
+        .await?;
+
+        let Some(record) = record else {
+            return Ok(None);
+        };
+        // The expiry may have been refreshed since the session was serialized
+        let mut session = serde_json::from_str::<Session>(&record.session)?;
+        if let Some(expires_at) = record.expires_at {
+            session.set_expiry(expires_at);
+        }
+        Ok(session.validate())
+    }
+
+    async fn store_session(&self, session: Session) -> async_session::Result<Option<String>> {
+        sqlx::query!(
+            r#"
+            INSERT INTO sessions (session_id, session, expires_at)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (session_id) DO UPDATE
+            SET session = EXCLUDED.session, expires_at = EXCLUDED.expires_at
+            "#,
+            session.id(),
+            serde_json::to_string(&session)?,
+            session.expiry()
+        )
+        .execute(&self.db_pool)
+        .await?;
+
+        Ok(session.into_cookie_value())
+    }
+
+    async fn destroy_session(&self, session: Session) -> async_session::Result {
+        sqlx::query!(
+            r#"DELETE FROM sessions WHERE session_id = $1"#,
+            session.id()
+        )
+        .execute(&self.db_pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn clear_store(&self) -> async_session::Result {
+        sqlx::query!(r#"DELETE FROM sessions"#)
+            .execute(&self.db_pool)
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn prune_expired_sessions(db_pool: &PgPool) -> Result<u64, sqlx::Error> {
+    Ok(
+        sqlx::query!(r#"DELETE FROM sessions WHERE expires_at < now()"#)
+            .execute(db_pool)
+            .await?
+            .rows_affected(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use async_session::{MemoryStore, Session, SessionStore};
+
+    use super::AppSessionStore;
+
+    #[tokio::test]
+    async fn expired_sessions_are_pruned_from_memory() {
+        let memory_store = MemoryStore::new();
+        let store = AppSessionStore::Memory(memory_store.clone());
+        let mut expired = Session::new();
+        expired.expire_in(Duration::ZERO);
+        let mut active = Session::new();
+        active.expire_in(Duration::from_secs(60));
+        store.store_session(expired).await.unwrap();
+        store.store_session(active).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        let _ = tokio::time::timeout(Duration::from_millis(50), store.prune_memory_loop()).await;
+
+        assert_eq!(memory_store.count().await, 1);
+    }
+}
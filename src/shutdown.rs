@@ -0,0 +1,27 @@
+use tokio::signal;
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+/// Cancels `shutdown` once the process receives SIGTERM or Ctrl-C.
+pub async fn cancel_on_signal(shutdown: CancellationToken) {
+    let ctrl_c = async {
+        signal::ctrl_c().await.expect("Failed to listen for Ctrl-C");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        signal::unix::signal(signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+        _ = shutdown.cancelled() => return,
+    }
+    info!("Shutdown signal received, finishing in-flight work.");
+    shutdown.cancel();
+}
@@ -1,33 +1,44 @@
-use std::{net::SocketAddr, time::Duration};
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
 
-use anyhow::Result;
-use async_redis_session::RedisSessionStore;
+use anyhow::{Context, Result};
 use axum::{
-    extract::FromRef,
-    middleware::from_fn_with_state,
+    extract::{connect_info::IntoMakeServiceWithConnectInfo, FromRef},
+    middleware::{from_fn, from_fn_with_state},
     routing::{get, post, IntoMakeService},
     Router, Server,
 };
 use axum_extra::extract::cookie::Key;
 use axum_flash::Config;
 use hyper::server::conn::AddrIncoming;
-use secrecy::ExposeSecret;
+use secrecy::{ExposeSecret, Secret};
 use sqlx::{postgres::PgPoolOptions, PgPool};
-
+use tokio_util::sync::CancellationToken;
+use tracing::warn;
 use url::Url;
 
 use crate::{
-    configuration::{DatabaseSettings, Settings},
+    configuration::{DatabaseSettings, PasswordHashingSettings, SessionSettings, Settings},
+    csrf::csrf_protection,
     email_client::EmailClient,
+    login_throttle::LoginThrottle,
     routes::{
-        admin_dashboard, change_password, change_password_form, confirm, health_check, home, login,
-        login_form, logout, publish_newsletter, publish_newsletter_form, subscribe,
+        accept_invite, accept_invite_form, admin_dashboard, change_password, change_password_form,
+        confirm, delivery_failures, dev_mailbox, dev_mailbox_message, enable_two_factor,
+        forgot_password, forgot_password_form, health_check, home, invite_user, invite_user_form,
+        log_out_everywhere, login, login_form, login_two_factor, login_two_factor_form, logout,
+        publish_newsletter, publish_newsletter_form, reenqueue_delivery_failures,
+        resend_confirmation, reset_password, reset_password_form, reset_two_factor,
+        reset_two_factor_form, revoke_session, sessions, subscribe, two_factor_form, unsubscribe,
+        unsubscribe_form, worker_health_check, WorkerHealth,
     },
-    user_session::redis_session,
+    session_store::AppSessionStore,
+    user_session::session_middleware,
 };
 
 pub struct App {
-    server: Server<AddrIncoming, IntoMakeService<Router>>,
+    server: Server<AddrIncoming, IntoMakeServiceWithConnectInfo<Router, SocketAddr>>,
+    session_store: AppSessionStore,
+    shutdown_timeout: Duration,
 }
 
 #[derive(Clone)]
@@ -35,6 +46,15 @@ pub struct AppState {
     pub db_pool: PgPool,
     pub email_client: EmailClient,
     pub base_url: Url,
+    pub hmac_secret: Secret<String>,
+    pub subscription_token_ttl: Duration,
+    pub invite_ttl: Duration,
+    pub password_reset_ttl: Duration,
+    pub login_throttle: LoginThrottle,
+    pub client_ip_header: Option<String>,
+    pub session_settings: SessionSettings,
+    pub password_hashing: PasswordHashingSettings,
+    pub dev_mailbox: Option<PathBuf>,
     flash_config: Config,
 }
 
@@ -44,49 +64,175 @@ impl FromRef<AppState> for Config {
     }
 }
 
+impl FromRef<AppState> for PgPool {
+    fn from_ref(state: &AppState) -> Self {
+        state.db_pool.clone()
+    }
+}
+
 #[derive(Clone)]
 pub struct SessionState {
-    pub redis_store: RedisSessionStore,
+    pub store: AppSessionStore,
+    pub db_pool: PgPool,
+    pub client_ip_header: Option<String>,
+    pub settings: SessionSettings,
     pub key: Key,
+    flash_config: Config,
+}
+
+impl FromRef<SessionState> for Config {
+    fn from_ref(state: &SessionState) -> Self {
+        state.flash_config.clone()
+    }
 }
 
 impl App {
     pub async fn build(config: &Settings) -> Result<Self> {
         let key = Key::from(config.application.hmac_secret.expose_secret().as_bytes());
+        config
+            .password_hashing
+            .params()
+            .context("Invalid password hashing settings")?;
+        let db_pool = get_connection_pool(&config.database);
         let app_state = AppState {
-            db_pool: get_connection_pool(&config.database),
+            login_throttle: LoginThrottle::new(
+                config.login_throttle.clone(),
+                &config.redis_uri,
+                db_pool.clone(),
+            )?,
+            db_pool,
+            client_ip_header: config.application.client_ip_header.clone(),
+            session_settings: config.session,
+            password_hashing: config.password_hashing,
             email_client: config.email_client.clone().client(),
             base_url: config.application.base_url.clone(),
+            hmac_secret: config.application.hmac_secret.clone(),
+            subscription_token_ttl: config.application.subscription_token_ttl,
+            invite_ttl: config.application.invite_ttl,
+            password_reset_ttl: config.application.password_reset_ttl,
+            dev_mailbox: config.email_client.dev_mailbox(),
             flash_config: Config::new(key.clone()),
         };
         let session_state = SessionState {
-            redis_store: RedisSessionStore::new(config.redis_uri.expose_secret().as_ref())?,
+            store: AppSessionStore::new(
+                &config.session,
+                &config.redis_uri,
+                app_state.db_pool.clone(),
+            )?,
+            db_pool: app_state.db_pool.clone(),
+            client_ip_header: app_state.client_ip_header.clone(),
+            settings: config.session,
+            flash_config: app_state.flash_config.clone(),
             key,
         };
-        let app = Router::new()
+        let mut router = Router::new()
             .route("/", get(home))
             .route("/admin/dashboard", get(admin_dashboard))
+            .route("/admin/deliveries/failures", get(delivery_failures))
+            .route(
+                "/admin/deliveries/failures",
+                post(reenqueue_delivery_failures),
+            )
             .route("/admin/newsletters", get(publish_newsletter_form))
             .route("/admin/newsletters", post(publish_newsletter))
             .route("/admin/password", get(change_password_form))
             .route("/admin/password", post(change_password))
+            .route("/admin/2fa", get(two_factor_form))
+            .route("/admin/2fa", post(enable_two_factor))
+            .route("/admin/users/2fa", get(reset_two_factor_form))
+            .route("/admin/users/2fa", post(reset_two_factor))
+            .route("/admin/sessions", get(sessions))
+            .route("/admin/sessions/revoke", post(revoke_session))
+            .route("/admin/sessions/revoke_all", post(log_out_everywhere))
+            .route("/admin/users/invite", get(invite_user_form))
+            .route("/admin/users/invite", post(invite_user))
             .route("/admin/logout", post(logout))
             .route("/health_check", get(health_check))
+            .route("/invites/accept", get(accept_invite_form))
+            .route("/invites/accept", post(accept_invite))
             .route("/login", get(login_form))
             .route("/login", post(login))
+            .route("/login/2fa", get(login_two_factor_form))
+            .route("/login/2fa", post(login_two_factor))
+            .route("/password/forgot", get(forgot_password_form))
+            .route("/password/forgot", post(forgot_password))
+            .route("/password/reset", get(reset_password_form))
+            .route("/password/reset", post(reset_password))
             .route("/subscriptions", post(subscribe))
             .route("/subscriptions/confirm", get(confirm))
-            .route_layer(from_fn_with_state(session_state, redis_session))
+            .route("/subscriptions/confirm/resend", post(resend_confirmation))
+            .route("/subscriptions/unsubscribe", get(unsubscribe_form))
+            .route("/subscriptions/unsubscribe", post(unsubscribe));
+        if app_state.dev_mailbox.is_some() {
+            router = router
+                .route("/dev/mailbox", get(dev_mailbox))
+                .route("/dev/mailbox/:file_name", get(dev_mailbox_message));
+        }
+        let session_store = session_state.store.clone();
+        let app = router
+            .route_layer(from_fn(csrf_protection))
+            .route_layer(from_fn_with_state(session_state, session_middleware))
             .with_state(app_state);
 
         let address = format!("{}:{}", config.application.host, config.application.port);
-        let server = Server::bind(&address.parse().unwrap()).serve(app.into_make_service());
+        let server = Server::bind(&address.parse().unwrap())
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>());
+
+        Ok(Self {
+            server,
+            session_store,
+            shutdown_timeout: config.application.shutdown_timeout,
+        })
+    }
+
+    /// Serves requests until `shutdown` is cancelled, then stops accepting
+    /// connections and gives in-flight requests `shutdown_timeout` to complete.
+    pub async fn run_until_stopped(self, shutdown: CancellationToken) -> Result<()> {
+        let signal = shutdown.clone();
+        let server = self
+            .server
+            .with_graceful_shutdown(async move { signal.cancelled().await });
+        tokio::pin!(server);
+
+        tokio::select! {
+            result = &mut server => return Ok(result?),
+            _ = shutdown.cancelled() => {},
+            _ = self.session_store.prune_memory_loop() => {},
+        }
+        match tokio::time::timeout(self.shutdown_timeout, server).await {
+            Ok(result) => result?,
+            Err(_) => warn!("Dropping connections still open after the shutdown timeout."),
+        }
+
+        Ok(())
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.server.local_addr()
+    }
+}
+
+/// Serves `GET /health_check` on its own for processes that only run the
+/// delivery worker.
+pub struct HealthCheckServer {
+    server: Server<AddrIncoming, IntoMakeService<Router>>,
+}
+
+impl HealthCheckServer {
+    pub fn build(host: &str, port: u16, health: WorkerHealth) -> Result<Self> {
+        let router = Router::new()
+            .route("/health_check", get(worker_health_check))
+            .with_state(health);
+        let address = format!("{host}:{port}");
+        let server = Server::try_bind(&address.parse()?)?.serve(router.into_make_service());
 
         Ok(Self { server })
     }
 
-    pub async fn run(self) -> Result<()> {
-        self.server.await?;
+    pub async fn run_until_stopped(self, shutdown: CancellationToken) -> Result<()> {
+        self.server
+            .with_graceful_shutdown(async move { shutdown.cancelled().await })
+            .await?;
 
         Ok(())
     }
@@ -96,6 +242,14 @@ impl App {
     }
 }
 
+/// Applies any pending database migrations.
+pub async fn migrate(config: &DatabaseSettings) -> Result<()> {
+    let db_pool = get_connection_pool(config);
+    sqlx::migrate!("./migrations").run(&db_pool).await?;
+
+    Ok(())
+}
+
 pub fn get_connection_pool(config: &DatabaseSettings) -> PgPool {
     PgPoolOptions::new()
         .acquire_timeout(Duration::from_secs(10))
@@ -0,0 +1,161 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use secrecy::ExposeSecret;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::domain::{RecoveryCode, TotpSecret};
+
+const N_RECOVERY_CODES: usize = 10;
+
+#[tracing::instrument(skip(db_pool))]
+pub async fn is_enabled(db_pool: &PgPool, user_id: Uuid) -> Result<bool, anyhow::Error> {
+    let user = sqlx::query!(
+        r#"SELECT totp_secret IS NOT NULL AS "enabled!" FROM users WHERE user_id = $1"#,
+        user_id
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed to query two-factor status")?;
+
+    Ok(user.is_some_and(|r| r.enabled))
+}
+
+/// Turns on two-factor authentication once the user has proven their
+/// authenticator works by sending `code`. Returns the recovery codes, which
+/// cannot be shown again, or `None` if the code is wrong or two-factor
+/// authentication is already on.
+#[tracing::instrument(skip_all, fields(uuid=?user_id))]
+pub async fn enable(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    secret: &TotpSecret,
+    code: &str,
+) -> Result<Option<Vec<RecoveryCode>>, anyhow::Error> {
+    let Some(step) = secret.verify(code, unix_time(), None) else {
+        return Ok(None);
+    };
+    let updated = sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_secret = $1, totp_last_used_step = $2
+        WHERE user_id = $3 AND totp_secret IS NULL
+        "#,
+        secret.expose_secret(),
+        step,
+        user_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to store TOTP secret")?;
+    if updated.rows_affected() == 0 {
+        return Ok(None);
+    }
+
+    let recovery_codes: Vec<_> = (0..N_RECOVERY_CODES)
+        .map(|_| RecoveryCode::generate())
+        .collect();
+    for recovery_code in &recovery_codes {
+        sqlx::query!(
+            r#"INSERT INTO totp_recovery_codes (user_id, code_hash) VALUES ($1, $2)"#,
+            user_id,
+            recovery_code.hash()
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to store recovery code")?;
+    }
+
+    Ok(Some(recovery_codes))
+}
+
+/// Checks the second step of a login, which takes either the current TOTP
+/// code or one of the recovery codes. Either can only be used once.
+#[tracing::instrument(skip(db_pool, code))]
+pub async fn verify(db_pool: &PgPool, user_id: Uuid, code: &str) -> Result<bool, anyhow::Error> {
+    let user = sqlx::query!(
+        r#"SELECT totp_secret, totp_last_used_step FROM users WHERE user_id = $1"#,
+        user_id
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed to query TOTP secret")?;
+    let Some((Some(secret), last_used_step)) = user.map(|r| (r.totp_secret, r.totp_last_used_step))
+    else {
+        return Ok(false);
+    };
+    let secret = TotpSecret::parse(secret)
+        .map_err(anyhow::Error::msg)
+        .context("Stored TOTP secret is invalid")?;
+
+    if let Some(step) = secret.verify(code, unix_time(), last_used_step) {
+        // Concurrent logins with the same code race for the step
+        let updated = sqlx::query!(
+            r#"
+            UPDATE users
+            SET totp_last_used_step = $1
+            WHERE user_id = $2
+                AND (totp_last_used_step IS NULL OR totp_last_used_step < $1)
+            "#,
+            step,
+            user_id
+        )
+        .execute(db_pool)
+        .await
+        .context("Failed to record used TOTP step")?;
+        return Ok(updated.rows_affected() == 1);
+    }
+
+    let used = sqlx::query!(
+        r#"DELETE FROM totp_recovery_codes WHERE user_id = $1 AND code_hash = $2"#,
+        user_id,
+        RecoveryCode::from(code.to_string()).hash()
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed to use recovery code")?;
+
+    Ok(used.rows_affected() == 1)
+}
+
+/// Turns off two-factor authentication, e.g. for a user who lost both their
+/// authenticator and their recovery codes.
+#[tracing::instrument(skip(db_pool))]
+pub async fn disable(db_pool: &PgPool, user_id: Uuid) -> Result<(), anyhow::Error> {
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to begin transaction")?;
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_secret = NULL, totp_last_used_step = NULL
+        WHERE user_id = $1
+        "#,
+        user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to clear TOTP secret")?;
+    sqlx::query!(
+        r#"DELETE FROM totp_recovery_codes WHERE user_id = $1"#,
+        user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete recovery codes")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit transaction")?;
+
+    Ok(())
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("The clock is set after 1970")
+        .as_secs()
+}
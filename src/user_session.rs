@@ -1,30 +1,44 @@
 use std::{
     convert::Infallible,
+    marker::PhantomData,
+    net::{IpAddr, SocketAddr},
     ops::{Deref, DerefMut},
+    time::Duration,
 };
 
 use async_session::{Session, SessionStore};
 use async_trait::async_trait;
-use axum::http::{Request, StatusCode};
+use axum::http::{header::USER_AGENT, Request, StatusCode};
 use axum::{
-    extract::{FromRequestParts, State},
+    extract::{ConnectInfo, FromRef, FromRequestParts, State},
     http::request::Parts,
     middleware::Next,
-    response::{IntoResponse, IntoResponseParts, Redirect, Response, ResponseParts},
+    response::{Html, IntoResponse, IntoResponseParts, Redirect, Response, ResponseParts},
     Extension,
 };
 use axum_extra::extract::{
     cookie::{Cookie, SameSite},
     SignedCookieJar,
 };
+use axum_flash::Flash;
+use chrono::{DateTime, Utc};
+use secrecy::ExposeSecret;
+use sqlx::{PgExecutor, PgPool};
+use subtle::ConstantTimeEq;
 use uuid::Uuid;
 
 use crate::{
-    log::{OkOrWrapAndLog, WrapAndLogErr},
+    client_ip::client_ip,
+    configuration::SessionSettings,
+    domain::{OneTimeToken, Role, TotpSecret},
+    log::{LogErr, WrapAndLogErr},
     startup::SessionState,
 };
 
 const USER_ID_KEY: &str = "user_id";
+const PENDING_TWO_FACTOR_KEY: &str = "pending_two_factor_user_id";
+const TOTP_ENROLLMENT_KEY: &str = "totp_enrollment_secret";
+const CSRF_TOKEN_KEY: &str = "csrf_token";
 const SESSION_COOKIE_KEY: &str = "session_id";
 
 pub struct UserSession(Session);
@@ -35,9 +49,38 @@ impl UserSession {
     }
 
     pub fn login(&mut self, user_id: Uuid) {
+        self.0.remove(PENDING_TWO_FACTOR_KEY);
         self.0.insert(USER_ID_KEY, user_id).unwrap()
     }
 
+    /// Records that `user_id` got their password right but still has to
+    /// send a second factor. This does not log them in.
+    pub fn start_two_factor(&mut self, user_id: Uuid) {
+        self.0.insert(PENDING_TWO_FACTOR_KEY, user_id).unwrap()
+    }
+
+    pub fn pending_two_factor(&self) -> Option<Uuid> {
+        self.0.get(PENDING_TWO_FACTOR_KEY)
+    }
+
+    /// Keeps the secret shown during enrollment until the user confirms it
+    /// with a code from their authenticator.
+    pub fn set_totp_enrollment(&mut self, secret: &TotpSecret) {
+        self.0
+            .insert(TOTP_ENROLLMENT_KEY, secret.expose_secret())
+            .unwrap()
+    }
+
+    pub fn totp_enrollment(&self) -> Option<TotpSecret> {
+        self.0
+            .get::<String>(TOTP_ENROLLMENT_KEY)
+            .and_then(|secret| TotpSecret::parse(secret).ok())
+    }
+
+    pub fn clear_totp_enrollment(&mut self) {
+        self.0.remove(TOTP_ENROLLMENT_KEY)
+    }
+
     pub fn logout(&mut self) {
         self.0.destroy()
     }
@@ -45,6 +88,27 @@ impl UserSession {
     pub fn user_id(&self) -> Option<Uuid> {
         self.0.get(USER_ID_KEY)
     }
+
+    pub fn id(&self) -> &str {
+        self.0.id()
+    }
+
+    /// The token forms send back to show they were served by this site for
+    /// this session. It is created the first time a form asks for it.
+    pub fn csrf_token(&mut self) -> String {
+        if let Some(token) = self.0.get(CSRF_TOKEN_KEY) {
+            return token;
+        }
+        let token = OneTimeToken::generate().as_ref().to_string();
+        self.0.insert(CSRF_TOKEN_KEY, &token).unwrap();
+        token
+    }
+
+    pub fn verify_csrf_token(&self, token: &str) -> bool {
+        self.0
+            .get::<String>(CSRF_TOKEN_KEY)
+            .is_some_and(|expected| bool::from(expected.as_bytes().ct_eq(token.as_bytes())))
+    }
 }
 
 #[async_trait]
@@ -58,7 +122,7 @@ where
         Ok(Self(
             Extension::from_request_parts(parts, state)
                 .await
-                .expect("redis_session layer is not installed")
+                .expect("session_middleware layer is not installed")
                 .0,
         ))
     }
@@ -108,6 +172,124 @@ where
     }
 }
 
+/// The least privileged role allowed through an [`Authorized`] extractor.
+pub trait Permission: Send + Sync {
+    const MINIMUM_ROLE: Role;
+}
+
+/// Reading stats, such as delivery failures.
+pub struct CanView;
+
+/// Drafting and publishing newsletters.
+pub struct CanPublish;
+
+/// Managing other admin users.
+pub struct CanManageUsers;
+
+impl Permission for CanView {
+    const MINIMUM_ROLE: Role = Role::Viewer;
+}
+
+impl Permission for CanPublish {
+    const MINIMUM_ROLE: Role = Role::Editor;
+}
+
+impl Permission for CanManageUsers {
+    const MINIMUM_ROLE: Role = Role::Owner;
+}
+
+/// A logged-in user whose role grants permission `P`.
+pub struct Authorized<P> {
+    pub user_id: UserId,
+    pub role: Role,
+    permission: PhantomData<P>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum AuthorizationError {
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+    #[error("Not logged in")]
+    NotLoggedIn,
+    #[error("The {0} role is required")]
+    Forbidden(Role),
+}
+
+impl IntoResponse for AuthorizationError {
+    fn into_response(self) -> Response {
+        match self {
+            AuthorizationError::NotLoggedIn => Redirect::to("/login").into_response(),
+            AuthorizationError::Forbidden(role) => (
+                StatusCode::FORBIDDEN,
+                Html(format!(
+                    r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Forbidden</title>
+</head>
+<body>
+    <p>You do not have permission to do this. Ask an owner for the {role} role.</p>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#
+                )),
+            )
+                .into_response(),
+            AuthorizationError::UnexpectedError(_) => {
+                (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()).into_response()
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<S, P> FromRequestParts<S> for Authorized<P>
+where
+    PgPool: FromRef<S>,
+    S: Send + Sync,
+    P: Permission,
+{
+    type Rejection = AuthorizationError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let user_id = UserId::from_request_parts(parts, state)
+            .await
+            .map_err(|_| AuthorizationError::NotLoggedIn)?;
+        let db_pool = PgPool::from_ref(state);
+        // The user may have been deleted since they logged in
+        let role = get_role(*user_id, &db_pool)
+            .await?
+            .ok_or(AuthorizationError::NotLoggedIn)?;
+
+        if role < P::MINIMUM_ROLE {
+            return Err(AuthorizationError::Forbidden(P::MINIMUM_ROLE)).log_err();
+        }
+        Ok(Self {
+            user_id,
+            role,
+            permission: PhantomData,
+        })
+    }
+}
+
+#[tracing::instrument(skip(db_pool))]
+async fn get_role(user_id: Uuid, db_pool: &PgPool) -> Result<Option<Role>, AuthorizationError> {
+    let user = sqlx::query!(r#"SELECT role FROM users WHERE user_id = $1"#, user_id)
+        .fetch_optional(db_pool)
+        .await
+        .wrap_and_log_err("Failed to query user role")?;
+
+    match user {
+        Some(user) => Ok(Some(
+            Role::parse(user.role)
+                .map_err(anyhow::Error::msg)
+                .wrap_and_log_err("Stored user role is invalid")?,
+        )),
+        None => Ok(None),
+    }
+}
+
 #[derive(thiserror::Error, Debug)]
 #[error(transparent)]
 pub struct SessionError(#[from] anyhow::Error);
@@ -118,56 +300,477 @@ impl IntoResponse for SessionError {
     }
 }
 
-pub async fn redis_session<B>(
+pub async fn session_middleware<B>(
     state: State<SessionState>,
+    flash: Flash,
     mut request: Request<B>,
     next: Next<B>,
 ) -> Result<Response, SessionError> {
     let cookies = SignedCookieJar::from_headers(request.headers(), state.key.clone());
+    // Public pages do not need a session to work, so they carry on anonymously
+    // when it cannot be used
+    let is_admin = request.uri().path().starts_with("/admin");
+    let mut stale_cookie = false;
     let session = match cookies.get(SESSION_COOKIE_KEY) {
-        Some(session_cookie) => state
-            .redis_store
+        Some(session_cookie) => match state
+            .store
             .load_session(session_cookie.value().to_string())
             .await
-            .wrap_and_log_err("Failed to load user session")?
-            .ok_or_wrap_and_log("Failed to find user session")?,
-        None => Session::new(),
+            .wrap_and_log_err("Failed to load user session")
+        {
+            Ok(Some(session)) => Some(session),
+            Ok(None) => {
+                // Otherwise the store was flushed, there is nothing left to log out of
+                if forget_expired_session(&state.db_pool, session_cookie.value()).await? && is_admin
+                {
+                    return Ok(session_expired(cookies, flash));
+                }
+                stale_cookie = true;
+                None
+            }
+            // The session may still be valid once the store is back
+            Err(_) if !is_admin => None,
+            Err(e) => return Err(e.into()),
+        },
+        None => None,
+    };
+    let is_stored = session.is_some();
+    let session = session.unwrap_or_default();
+    let client = ClientDetails {
+        ip: request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(peer)| {
+                client_ip(request.headers(), *peer, state.client_ip_header.as_deref())
+            }),
+        user_agent: request
+            .headers()
+            .get(USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(String::from),
+    };
+    let record = mark_seen(&state.db_pool, &session, &state.settings).await?;
+    let (session, logged_in_at) = match record {
+        SessionRecord::Anonymous => (session, None),
+        SessionRecord::Active { logged_in_at } => (session, Some(logged_in_at)),
+        SessionRecord::Revoked | SessionRecord::Expired => {
+            state
+                .store
+                .destroy_session(session)
+                .await
+                .wrap_and_log_err("Failed to cleanup revoked user session")?;
+            if record == SessionRecord::Expired && is_admin {
+                return Ok(session_expired(cookies, flash));
+            }
+            (Session::new(), None)
+        }
     };
+    let ended = matches!(record, SessionRecord::Revoked | SessionRecord::Expired);
+    // Sessions already in the store get their expiry pushed back
+    let is_stored = is_stored && !ended;
 
-    request.extensions_mut().insert(session);
+    // Handlers get a clone sharing the session data, but it loses the cookie
+    // value of a new session, so only a renewed one is taken back
+    request.extensions_mut().insert(session.clone());
 
     let mut response = next.run(request).await;
 
-    let cookies = match response.extensions_mut().remove::<Session>() {
-        Some(session) if session.is_destroyed() => {
+    let (session, is_stored) = match response.extensions_mut().remove::<Session>() {
+        Some(renewed) if renewed.id() != session.id() => {
+            if is_stored {
+                state
+                    .store
+                    .destroy_session(session)
+                    .await
+                    .wrap_and_log_err("Failed to cleanup renewed user session")?;
+            }
+            (renewed, false)
+        }
+        _ => (session, is_stored),
+    };
+    let cookies = match session {
+        session if session.is_destroyed() => {
+            forget_session(&state.db_pool, session.id())
+                .await
+                .wrap_and_log_err("Failed to forget user session")?;
             state
-                .redis_store
+                .store
                 .destroy_session(session)
                 .await
                 .wrap_and_log_err("Failed to cleanup user session")?;
-            let mut cookie = Cookie::build(SESSION_COOKIE_KEY, "")
-                .http_only(true)
-                .path("/")
-                .finish();
-            cookie.make_removal();
-            cookies.add(cookie)
+            cookies.add(removal_cookie())
         }
-        Some(session) if session.data_changed() => {
-            let cookie_value = state
-                .redis_store
+        mut session if session.data_changed() || is_stored => {
+            let logged_in_at = match session.get::<Uuid>(USER_ID_KEY) {
+                Some(user_id) => {
+                    // Logging in always renews the session, so any other one
+                    // carrying a user was recorded already, and may have been
+                    // revoked while this request ran
+                    if !is_stored {
+                        record_session(
+                            &state.db_pool,
+                            session.id(),
+                            user_id,
+                            &client,
+                            &state.settings,
+                        )
+                        .await
+                        .wrap_and_log_err("Failed to record user session")?;
+                    }
+                    // The user has just logged in if the session was not active before
+                    Some(logged_in_at.unwrap_or_else(Utc::now))
+                }
+                None => None,
+            };
+            session.expire_in(time_to_live(&state.settings, logged_in_at));
+            // Storing an unchanged session again would overwrite the changes
+            // of concurrent requests with the copy loaded by this one
+            if is_stored && !session.data_changed() {
+                state
+                    .store
+                    .refresh_expiry(&session)
+                    .await
+                    .wrap_and_log_err("Failed to refresh user session")?;
+                return Ok((cookies, response).into_response());
+            }
+            // Only new or renewed sessions need a cookie, the others already have one
+            match state
+                .store
                 .store_session(session)
                 .await
                 .wrap_and_log_err("Failed to store user session")?
-                .ok_or_wrap_and_log("Failed to get session cookie")?;
-            let cookie = Cookie::build(SESSION_COOKIE_KEY, cookie_value)
-                .http_only(true)
-                .same_site(SameSite::Strict)
-                .path("/")
-                .finish();
-            cookies.add(cookie)
+            {
+                Some(cookie_value) => {
+                    let cookie = Cookie::build(SESSION_COOKIE_KEY, cookie_value)
+                        .http_only(true)
+                        .same_site(SameSite::Strict)
+                        .path("/")
+                        .finish();
+                    cookies.add(cookie)
+                }
+                None => cookies,
+            }
         }
+        _ if ended || stale_cookie => cookies.add(removal_cookie()),
         _ => cookies,
     };
 
     Ok((cookies, response).into_response())
 }
+
+/// Sends the user back to the login form, explaining why they have to log in
+/// again.
+fn session_expired(cookies: SignedCookieJar, flash: Flash) -> Response {
+    (
+        cookies.add(removal_cookie()),
+        flash.info("Your session has expired - please log in again."),
+        Redirect::to("/login"),
+    )
+        .into_response()
+}
+
+/// Each request restarts the idle timeout, but never past the end of the
+/// login's lifetime.
+fn time_to_live(settings: &SessionSettings, logged_in_at: Option<DateTime<Utc>>) -> Duration {
+    let ttl = match logged_in_at {
+        Some(logged_in_at) => {
+            let elapsed = (Utc::now() - logged_in_at)
+                .to_std()
+                .unwrap_or(Duration::ZERO);
+            settings
+                .lifetime
+                .saturating_sub(elapsed)
+                .min(settings.idle_timeout)
+        }
+        None => settings.idle_timeout,
+    };
+    // Redis truncates the time left to whole seconds when storing, and rejects
+    // a zero one, so keep sessions a little longer. The index enforces exact
+    // timeouts for logged-in sessions.
+    Duration::from_secs(ttl.as_secs() + 2)
+}
+
+fn removal_cookie() -> Cookie<'static> {
+    let mut cookie = Cookie::build(SESSION_COOKIE_KEY, "")
+        .http_only(true)
+        .path("/")
+        .finish();
+    cookie.make_removal();
+    cookie
+}
+
+/// Logs `user_id` out of every session, e.g. after their password is reset.
+/// Each session is cleaned up from the store the next time it is used.
+#[tracing::instrument(skip(executor))]
+pub async fn revoke_sessions(
+    executor: impl PgExecutor<'_>,
+    user_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(r#"DELETE FROM user_sessions WHERE user_id = $1"#, user_id)
+        .execute(executor)
+        .await?;
+
+    Ok(())
+}
+
+/// Logs `user_id` out of every session but `current_session_id`, e.g. after
+/// they change their password.
+#[tracing::instrument(skip(executor))]
+pub async fn revoke_other_sessions(
+    executor: impl PgExecutor<'_>,
+    user_id: Uuid,
+    current_session_id: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"DELETE FROM user_sessions WHERE user_id = $1 AND session_id <> $2"#,
+        user_id,
+        current_session_id
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+/// Returns whether `user_id` had a session with `public_id` to revoke.
+#[tracing::instrument(skip(db_pool))]
+pub async fn revoke_session(
+    db_pool: &PgPool,
+    user_id: Uuid,
+    public_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let revoked = sqlx::query!(
+        r#"DELETE FROM user_sessions WHERE user_id = $1 AND public_id = $2"#,
+        user_id,
+        public_id
+    )
+    .execute(db_pool)
+    .await?;
+
+    Ok(revoked.rows_affected() == 1)
+}
+
+pub struct ActiveSession {
+    pub public_id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub is_current: bool,
+}
+
+/// Lists the sessions of `user_id` that have not expired yet, most recently
+/// used first.
+#[tracing::instrument(skip(db_pool, settings))]
+pub async fn list_sessions(
+    db_pool: &PgPool,
+    user_id: Uuid,
+    current_session_id: &str,
+    settings: &SessionSettings,
+) -> Result<Vec<ActiveSession>, sqlx::Error> {
+    sqlx::query_as!(
+        ActiveSession,
+        r#"
+        SELECT
+            public_id,
+            created_at,
+            last_seen_at,
+            ip,
+            user_agent,
+            session_id = $2 AS "is_current!"
+        FROM user_sessions
+        WHERE user_id = $1
+            AND last_seen_at > now() - make_interval(secs => $3)
+            AND created_at > now() - make_interval(secs => $4)
+        ORDER BY last_seen_at DESC
+        "#,
+        user_id,
+        current_session_id,
+        settings.idle_timeout.as_secs_f64(),
+        settings.lifetime.as_secs_f64()
+    )
+    .fetch_all(db_pool)
+    .await
+}
+
+struct ClientDetails {
+    ip: Option<IpAddr>,
+    user_agent: Option<String>,
+}
+
+/// What the `user_sessions` index says about a session.
+#[derive(Debug, PartialEq, Eq)]
+enum SessionRecord {
+    /// Nobody is logged in, so there is nothing to check.
+    Anonymous,
+    Active {
+        logged_in_at: DateTime<Utc>,
+    },
+    /// Logged out from another session, or by a password change.
+    Revoked,
+    /// Idle for too long, or past its lifetime.
+    Expired,
+}
+
+/// A logged-in session is only honoured while it is still listed in
+/// `user_sessions` and within its timeouts. Records that it was just used.
+async fn mark_seen(
+    db_pool: &PgPool,
+    session: &Session,
+    settings: &SessionSettings,
+) -> Result<SessionRecord, SessionError> {
+    let Some(user_id) = session.get::<Uuid>(USER_ID_KEY) else {
+        return Ok(SessionRecord::Anonymous);
+    };
+    let expired = sqlx::query!(
+        r#"
+        DELETE FROM user_sessions
+        WHERE session_id = $1
+            AND (last_seen_at < now() - make_interval(secs => $2)
+                OR created_at < now() - make_interval(secs => $3))
+        "#,
+        session.id(),
+        settings.idle_timeout.as_secs_f64(),
+        settings.lifetime.as_secs_f64()
+    )
+    .execute(db_pool)
+    .await
+    .wrap_and_log_err("Failed to expire user session")?;
+    if expired.rows_affected() == 1 {
+        return Ok(SessionRecord::Expired);
+    }
+
+    let seen = sqlx::query!(
+        r#"
+        UPDATE user_sessions
+        SET last_seen_at = now()
+        WHERE session_id = $1 AND user_id = $2
+        RETURNING created_at
+        "#,
+        session.id(),
+        user_id
+    )
+    .fetch_optional(db_pool)
+    .await
+    .wrap_and_log_err("Failed to look up user session")?;
+
+    Ok(match seen {
+        Some(seen) => SessionRecord::Active {
+            logged_in_at: seen.created_at,
+        },
+        None => SessionRecord::Revoked,
+    })
+}
+
+/// The store drops sessions once they expire, but a logged-in one is still
+/// listed in `user_sessions`, which tells it apart from a made-up cookie.
+/// Returns whether the session was logged in.
+async fn forget_expired_session(
+    db_pool: &PgPool,
+    cookie_value: &str,
+) -> Result<bool, SessionError> {
+    let Ok(session_id) = Session::id_from_cookie_value(cookie_value) else {
+        return Ok(false);
+    };
+    let forgotten = sqlx::query!(
+        r#"DELETE FROM user_sessions WHERE session_id = $1"#,
+        session_id
+    )
+    .execute(db_pool)
+    .await
+    .wrap_and_log_err("Failed to forget expired user session")?;
+
+    Ok(forgotten.rows_affected() == 1)
+}
+
+async fn record_session(
+    db_pool: &PgPool,
+    session_id: &str,
+    user_id: Uuid,
+    client: &ClientDetails,
+    settings: &SessionSettings,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO user_sessions (session_id, user_id, public_id, ip, user_agent)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (session_id) DO NOTHING
+        "#,
+        session_id,
+        user_id,
+        Uuid::new_v4(),
+        client.ip.map(|ip| ip.to_string()),
+        client.user_agent
+    )
+    .execute(db_pool)
+    .await?;
+    // Expired sessions that are never used again are cleaned up as we go
+    sqlx::query!(
+        r#"
+        DELETE FROM user_sessions
+        WHERE last_seen_at < now() - make_interval(secs => $1)
+            OR created_at < now() - make_interval(secs => $2)
+        "#,
+        settings.idle_timeout.as_secs_f64(),
+        settings.lifetime.as_secs_f64()
+    )
+    .execute(db_pool)
+    .await?;
+
+    Ok(())
+}
+
+async fn forget_session(db_pool: &PgPool, session_id: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"DELETE FROM user_sessions WHERE session_id = $1"#,
+        session_id
+    )
+    .execute(db_pool)
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::Utc;
+
+    use super::time_to_live;
+    use crate::configuration::{SessionSettings, SessionStoreKind};
+
+    const SETTINGS: SessionSettings = SessionSettings {
+        store: SessionStoreKind::Memory,
+        idle_timeout: Duration::from_secs(1800),
+        lifetime: Duration::from_secs(43200),
+    };
+
+    #[test]
+    fn anonymous_sessions_live_for_the_idle_timeout() {
+        assert_eq!(
+            time_to_live(&SETTINGS, None),
+            SETTINGS.idle_timeout + Duration::from_secs(2)
+        );
+    }
+
+    #[test]
+    fn logged_in_sessions_never_outlive_their_lifetime() {
+        let logged_in_at = Utc::now() - chrono::Duration::seconds(43000);
+
+        let ttl = time_to_live(&SETTINGS, Some(logged_in_at));
+
+        assert!(ttl <= Duration::from_secs(202));
+        assert!(ttl > Duration::from_secs(190));
+    }
+
+    #[test]
+    fn the_time_to_live_is_never_too_short_to_store() {
+        let logged_in_at = Utc::now() - chrono::Duration::days(1);
+
+        assert_eq!(
+            time_to_live(&SETTINGS, Some(logged_in_at)),
+            Duration::from_secs(2)
+        );
+    }
+}
@@ -0,0 +1,230 @@
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+use crate::{
+    authentication::{
+        change_password, check_password_rules, hash_password, AuthError, PasswordRuleError,
+    },
+    configuration::PasswordHashingSettings,
+    domain::{Role, SubscriberEmail},
+    two_factor,
+};
+
+const MAX_USERNAME_LENGTH: usize = 64;
+
+pub struct User {
+    pub user_id: Uuid,
+    pub username: String,
+    pub role: Role,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum UserError {
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+    #[error(transparent)]
+    WeakPassword(#[from] PasswordRuleError),
+    #[error(
+        "Usernames must be 1 to {MAX_USERNAME_LENGTH} letters, digits, dots, dashes or underscores"
+    )]
+    InvalidUsername,
+    #[error("There is no user named {0}")]
+    UnknownUser(String),
+    #[error("A user named {0} already exists")]
+    DuplicateUsername(String),
+    #[error("Another account already uses {0}")]
+    DuplicateEmail(String),
+    #[error("{0} is the only remaining user and cannot be deleted")]
+    LastUser(String),
+    #[error("{0} is the only remaining owner and cannot be deleted")]
+    LastOwner(String),
+}
+
+impl From<AuthError> for UserError {
+    fn from(error: AuthError) -> Self {
+        UserError::UnexpectedError(error.into())
+    }
+}
+
+#[tracing::instrument(skip(executor, password))]
+pub async fn create_user(
+    executor: impl PgExecutor<'_>,
+    username: &str,
+    password: Secret<String>,
+    role: Role,
+    email: Option<&SubscriberEmail>,
+    hashing: PasswordHashingSettings,
+) -> Result<Uuid, UserError> {
+    if !is_valid_username(username) {
+        return Err(UserError::InvalidUsername);
+    }
+    check_password_rules(&password)?;
+    let password_hash = hash_password(password, hashing).await?;
+
+    let user = sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, password_hash, role, email)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (username) DO NOTHING
+        RETURNING user_id
+        "#,
+        Uuid::new_v4(),
+        username,
+        password_hash.expose_secret(),
+        role.as_ref(),
+        email.map(AsRef::as_ref)
+    )
+    .fetch_optional(executor)
+    .await
+    .map_err(|e| match &e {
+        sqlx::Error::Database(db_error) if db_error.constraint() == Some("users_email_key") => {
+            UserError::DuplicateEmail(email.map(AsRef::as_ref).unwrap_or_default().into())
+        }
+        _ => UserError::UnexpectedError(anyhow::Error::new(e).context("Failed to insert user")),
+    })?;
+
+    user.map(|r| r.user_id)
+        .ok_or_else(|| UserError::DuplicateUsername(username.into()))
+}
+
+/// Usernames are typed at the login form and shown on admin pages, so they
+/// stick to a few plain characters.
+fn is_valid_username(username: &str) -> bool {
+    (1..=MAX_USERNAME_LENGTH).contains(&username.len())
+        && username
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+}
+
+#[tracing::instrument(skip(db_pool, password))]
+pub async fn reset_password(
+    db_pool: &PgPool,
+    username: &str,
+    password: Secret<String>,
+    hashing: PasswordHashingSettings,
+) -> Result<(), UserError> {
+    check_password_rules(&password)?;
+    let user_id = get_user_id(db_pool, username).await?;
+    change_password(user_id, password, hashing, db_pool).await?;
+
+    Ok(())
+}
+
+/// Lets a user who lost their authenticator log in with their password alone,
+/// so that they can set up two-factor authentication again.
+#[tracing::instrument(skip(db_pool))]
+pub async fn reset_two_factor(db_pool: &PgPool, username: &str) -> Result<(), UserError> {
+    let user_id = get_user_id(db_pool, username).await?;
+    two_factor::disable(db_pool, user_id).await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn list_users(db_pool: &PgPool) -> Result<Vec<User>, UserError> {
+    let users = sqlx::query!(r#"SELECT user_id, username, role FROM users ORDER BY username"#)
+        .fetch_all(db_pool)
+        .await
+        .context("Failed to list users")?;
+
+    users
+        .into_iter()
+        .map(|r| {
+            Ok(User {
+                user_id: r.user_id,
+                username: r.username,
+                role: Role::parse(r.role).map_err(anyhow::Error::msg)?,
+            })
+        })
+        .collect()
+}
+
+/// Deletes a user along with their idempotency keys. The last remaining user is
+/// kept so that somebody can still log in, and the last owner so that somebody
+/// can still manage the others.
+#[tracing::instrument(skip(db_pool))]
+pub async fn delete_user(db_pool: &PgPool, username: &str) -> Result<(), UserError> {
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to begin transaction")?;
+    // Serialises concurrent deletions so they cannot remove every user between them
+    sqlx::query!("LOCK TABLE users IN SHARE ROW EXCLUSIVE MODE")
+        .execute(&mut transaction)
+        .await
+        .context("Failed to lock users table")?;
+
+    let user = sqlx::query!(
+        r#"
+        SELECT
+            user_id,
+            role,
+            (SELECT count(*) FROM users) AS "n_users!",
+            (SELECT count(*) FROM users WHERE role = 'owner') AS "n_owners!"
+        FROM users
+        WHERE username = $1
+        "#,
+        username
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to query user")?
+    .ok_or_else(|| UserError::UnknownUser(username.into()))?;
+    if user.n_users <= 1 {
+        return Err(UserError::LastUser(username.into()));
+    }
+    if user.role == Role::Owner.as_ref() && user.n_owners <= 1 {
+        return Err(UserError::LastOwner(username.into()));
+    }
+
+    sqlx::query!(
+        r#"DELETE FROM idempotency WHERE user_id = $1"#,
+        user.user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete idempotency keys")?;
+    sqlx::query!(r#"DELETE FROM users WHERE user_id = $1"#, user.user_id)
+        .execute(&mut transaction)
+        .await
+        .context("Failed to delete user")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit transaction")?;
+
+    Ok(())
+}
+
+async fn get_user_id(db_pool: &PgPool, username: &str) -> Result<Uuid, UserError> {
+    let user = sqlx::query!(r#"SELECT user_id FROM users WHERE username = $1"#, username)
+        .fetch_optional(db_pool)
+        .await
+        .context("Failed to query user")?;
+
+    user.map(|r| r.user_id)
+        .ok_or_else(|| UserError::UnknownUser(username.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_valid_username;
+
+    #[test]
+    fn usernames_stick_to_plain_characters() {
+        for username in ["admin", "ursula.le-guin_2", &"a".repeat(64)] {
+            assert!(is_valid_username(username), "{username}");
+        }
+        for username in [
+            "",
+            "ursula le guin",
+            "<b>ursula</b>",
+            "ürsula",
+            &"a".repeat(65),
+        ] {
+            assert!(!is_valid_username(username), "{username}");
+        }
+    }
+}
@@ -27,3 +27,22 @@ async fn logout_clears_session_state() {
     let response = app.get_admin_dashboard().await;
     assert_is_redirect_to(&response, "/login");
 }
+
+#[tokio::test]
+async fn the_dashboard_escapes_the_username() {
+    let app = spawn_app().await;
+    app.login().await;
+    // Usernames were not restricted to plain characters at first
+    sqlx::query!(
+        "UPDATE users SET username = '<script>alert(1)</script>' WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let html_page = app.get_admin_dashboard_html().await;
+
+    assert!(!html_page.contains("<script>"));
+    assert!(html_page.contains("Welcome &lt;script&gt;alert(1)&lt;/script&gt;!"));
+}
@@ -0,0 +1,226 @@
+use secrecy::Secret;
+use uuid::Uuid;
+use zero2prod::{
+    domain::Role,
+    users::{create_user, delete_user, list_users, reset_password, UserError},
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+
+fn password() -> String {
+    Uuid::new_v4().to_string()
+}
+
+#[tokio::test]
+async fn created_users_can_log_in() {
+    let app = spawn_app().await;
+    let password = password();
+
+    create_user(
+        &app.db_pool,
+        "editor",
+        Secret::new(password.clone()),
+        Role::Editor,
+        None,
+        app.password_hashing,
+    )
+    .await
+    .unwrap();
+
+    let response = app
+        .post_login(&serde_json::json!({ "username": "editor", "password": password }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn created_users_get_the_requested_role() {
+    let app = spawn_app().await;
+
+    create_user(
+        &app.db_pool,
+        "viewer",
+        Secret::new(password()),
+        Role::Viewer,
+        None,
+        app.password_hashing,
+    )
+    .await
+    .unwrap();
+
+    let users = list_users(&app.db_pool).await.unwrap();
+    let user = users.iter().find(|u| u.username == "viewer").unwrap();
+    assert_eq!(user.role, Role::Viewer);
+}
+
+#[tokio::test]
+async fn usernames_must_be_unique() {
+    let app = spawn_app().await;
+
+    let result = create_user(
+        &app.db_pool,
+        &app.test_user.username,
+        Secret::new(password()),
+        Role::Owner,
+        None,
+        app.password_hashing,
+    )
+    .await;
+
+    assert!(matches!(result, Err(UserError::DuplicateUsername(_))));
+}
+
+#[tokio::test]
+async fn new_passwords_must_follow_the_password_rules() {
+    let app = spawn_app().await;
+
+    let created = create_user(
+        &app.db_pool,
+        "editor",
+        Secret::new("short".into()),
+        Role::Editor,
+        None,
+        app.password_hashing,
+    )
+    .await;
+    let reset = reset_password(
+        &app.db_pool,
+        &app.test_user.username,
+        Secret::new("short".into()),
+        app.password_hashing,
+    )
+    .await;
+
+    assert!(matches!(created, Err(UserError::WeakPassword(_))));
+    assert!(matches!(reset, Err(UserError::WeakPassword(_))));
+}
+
+#[tokio::test]
+async fn reset_passwords_replace_the_old_ones() {
+    let app = spawn_app().await;
+    let new_password = password();
+
+    reset_password(
+        &app.db_pool,
+        &app.test_user.username,
+        Secret::new(new_password.clone()),
+        app.password_hashing,
+    )
+    .await
+    .unwrap();
+
+    let response = app.login().await;
+    assert_is_redirect_to(&response, "/login");
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn resetting_the_password_of_an_unknown_user_fails() {
+    let app = spawn_app().await;
+
+    let result = reset_password(
+        &app.db_pool,
+        "nobody",
+        Secret::new(password()),
+        app.password_hashing,
+    )
+    .await;
+
+    assert!(matches!(result, Err(UserError::UnknownUser(_))));
+}
+
+#[tokio::test]
+async fn users_are_listed_by_username() {
+    let app = spawn_app().await;
+
+    let usernames: Vec<_> = list_users(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|user| user.username)
+        .collect();
+
+    let mut expected = vec!["admin".to_string(), app.test_user.username.clone()];
+    expected.sort();
+    assert_eq!(usernames, expected);
+}
+
+#[tokio::test]
+async fn the_seeded_admin_can_be_deleted() {
+    let app = spawn_app().await;
+
+    delete_user(&app.db_pool, "admin").await.unwrap();
+
+    let users = list_users(&app.db_pool).await.unwrap();
+    assert_eq!(users.len(), 1);
+    assert_eq!(users[0].username, app.test_user.username);
+}
+
+#[tokio::test]
+async fn deleted_users_lose_their_idempotency_keys() {
+    let app = spawn_app().await;
+    app.login().await;
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    }))
+    .await;
+
+    delete_user(&app.db_pool, &app.test_user.username)
+        .await
+        .unwrap();
+
+    let keys = sqlx::query!("SELECT user_id FROM idempotency")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(keys.is_empty());
+}
+
+#[tokio::test]
+async fn the_last_user_cannot_be_deleted() {
+    let app = spawn_app().await;
+    delete_user(&app.db_pool, "admin").await.unwrap();
+
+    let result = delete_user(&app.db_pool, &app.test_user.username).await;
+
+    assert!(matches!(result, Err(UserError::LastUser(_))));
+}
+
+#[tokio::test]
+async fn the_last_owner_cannot_be_deleted() {
+    let app = spawn_app().await;
+    delete_user(&app.db_pool, "admin").await.unwrap();
+    create_user(
+        &app.db_pool,
+        "editor",
+        Secret::new(password()),
+        Role::Editor,
+        None,
+        app.password_hashing,
+    )
+    .await
+    .unwrap();
+
+    let result = delete_user(&app.db_pool, &app.test_user.username).await;
+
+    assert!(matches!(result, Err(UserError::LastOwner(_))));
+    delete_user(&app.db_pool, "editor").await.unwrap();
+}
+
+#[tokio::test]
+async fn deleting_an_unknown_user_fails() {
+    let app = spawn_app().await;
+
+    let result = delete_user(&app.db_pool, "nobody").await;
+
+    assert!(matches!(result, Err(UserError::UnknownUser(_))));
+}
@@ -0,0 +1,147 @@
+use reqwest::{redirect::Policy, Client, Response};
+use serde_json::json;
+
+use uuid::Uuid;
+
+use crate::helpers::{assert_is_redirect_to, csrf_token_in, spawn_app, TestApp};
+
+/// Posts a form from the test user's browser as another site would, with
+/// the session cookie but without the token.
+async fn cross_site_post(app: &TestApp, path: &str, form: serde_json::Value) -> Response {
+    app.api_client
+        .post(format!("http://{}{path}", &app.address))
+        .form(&form)
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn forms_carry_the_csrf_token_of_the_session() {
+    let app = spawn_app().await;
+    app.login().await;
+    let csrf_token = app.csrf_token().await;
+
+    for html_page in [
+        app.get_admin_dashboard_html().await,
+        app.get_change_password_html().await,
+        app.get_publish_newsletter_html().await,
+        app.get_sessions_html().await,
+    ] {
+        assert!(html_page.contains(&format!(
+            r#"<input hidden type="text" name="csrf_token" value="{csrf_token}">"#
+        )));
+    }
+}
+
+#[tokio::test]
+async fn a_cross_site_login_is_rejected() {
+    let app = spawn_app().await;
+    app.get_login_html().await;
+
+    let response = cross_site_post(
+        &app,
+        "/login",
+        json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+        }),
+    )
+    .await;
+
+    assert_eq!(response.status().as_u16(), 403);
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn a_cross_site_password_change_is_rejected() {
+    let app = spawn_app().await;
+    app.login().await;
+    let new_password = "a-brand-new-password";
+
+    let response = cross_site_post(
+        &app,
+        "/admin/password",
+        json!({
+            "current_password": &app.test_user.password,
+            "new_password": new_password,
+            "new_password_check": new_password,
+        }),
+    )
+    .await;
+
+    assert_eq!(response.status().as_u16(), 403);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("This form has expired or was sent from another site."));
+    app.post_logout().await;
+    let response = app.login().await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn a_cross_site_logout_is_rejected() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    let response = cross_site_post(&app, "/admin/logout", json!({})).await;
+
+    assert_eq!(response.status().as_u16(), 403);
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn the_token_of_another_session_is_rejected() {
+    let app = spawn_app().await;
+    let other_client = Client::builder()
+        .redirect(Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap();
+    let html_page = other_client
+        .get(format!("http://{}/login", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    let other_token = csrf_token_in(&html_page);
+    app.login().await;
+
+    let response = cross_site_post(
+        &app,
+        "/admin/newsletters",
+        json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+            "csrf_token": other_token,
+        }),
+    )
+    .await;
+
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn oversized_forms_are_rejected_before_they_are_read() {
+    let app = spawn_app().await;
+    app.get_login_html().await;
+
+    let response = app
+        .api_client
+        .post(format!("http://{}/login", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(format!("username={}", "a".repeat(3 * 1024 * 1024)))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 413);
+}
@@ -0,0 +1,231 @@
+use serde_json::json;
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{
+    accept_batch, assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp,
+};
+
+async fn publish_newsletter(app: &TestApp) {
+    app.post_publish_newsletter(&json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    }))
+    .await;
+}
+
+async fn exhaust_retries(app: &TestApp) {
+    sqlx::query!(
+        "UPDATE issue_delivery_queue SET n_retries = $1",
+        app.worker.retry.max_attempts - 1
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+async fn dead_letter_delivery(app: &TestApp) {
+    let _mock_guard = Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .named("Fail delivery")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    publish_newsletter(app).await;
+    exhaust_retries(app).await;
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_delivery_failures() {
+    let app = spawn_app().await;
+
+    let response = app.get_delivery_failures().await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_reenqueue_delivery_failures() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_reenqueue_delivery_failures(&[("failure_id", Uuid::new_v4().to_string())])
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn exhausted_deliveries_are_moved_to_the_failures_table() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login().await;
+
+    dead_letter_delivery(&app).await;
+
+    let queued = sqlx::query!("SELECT subscriber_email FROM issue_delivery_queue")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(queued.is_empty());
+    let failure = sqlx::query!(
+        "SELECT s.email, f.subscriber_email, f.n_attempts, f.last_error
+        FROM issue_delivery_failures f, subscriptions s"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(failure.subscriber_email, failure.email);
+    assert_eq!(failure.n_attempts, app.worker.retry.max_attempts);
+    assert!(failure.last_error.contains("500"));
+}
+
+#[tokio::test]
+async fn deliveries_rejected_with_a_client_error_are_not_retried() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login().await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(400))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    let failure = sqlx::query!("SELECT n_attempts, last_error FROM issue_delivery_failures")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(failure.n_attempts, 1);
+    assert!(failure.last_error.contains("400"));
+}
+
+#[tokio::test]
+async fn deliveries_to_invalid_emails_are_moved_to_the_failures_table() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login().await;
+
+    publish_newsletter(&app).await;
+    sqlx::query!("UPDATE issue_delivery_queue SET subscriber_email = 'not-an-email'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let failure = sqlx::query!("SELECT subscriber_email, n_attempts FROM issue_delivery_failures")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(failure.subscriber_email, "not-an-email");
+    assert_eq!(failure.n_attempts, 0);
+}
+
+#[tokio::test]
+async fn delivery_failures_are_listed_per_issue() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login().await;
+    dead_letter_delivery(&app).await;
+
+    let failure = sqlx::query!("SELECT failure_id, subscriber_email FROM issue_delivery_failures")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    let html_page = app.get_delivery_failures_html().await;
+
+    assert!(html_page.contains("<h2>Newsletter title</h2>"));
+    assert!(html_page.contains(&failure.subscriber_email));
+    assert!(html_page.contains(&format!(
+        r#"<input type="checkbox" name="failure_id" value="{}">"#,
+        failure.failure_id
+    )));
+}
+
+#[tokio::test]
+async fn selected_failures_are_reenqueued_and_delivered() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login().await;
+    dead_letter_delivery(&app).await;
+
+    let failure_id = sqlx::query!("SELECT failure_id FROM issue_delivery_failures")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .failure_id;
+    let response = app
+        .post_reenqueue_delivery_failures(&[("failure_id", failure_id.to_string())])
+        .await;
+    assert_is_redirect_to(&response, "/admin/deliveries/failures");
+
+    let html_page = app.get_delivery_failures_html().await;
+    assert!(html_page.contains("<p><i>1 deliveries have been re-enqueued.</i></p>"));
+    assert!(html_page.contains("There are no failed deliveries."));
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(accept_batch)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn failures_of_deliveries_still_queued_are_kept() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login().await;
+    dead_letter_delivery(&app).await;
+    let failure = sqlx::query!(
+        "SELECT failure_id, newsletter_issue_id, subscriber_email FROM issue_delivery_failures"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email, n_retries, execute_after)
+        VALUES ($1, $2, 0, now())
+        "#,
+        failure.newsletter_issue_id,
+        failure.subscriber_email
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let response = app
+        .post_reenqueue_delivery_failures(&[("failure_id", failure.failure_id.to_string())])
+        .await;
+    assert_is_redirect_to(&response, "/admin/deliveries/failures");
+
+    let html_page = app.get_delivery_failures_html().await;
+    assert!(html_page.contains("<p><i>0 deliveries have been re-enqueued.</i></p>"));
+    assert!(html_page
+        .contains("<p><i>1 deliveries are still queued, their failures have been kept.</i></p>"));
+    assert!(html_page.contains(&failure.failure_id.to_string()));
+}
+
+#[tokio::test]
+async fn reenqueueing_requires_a_selection() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    let response = app.post_reenqueue_delivery_failures(&[]).await;
+    assert_is_redirect_to(&response, "/admin/deliveries/failures");
+
+    let html_page = app.get_delivery_failures_html().await;
+    assert!(html_page.contains("<p><i>Select at least one delivery to re-enqueue.</i></p>"));
+}
@@ -0,0 +1,121 @@
+use std::path::PathBuf;
+
+use uuid::Uuid;
+use zero2prod::configuration::{EmailTransportKind, MailboxSettings};
+
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
+
+async fn spawn_app_with_mailbox(page_enabled: bool) -> (TestApp, PathBuf) {
+    let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+    let app = spawn_app_with(|c| {
+        c.email_client.transport = EmailTransportKind::Mailbox;
+        c.email_client.mailbox = Some(MailboxSettings {
+            directory: directory.clone(),
+            page_enabled,
+        });
+    })
+    .await;
+    (app, directory)
+}
+
+async fn get_mailbox_html(app: &TestApp, path: &str) -> reqwest::Response {
+    app.api_client
+        .get(format!("http://{}/dev/mailbox{path}", app.address))
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+#[tokio::test]
+async fn subscribing_writes_the_confirmation_email_to_the_mailbox() {
+    let (app, directory) = spawn_app_with_mailbox(false).await;
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let mut entries = std::fs::read_dir(&directory).unwrap();
+    let path = entries.next().unwrap().unwrap().path();
+    assert!(entries.next().is_none());
+    assert_eq!(path.extension().unwrap(), "eml");
+
+    let message = std::fs::read_to_string(path).unwrap();
+    assert!(message.contains("To: ursula_le_guin@gmail.com\r\n"));
+    assert!(message.contains("Content-Type: text/plain"));
+    assert!(message.contains("Content-Type: text/html"));
+    assert!(message.contains("/subscriptions/confirm?subscription_token="));
+
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[tokio::test]
+async fn mailbox_page_lists_and_shows_written_messages() {
+    let (app, directory) = spawn_app_with_mailbox(true).await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    let file_name = std::fs::read_dir(&directory)
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap()
+        .file_name()
+        .into_string()
+        .unwrap();
+
+    let response = get_mailbox_html(&app, "").await;
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response.text().await.unwrap();
+    assert!(html.contains(&format!(
+        r#"<a href="/dev/mailbox/{file_name}">Welcome!</a>"#
+    )));
+    assert!(html.contains("ursula_le_guin@gmail.com"));
+
+    let response = get_mailbox_html(&app, &format!("/{file_name}")).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response.text().await.unwrap();
+    assert!(html.contains("/subscriptions/confirm?subscription_token="));
+
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[tokio::test]
+async fn mailbox_page_only_serves_messages_from_the_mailbox() {
+    let (app, directory) = spawn_app_with_mailbox(true).await;
+
+    let response = get_mailbox_html(&app, "/..%2F..%2Fetc%2Fpasswd").await;
+    assert_eq!(response.status().as_u16(), 404);
+    let response = get_mailbox_html(&app, "/missing.eml").await;
+    assert_eq!(response.status().as_u16(), 404);
+
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[tokio::test]
+async fn mailbox_page_escapes_file_names() {
+    let (app, directory) = spawn_app_with_mailbox(true).await;
+    std::fs::create_dir_all(&directory).unwrap();
+    let file_name = r#"x"><img src=x onerror=alert(1)>.eml"#;
+    std::fs::write(directory.join(file_name), "Subject: Hello\r\n\r\nBody").unwrap();
+
+    let link = "/dev/mailbox/x%22%3E%3Cimg%20src=x%20onerror=alert(1)%3E.eml";
+    let html = get_mailbox_html(&app, "").await.text().await.unwrap();
+    assert!(!html.contains("<img"));
+    assert!(html.contains(&format!(r#"<a href="{link}">Hello</a>"#)));
+
+    let path = link.strip_prefix("/dev/mailbox").unwrap();
+    let html = get_mailbox_html(&app, path).await.text().await.unwrap();
+    assert!(!html.contains("<img"));
+    assert!(html.contains("<title>x&quot;&gt;&lt;img src=x onerror=alert(1)&gt;.eml</title>"));
+
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[tokio::test]
+async fn mailbox_page_is_disabled_by_default() {
+    let app = spawn_app().await;
+
+    let response = get_mailbox_html(&app, "").await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
@@ -1,6 +1,12 @@
+use std::{net::SocketAddr, time::Duration};
+
 use reqwest::Client;
+use tokio_util::sync::CancellationToken;
+use zero2prod::{
+    issue_delivery_worker::Heartbeat, routes::WorkerHealth, startup::HealthCheckServer,
+};
 
-use crate::helpers::spawn_app;
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
 
 #[tokio::test]
 async fn health_check_works() {
@@ -16,3 +22,55 @@ async fn health_check_works() {
 
     assert!(response.status().is_success());
 }
+
+/// Serves the worker health check for `app`'s database, and returns its address.
+fn spawn_worker_health_check(app: &TestApp, heartbeat: Heartbeat) -> SocketAddr {
+    let health = WorkerHealth {
+        db_pool: app.db_pool.clone(),
+        heartbeat,
+    };
+    let server = HealthCheckServer::build("127.0.0.1", 0, health).unwrap();
+    let address = server.local_addr();
+    tokio::spawn(server.run_until_stopped(CancellationToken::new()));
+    address
+}
+
+#[tokio::test]
+async fn worker_health_check_works() {
+    let app = spawn_app().await;
+    let shutdown = CancellationToken::new();
+    let heartbeat = Heartbeat::new(&app.worker);
+    let worker = app.spawn_worker_with(heartbeat.clone(), shutdown.clone());
+    let address = spawn_worker_health_check(&app, heartbeat);
+
+    let response = Client::new()
+        .get(format!("http://{address}/health_check"))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    assert!(response.status().is_success());
+    shutdown.cancel();
+    worker.await.unwrap();
+}
+
+#[tokio::test]
+async fn worker_health_check_fails_once_the_workers_stop() {
+    let app = spawn_app_with(|c| {
+        c.worker.claim_timeout = Duration::ZERO;
+        c.worker.empty_queue_sleep = Duration::from_millis(100);
+        c.worker.error_sleep = Duration::from_millis(100);
+    })
+    .await;
+    // No worker runs to keep the heartbeat going
+    let address = spawn_worker_health_check(&app, Heartbeat::new(&app.worker));
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let response = Client::new()
+        .get(format!("http://{address}/health_check"))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    assert_eq!(response.status().as_u16(), 503);
+}
@@ -1,16 +1,39 @@
-use std::{env, net::SocketAddr};
+use std::{
+    env,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
 
 use argon2::{password_hash::SaltString, Argon2, PasswordHasher};
 use chrono::{DateTime, Utc};
+use fake::{
+    faker::{internet::en::SafeEmail, name::en::Name},
+    Fake,
+};
 use once_cell::sync::Lazy;
 use reqwest::{redirect::Policy, Client, Response, Url};
+use secrecy::Secret;
+use serde_json::json;
 use sqlx::{Connection, Executor, PgConnection, PgPool};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
-use wiremock::{MockServer, Request};
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, Request, ResponseTemplate,
+};
 use zero2prod::{
-    configuration::{get_configuration, DatabaseSettings},
+    configuration::{
+        get_configuration, DatabaseSettings, EmailTransportKind, PasswordHashingSettings,
+        SessionStoreKind, Settings, ThrottleStoreKind, WorkerSettings,
+    },
+    domain::Role,
     email_client::EmailClient,
-    issue_delivery_worker::{prune_idempotency_table, try_execute_task, ExecutionOutcome},
+    issue_delivery_worker::{
+        prune_idempotency_table, run_worker_until_stopped, try_execute_task, ExecutionOutcome,
+        Heartbeat,
+    },
+    session_store::prune_expired_sessions,
     startup::{get_connection_pool, App},
     telemetry::init_telemetry,
 };
@@ -27,12 +50,20 @@ pub struct TestApp {
     pub test_user: TestUser,
     pub api_client: Client,
     pub email_client: EmailClient,
+    pub base_url: Url,
+    pub hmac_secret: Secret<String>,
+    pub worker: WorkerSettings,
+    pub password_hashing: PasswordHashingSettings,
+    pub shutdown: CancellationToken,
+    pub server: JoinHandle<anyhow::Result<()>>,
+    config: Settings,
 }
 
 pub struct TestUser {
     pub user_id: Uuid,
     pub username: String,
     pub password: String,
+    pub role: Role,
 }
 
 pub struct ConfirmationLinks {
@@ -40,6 +71,11 @@ pub struct ConfirmationLinks {
     pub plain_text: Url,
 }
 
+pub struct UnsubscribeLinks {
+    pub html: Url,
+    pub plain_text: Url,
+}
+
 impl TestApp {
     pub async fn post_subscriptions(&self, body: String) -> Response {
         self.api_client
@@ -54,30 +90,116 @@ impl TestApp {
     pub fn get_confirmation_links(&self, email_request: &Request) -> ConfirmationLinks {
         let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
 
-        let get_link = |s: &str| {
-            let links: Vec<_> = linkify::LinkFinder::new()
-                .links(s)
-                .filter(|l| *l.kind() == linkify::LinkKind::Url)
-                .collect();
-            assert_eq!(links.len(), 1);
-            let raw_link = links[0].as_str().to_owned();
-            let mut confirmation_link = reqwest::Url::parse(&raw_link).unwrap();
-            // Let's make sure we don't call random APIs on the web
-            assert_eq!(confirmation_link.host_str().unwrap(), "127.0.0.1");
-            confirmation_link
-                .set_port(Some(self.address.port()))
-                .unwrap();
-            confirmation_link
-        };
-
-        let html = get_link(body["HtmlBody"].as_str().unwrap());
-        let plain_text = get_link(body["TextBody"].as_str().unwrap());
+        let html = self.get_link(body["HtmlBody"].as_str().unwrap());
+        let plain_text = self.get_link(body["TextBody"].as_str().unwrap());
         ConfirmationLinks { html, plain_text }
     }
 
+    pub fn get_unsubscribe_links(&self, batch_request: &Request) -> UnsubscribeLinks {
+        let batch: serde_json::Value = serde_json::from_slice(&batch_request.body).unwrap();
+        let body = &batch[0];
+
+        let html = self.get_link(body["HtmlBody"].as_str().unwrap());
+        let plain_text = self.get_link(body["TextBody"].as_str().unwrap());
+        UnsubscribeLinks { html, plain_text }
+    }
+
+    fn get_link(&self, s: &str) -> Url {
+        let links: Vec<_> = linkify::LinkFinder::new()
+            .links(s)
+            .filter(|l| *l.kind() == linkify::LinkKind::Url)
+            .collect();
+        assert_eq!(links.len(), 1);
+        let raw_link = links[0].as_str().to_owned();
+        let mut link = reqwest::Url::parse(&raw_link).unwrap();
+        // Let's make sure we don't call random APIs on the web
+        assert_eq!(link.host_str().unwrap(), "127.0.0.1");
+        link.set_port(Some(self.address.port())).unwrap();
+        link
+    }
+
+    /// Extracts the only link of an email sent through the HTTP API.
+    pub fn get_email_link(&self, email_request: &Request) -> Url {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+        let link = self.get_link(body["TextBody"].as_str().unwrap());
+        assert_eq!(link, self.get_link(body["HtmlBody"].as_str().unwrap()));
+        link
+    }
+
+    pub async fn get_invite_user_form(&self) -> Response {
+        self.api_client
+            .get(format!("http://{}/admin/users/invite", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_invite_user_form_html(&self) -> String {
+        self.get_invite_user_form().await.text().await.unwrap()
+    }
+
+    pub async fn post_invite_user<Body>(&self, body: &Body) -> Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("http://{}/admin/users/invite", &self.address))
+            .form(&self.with_csrf_token(body).await)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_accept_invite<Body>(&self, body: &Body) -> Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("http://{}/invites/accept", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_forgot_password<Body>(&self, body: &Body) -> Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("http://{}/password/forgot", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_forgot_password_html(&self) -> String {
+        self.api_client
+            .get(format!("http://{}/password/forgot", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_reset_password<Body>(&self, body: &Body) -> Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("http://{}/password/reset", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn get_publish_newsletter(&self) -> Response {
         self.api_client
-            .get(&format!("http://{}/admin/newsletters", &self.address))
+            .get(format!("http://{}/admin/newsletters", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -92,28 +214,71 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("http://{}/admin/newsletters", &self.address))
-            .form(body)
+            .post(format!("http://{}/admin/newsletters", &self.address))
+            .form(&self.with_csrf_token(body).await)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_delivery_failures(&self) -> Response {
+        self.api_client
+            .get(format!(
+                "http://{}/admin/deliveries/failures",
+                &self.address
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_delivery_failures_html(&self) -> String {
+        self.get_delivery_failures().await.text().await.unwrap()
+    }
+
+    /// Takes a list of pairs, as the form repeats `failure_id` for each
+    /// selected delivery.
+    pub async fn post_reenqueue_delivery_failures(&self, fields: &[(&str, String)]) -> Response {
+        let mut fields = fields.to_vec();
+        fields.push(("csrf_token", self.csrf_token().await));
+        self.api_client
+            .post(format!(
+                "http://{}/admin/deliveries/failures",
+                &self.address
+            ))
+            .form(&fields)
             .send()
             .await
             .expect("Failed to execute request")
     }
 
     pub async fn login(&self) -> Response {
+        self.login_as(&self.test_user).await
+    }
+
+    pub async fn login_as(&self, user: &TestUser) -> Response {
         self.post_login(&serde_json::json!({
-            "username": &self.test_user.username,
-            "password": &self.test_user.password,
+            "username": &user.username,
+            "password": &user.password,
         }))
         .await
     }
 
+    /// Stores a new user with `role` and logs in as them.
+    pub async fn login_with_role(&self, role: Role) -> TestUser {
+        let user = TestUser::generate_with_role(role);
+        user.store(&self.db_pool).await;
+        self.login_as(&user).await;
+        user
+    }
+
     pub async fn post_login<Body>(&self, body: &Body) -> Response
     where
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("http://{}/login", &self.address))
-            .form(body)
+            .post(format!("http://{}/login", &self.address))
+            .form(&self.with_csrf_token(body).await)
             .send()
             .await
             .expect("Failed to execute request.")
@@ -121,7 +286,7 @@ impl TestApp {
 
     pub async fn get_login_html(&self) -> String {
         self.api_client
-            .get(&format!("http://{}/login", &self.address))
+            .get(format!("http://{}/login", &self.address))
             .send()
             .await
             .expect("Failed to execute request")
@@ -130,9 +295,67 @@ impl TestApp {
             .unwrap()
     }
 
+    pub async fn post_login_two_factor<Body>(&self, body: &Body) -> Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("http://{}/login/2fa", &self.address))
+            .form(&self.with_csrf_token(body).await)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_two_factor_html(&self) -> String {
+        self.api_client
+            .get(format!("http://{}/admin/2fa", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_two_factor<Body>(&self, body: &Body) -> Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("http://{}/admin/2fa", &self.address))
+            .form(&self.with_csrf_token(body).await)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_reset_two_factor<Body>(&self, body: &Body) -> Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("http://{}/admin/users/2fa", &self.address))
+            .form(&self.with_csrf_token(body).await)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_reset_two_factor_html(&self) -> String {
+        self.api_client
+            .get(format!("http://{}/admin/users/2fa", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
     pub async fn get_admin_dashboard(&self) -> Response {
         self.api_client
-            .get(&format!("http://{}/admin/dashboard", &self.address))
+            .get(format!("http://{}/admin/dashboard", &self.address))
             .send()
             .await
             .expect("Failed to execute request")
@@ -144,7 +367,7 @@ impl TestApp {
 
     pub async fn get_change_password(&self) -> Response {
         self.api_client
-            .get(&format!("http://{}/admin/password", &self.address))
+            .get(format!("http://{}/admin/password", &self.address))
             .send()
             .await
             .expect("Failed to execute request")
@@ -159,8 +382,44 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("http://{}/admin/password", &self.address))
-            .form(body)
+            .post(format!("http://{}/admin/password", &self.address))
+            .form(&self.with_csrf_token(body).await)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_sessions(&self) -> Response {
+        self.api_client
+            .get(format!("http://{}/admin/sessions", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_sessions_html(&self) -> String {
+        self.get_sessions().await.text().await.unwrap()
+    }
+
+    pub async fn post_revoke_session<Body>(&self, body: &Body) -> Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("http://{}/admin/sessions/revoke", &self.address))
+            .form(&self.with_csrf_token(body).await)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_log_out_everywhere(&self) -> Response {
+        self.api_client
+            .post(format!(
+                "http://{}/admin/sessions/revoke_all",
+                &self.address
+            ))
+            .form(&self.with_csrf_token(&json!({})).await)
             .send()
             .await
             .expect("Failed to execute request.")
@@ -168,24 +427,89 @@ impl TestApp {
 
     pub async fn post_logout(&self) -> Response {
         self.api_client
-            .post(&format!("http://{}/admin/logout", &self.address))
+            .post(format!("http://{}/admin/logout", &self.address))
+            .form(&self.with_csrf_token(&json!({})).await)
             .send()
             .await
             .expect("Failed to execute request.")
     }
 
+    /// The CSRF token of the current session, as the login form shows it.
+    pub async fn csrf_token(&self) -> String {
+        csrf_token_in(&self.get_login_html().await)
+    }
+
+    /// Adds the CSRF token of the current session to a form.
+    async fn with_csrf_token<Body>(&self, body: &Body) -> serde_json::Value
+    where
+        Body: serde::Serialize,
+    {
+        let mut form = serde_json::to_value(body).unwrap();
+        form["csrf_token"] = self.csrf_token().await.into();
+        form
+    }
+
     pub async fn dispatch_all_pending_emails(&self) {
+        while let ExecutionOutcome::TaskCompleted = self.execute_task().await {}
+    }
+
+    /// Runs the delivery worker in the background, as the binary would.
+    pub fn spawn_worker(&self) {
+        self.spawn_stoppable_worker(CancellationToken::new());
+    }
+
+    pub fn spawn_stoppable_worker(&self, shutdown: CancellationToken) -> JoinHandle<()> {
+        self.spawn_worker_with(Heartbeat::new(&self.worker), shutdown)
+    }
+
+    /// Runs the delivery worker in the background, beating `heartbeat`.
+    pub fn spawn_worker_with(
+        &self,
+        heartbeat: Heartbeat,
+        shutdown: CancellationToken,
+    ) -> JoinHandle<()> {
+        tokio::spawn(run_worker_until_stopped(
+            self.config.clone(),
+            heartbeat,
+            shutdown,
+        ))
+    }
+
+    /// Waits until the email server has received `n` batch requests.
+    pub async fn wait_for_batches(&self, n: usize, timeout: Duration) {
+        let deadline = Instant::now() + timeout;
         loop {
-            if let ExecutionOutcome::EmptyQueue =
-                try_execute_task(&self.db_pool, &self.email_client)
-                    .await
-                    .unwrap()
-            {
-                break;
+            let n_batches = self
+                .email_server
+                .received_requests()
+                .await
+                .unwrap()
+                .iter()
+                .filter(|r| r.url.path() == "/email/batch")
+                .count();
+            if n_batches >= n {
+                return;
             }
+            assert!(
+                Instant::now() < deadline,
+                "Received {n_batches} of {n} batches before the timeout"
+            );
+            tokio::time::sleep(Duration::from_millis(50)).await;
         }
     }
 
+    pub async fn execute_task(&self) -> ExecutionOutcome {
+        try_execute_task(
+            &self.db_pool,
+            &self.email_client,
+            &self.base_url,
+            &self.hmac_secret,
+            &self.worker,
+        )
+        .await
+        .unwrap()
+    }
+
     pub async fn insert_idempotency_key(&self, created_on: DateTime<Utc>) {
         dbg!(&created_on);
         sqlx::query!(
@@ -210,9 +534,17 @@ impl TestApp {
     pub async fn prune_idempotency_table(&self) -> u64 {
         prune_idempotency_table(&self.db_pool).await.unwrap()
     }
+
+    pub async fn prune_expired_sessions(&self) -> u64 {
+        prune_expired_sessions(&self.db_pool).await.unwrap()
+    }
 }
 
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+pub async fn spawn_app_with(configure: impl FnOnce(&mut Settings)) -> TestApp {
     Lazy::force(&TRACING);
 
     let email_server = MockServer::start().await;
@@ -222,6 +554,15 @@ pub async fn spawn_app() -> TestApp {
         c.database.database_name = Uuid::new_v4().to_string();
         c.application.port = 0;
         c.email_client.base_url = Url::parse(&email_server.uri()).unwrap();
+        c.email_client.transport = EmailTransportKind::Http;
+        // Retries are due straight away so tests can drain the queue in one go
+        c.worker.retry.base_delay = Duration::ZERO;
+        // Only the tests of the Redis backends need Redis, and as every test
+        // app logs in from 127.0.0.1 they keep their counters apart there
+        c.login_throttle.store = ThrottleStoreKind::Postgres;
+        c.login_throttle.key_prefix = format!("login_failures:{}", Uuid::new_v4());
+        c.session.store = SessionStoreKind::Memory;
+        configure(&mut c);
         c
     };
 
@@ -231,7 +572,8 @@ pub async fn spawn_app() -> TestApp {
         .await
         .expect("Failed to build application");
     let address = app.local_addr();
-    tokio::spawn(app.run());
+    let shutdown = CancellationToken::new();
+    let server = tokio::spawn(app.run_until_stopped(shutdown.clone()));
 
     let api_client = Client::builder()
         .redirect(Policy::none())
@@ -245,7 +587,14 @@ pub async fn spawn_app() -> TestApp {
         email_server,
         test_user: TestUser::generate(),
         api_client,
-        email_client: config.email_client.client(),
+        email_client: config.email_client.clone().client(),
+        base_url: config.application.base_url.clone(),
+        hmac_secret: config.application.hmac_secret.clone(),
+        worker: config.worker.clone(),
+        password_hashing: config.password_hashing,
+        shutdown,
+        server,
+        config,
     };
     test_app.test_user.store(&test_app.db_pool).await;
     test_app
@@ -271,25 +620,31 @@ async fn configure_database(config: &DatabaseSettings) -> PgPool {
 
 impl TestUser {
     pub fn generate() -> Self {
+        Self::generate_with_role(Role::Owner)
+    }
+
+    pub fn generate_with_role(role: Role) -> Self {
         Self {
             user_id: Uuid::new_v4(),
             username: Uuid::new_v4().to_string(),
             password: "everything_has_to_start_somewhere".into(),
+            role,
         }
     }
 
-    async fn store(&self, db_pool: &PgPool) {
+    pub async fn store(&self, db_pool: &PgPool) {
         let salt = SaltString::generate(&mut rand::thread_rng());
         let password_hash = Argon2::default()
             .hash_password(self.password.as_bytes(), &salt)
             .unwrap()
             .to_string();
         sqlx::query!(
-            r#"INSERT INTO users (user_id, username, password_hash)
-            VALUES ($1, $2, $3)"#,
+            r#"INSERT INTO users (user_id, username, password_hash, role)
+            VALUES ($1, $2, $3, $4)"#,
             self.user_id,
             self.username,
             password_hash,
+            self.role.as_ref(),
         )
         .execute(db_pool)
         .await
@@ -297,6 +652,70 @@ impl TestUser {
     }
 }
 
+pub async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
+    let name: String = Name().fake();
+    let email: String = SafeEmail().fake();
+    let body = serde_urlencoded::to_string(json!({
+        "name": name,
+        "email": email
+    }))
+    .unwrap();
+
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create unconfirmed subscriber")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+
+    app.get_confirmation_links(&email_request)
+}
+
+pub async fn create_confirmed_subscriber(app: &TestApp) {
+    let confirmation_link = create_unconfirmed_subscriber(app).await;
+    reqwest::get(confirmation_link.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+/// Replies to an `/email/batch` request as if every message was accepted.
+pub fn accept_batch(request: &Request) -> ResponseTemplate {
+    let batch: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+    let results: Vec<_> = batch
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|_| json!({ "ErrorCode": 0, "Message": "OK" }))
+        .collect();
+    ResponseTemplate::new(200).set_body_json(results)
+}
+
+pub fn csrf_token_in(html_page: &str) -> String {
+    html_page
+        .split(r#"name="csrf_token" value=""#)
+        .nth(1)
+        .and_then(|s| s.split_once('"'))
+        .expect("No CSRF token in the page")
+        .0
+        .to_string()
+}
+
 pub fn assert_is_redirect_to(response: &Response, location: &str) {
     assert_eq!(response.status().as_u16(), 303);
     assert_eq!(response.headers().get("Location").unwrap(), location);
@@ -0,0 +1,399 @@
+use reqwest::Url;
+use serde_json::json;
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::domain::Role;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp, TestUser};
+
+const PASSWORD: &str = "a-brand-new-password";
+
+/// Invites a colleague as the logged-in owner and returns the link they received.
+async fn invite(app: &TestApp, email: &str, role: &str) -> Url {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Invite email")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let response = app
+        .post_invite_user(&json!({ "email": email, "role": role }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/users/invite");
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_email_link(&email_request)
+}
+
+fn token(invite_link: &Url) -> String {
+    invite_link
+        .query_pairs()
+        .find(|(name, _)| name == "token")
+        .unwrap()
+        .1
+        .into_owned()
+}
+
+fn accept_form(invite_link: &Url, username: &str) -> serde_json::Value {
+    json!({
+        "token": token(invite_link),
+        "username": username,
+        "password": PASSWORD,
+        "password_check": PASSWORD,
+    })
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_invite_colleagues() {
+    let app = spawn_app().await;
+
+    let form = app.get_invite_user_form().await;
+    let response = app
+        .post_invite_user(&json!({ "email": "ursula@example.com", "role": "editor" }))
+        .await;
+
+    assert_is_redirect_to(&form, "/login");
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn only_owners_can_invite_colleagues() {
+    let app = spawn_app().await;
+    app.login_with_role(Role::Editor).await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let form = app.get_invite_user_form().await;
+    let response = app
+        .post_invite_user(&json!({ "email": "ursula@example.com", "role": "editor" }))
+        .await;
+
+    assert_eq!(form.status().as_u16(), 403);
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn owners_are_offered_to_invite_colleagues_from_the_dashboard() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    let html_page = app.get_admin_dashboard_html().await;
+
+    assert!(html_page.contains(r#"action="/admin/users/invite""#));
+}
+
+#[tokio::test]
+async fn inviting_a_colleague_emails_them_a_link() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    let invite_link = invite(&app, "ursula@example.com", "editor").await;
+
+    assert_eq!(invite_link.path(), "/invites/accept");
+    let html_page = app.get_invite_user_form_html().await;
+    assert!(html_page.contains("<p><i>An invite has been sent to ursula@example.com.</i></p>"));
+}
+
+#[tokio::test]
+async fn invalid_emails_are_rejected() {
+    let app = spawn_app().await;
+    app.login().await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_invite_user(&json!({ "email": "not-an-email", "role": "editor" }))
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/users/invite");
+    let html_page = app.get_invite_user_form_html().await;
+    assert!(html_page.contains("not-an-email is not a valid subscriber email"));
+}
+
+#[tokio::test]
+async fn flash_messages_are_escaped() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    app.post_invite_user(&json!({ "email": "ursula@example.com", "role": "<b>owner</b>" }))
+        .await;
+
+    let html_page = app.get_invite_user_form_html().await;
+    assert!(html_page.contains("&lt;b&gt;owner&lt;/b&gt; is not a valid role"));
+    assert!(!html_page.contains("<b>owner</b>"));
+}
+
+#[tokio::test]
+async fn the_invite_link_shows_a_signup_form() {
+    let app = spawn_app().await;
+    app.login().await;
+    let invite_link = invite(&app, "ursula@example.com", "viewer").await;
+
+    let response = app.api_client.get(invite_link).send().await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("You have been invited as viewer with ursula@example.com."));
+    assert!(html_page.contains(r#"name="password_check""#));
+}
+
+#[tokio::test]
+async fn invited_colleagues_can_log_in_with_the_invited_role() {
+    let app = spawn_app().await;
+    app.login().await;
+    let invite_link = invite(&app, "ursula@example.com", "editor").await;
+    app.post_logout().await;
+
+    let response = app
+        .post_accept_invite(&accept_form(&invite_link, "ursula"))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    let colleague = TestUser {
+        user_id: Uuid::nil(),
+        username: "ursula".into(),
+        password: PASSWORD.into(),
+        role: Role::Editor,
+    };
+    let response = app.login_as(&colleague).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains("You are signed in as editor."));
+    let user = sqlx::query!("SELECT email FROM users WHERE username = 'ursula'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(user.email.as_deref(), Some("ursula@example.com"));
+}
+
+#[tokio::test]
+async fn invite_links_can_only_be_used_once() {
+    let app = spawn_app().await;
+    app.login().await;
+    let invite_link = invite(&app, "ursula@example.com", "editor").await;
+    app.post_accept_invite(&accept_form(&invite_link, "ursula"))
+        .await;
+
+    let page = app
+        .api_client
+        .get(invite_link.clone())
+        .send()
+        .await
+        .unwrap();
+    let response = app
+        .post_accept_invite(&accept_form(&invite_link, "ursula2"))
+        .await;
+
+    assert_eq!(page.status().as_u16(), 401);
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn expired_invite_links_are_rejected() {
+    let app = spawn_app().await;
+    app.login().await;
+    let invite_link = invite(&app, "ursula@example.com", "editor").await;
+    sqlx::query!("UPDATE user_invites SET created_at = now() - interval '30 days'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let page = app
+        .api_client
+        .get(invite_link.clone())
+        .send()
+        .await
+        .unwrap();
+    let response = app
+        .post_accept_invite(&accept_form(&invite_link, "ursula"))
+        .await;
+
+    assert_eq!(page.status().as_u16(), 410);
+    assert_eq!(response.status().as_u16(), 410);
+}
+
+#[tokio::test]
+async fn passwords_must_match_and_follow_the_password_rules() {
+    let app = spawn_app().await;
+    app.login().await;
+    let invite_link = invite(&app, "ursula@example.com", "editor").await;
+
+    for (password, password_check, error) in [
+        (
+            PASSWORD,
+            "another-long-password",
+            "You entered two different passwords - the field values must match.",
+        ),
+        ("short", "short", "The new password is too short."),
+    ] {
+        let response = app
+            .post_accept_invite(&json!({
+                "token": token(&invite_link),
+                "username": "ursula",
+                "password": password,
+                "password_check": password_check,
+            }))
+            .await;
+        let retry = format!("/invites/accept?token={}", token(&invite_link));
+        assert_is_redirect_to(&response, &retry);
+
+        let html_page = app
+            .api_client
+            .get(invite_link.clone())
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert!(html_page.contains(error));
+    }
+}
+
+#[tokio::test]
+async fn usernames_with_markup_are_rejected() {
+    let app = spawn_app().await;
+    app.login().await;
+    let invite_link = invite(&app, "ursula@example.com", "editor").await;
+
+    let response = app
+        .post_accept_invite(&accept_form(&invite_link, "<script>alert(1)</script>"))
+        .await;
+
+    let retry = format!("/invites/accept?token={}", token(&invite_link));
+    assert_is_redirect_to(&response, &retry);
+    let html_page = app
+        .api_client
+        .get(invite_link)
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(!html_page.contains("<script>"));
+    assert!(html_page
+        .contains("Usernames must be 1 to 64 letters, digits, dots, dashes or underscores"));
+}
+
+#[tokio::test]
+async fn taken_usernames_do_not_use_up_the_invite() {
+    let app = spawn_app().await;
+    app.login().await;
+    let invite_link = invite(&app, "ursula@example.com", "editor").await;
+
+    let response = app
+        .post_accept_invite(&accept_form(&invite_link, &app.test_user.username))
+        .await;
+    assert_eq!(response.status().as_u16(), 303);
+    let response = app
+        .post_accept_invite(&accept_form(&invite_link, "ursula"))
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn colleagues_with_an_account_cannot_be_invited() {
+    let app = spawn_app().await;
+    app.login().await;
+    let invite_link = invite(&app, "ursula@example.com", "editor").await;
+    app.post_accept_invite(&accept_form(&invite_link, "ursula"))
+        .await;
+
+    let response = app
+        .post_invite_user(&json!({ "email": "ursula@example.com", "role": "owner" }))
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/users/invite");
+    let html_page = app.get_invite_user_form_html().await;
+    assert!(html_page.contains("ursula@example.com already has an account."));
+}
+
+#[tokio::test]
+async fn colleagues_with_a_pending_invite_cannot_be_invited_again() {
+    let app = spawn_app().await;
+    app.login().await;
+    invite(&app, "ursula@example.com", "editor").await;
+
+    let response = app
+        .post_invite_user(&json!({ "email": "ursula@example.com", "role": "owner" }))
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/users/invite");
+    let html_page = app.get_invite_user_form_html().await;
+    assert!(html_page.contains("ursula@example.com has already been invited."));
+}
+
+#[tokio::test]
+async fn an_invite_that_could_not_be_sent_can_be_retried() {
+    let app = spawn_app().await;
+    app.login().await;
+    {
+        let _mock_guard = Mock::given(path("/email"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(500))
+            .named("Failing invite email")
+            .expect(1)
+            .mount_as_scoped(&app.email_server)
+            .await;
+        let response = app
+            .post_invite_user(&json!({ "email": "ursula@example.com", "role": "editor" }))
+            .await;
+        assert_eq!(response.status().as_u16(), 500);
+    }
+
+    let invite_link = invite(&app, "ursula@example.com", "editor").await;
+
+    let response = app
+        .post_accept_invite(&accept_form(&invite_link, "ursula"))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn an_invite_to_an_address_taken_since_cannot_be_accepted() {
+    let app = spawn_app().await;
+    app.login().await;
+    let invite_link = invite(&app, "ursula@example.com", "editor").await;
+    sqlx::query!(
+        "UPDATE users SET email = 'ursula@example.com' WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let response = app
+        .post_accept_invite(&accept_form(&invite_link, "ursula"))
+        .await;
+
+    let retry = format!("/invites/accept?token={}", token(&invite_link));
+    assert_is_redirect_to(&response, &retry);
+    let html_page = app
+        .api_client
+        .get(invite_link)
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("Another account already uses ursula@example.com"));
+}
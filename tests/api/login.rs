@@ -1,6 +1,9 @@
+use std::time::{Duration, Instant};
+
 use serde_json::json;
+use zero2prod::configuration::ThrottleStoreKind;
 
-use crate::helpers::{assert_is_redirect_to, spawn_app};
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
 
 #[tokio::test]
 async fn an_error_flash_message_is_set_on_failure() {
@@ -35,3 +38,235 @@ async fn redirect_to_admin_dashboard_after_login_successful() {
     let html_page = app.get_admin_dashboard_html().await;
     assert!(html_page.contains(&format!("Welcome {}", app.test_user.username)))
 }
+
+async fn fail_login(app: &TestApp, username: &str) {
+    let response = app
+        .post_login(&json!({
+            "username": username,
+            "password": "random-password"
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn failures_past_the_free_ones_delay_the_next_attempt() {
+    let app = spawn_app_with(|c| {
+        c.login_throttle.free_failures = 1;
+        c.login_throttle.base_delay = Duration::from_millis(300);
+    })
+    .await;
+    fail_login(&app, &app.test_user.username).await;
+    fail_login(&app, &app.test_user.username).await;
+
+    // Even the right password is turned away until the delay has passed
+    let response = app.login().await;
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("Too many failed login attempts - try again in 1 second"));
+
+    tokio::time::sleep(Duration::from_millis(700)).await;
+    let response = app.login().await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn too_many_failures_lock_the_username_out() {
+    let app = spawn_app_with(|c| c.login_throttle.max_failures = 3).await;
+    for _ in 0..3 {
+        fail_login(&app, &app.test_user.username).await;
+    }
+
+    let response = app.login().await;
+
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("Too many failed login attempts - try again in 15 minutes"));
+}
+
+#[tokio::test]
+async fn a_successful_login_forgets_the_failures() {
+    let app = spawn_app_with(|c| c.login_throttle.max_failures = 3).await;
+    for _ in 0..2 {
+        fail_login(&app, &app.test_user.username).await;
+    }
+    app.login().await;
+    app.post_logout().await;
+
+    for _ in 0..2 {
+        fail_login(&app, &app.test_user.username).await;
+    }
+    let response = app.login().await;
+
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn too_many_failures_lock_the_client_ip_out() {
+    let app = spawn_app_with(|c| c.login_throttle.max_failures_per_ip = 2).await;
+    fail_login(&app, "first-guess").await;
+    fail_login(&app, "second-guess").await;
+
+    let response = app.login().await;
+
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("Too many failed login attempts"));
+}
+
+#[tokio::test]
+async fn the_client_ip_can_come_from_a_trusted_proxy() {
+    let app = spawn_app_with(|c| {
+        c.login_throttle.max_failures_per_ip = 1;
+        c.application.client_ip_header = Some("X-Forwarded-For".into());
+    })
+    .await;
+    let csrf_token = app.csrf_token().await;
+    let post_login = |forwarded_for: &'static str, password: &str| {
+        app.api_client
+            .post(format!("http://{}/login", &app.address))
+            .header("X-Forwarded-For", forwarded_for)
+            .form(&json!({
+                "username": "someone-else",
+                "password": password,
+                "csrf_token": &csrf_token,
+            }))
+            .send()
+    };
+    post_login("1.1.1.1, 10.0.0.1", "random-password")
+        .await
+        .unwrap();
+
+    // Only the address appended by the proxy counts
+    post_login("10.0.0.1", "random-password").await.unwrap();
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("Too many failed login attempts"));
+    post_login("10.0.0.2", "random-password").await.unwrap();
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("Authentication failed"));
+}
+
+#[tokio::test]
+async fn failures_can_be_counted_in_postgres() {
+    let app = spawn_app_with(|c| {
+        c.login_throttle.store = ThrottleStoreKind::Postgres;
+        c.login_throttle.max_failures = 2;
+    })
+    .await;
+    for _ in 0..2 {
+        fail_login(&app, &app.test_user.username).await;
+    }
+
+    let response = app.login().await;
+
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("Too many failed login attempts - try again in 15 minutes"));
+    let failures = sqlx::query!("SELECT throttle_key, failures FROM login_failures")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(failures.len(), 2);
+    assert!(failures.iter().all(|r| r.failures == 2));
+}
+
+#[tokio::test]
+async fn parallel_attempts_cannot_get_past_the_lockout() {
+    let app = spawn_app_with(|c| {
+        c.login_throttle.store = ThrottleStoreKind::Postgres;
+        c.login_throttle.max_failures = 3;
+    })
+    .await;
+    let username = &app.test_user.username;
+
+    // Each would have been let through before the first failure was counted
+    tokio::join!(
+        fail_login(&app, username),
+        fail_login(&app, username),
+        fail_login(&app, username),
+        fail_login(&app, username),
+        fail_login(&app, username),
+        fail_login(&app, username),
+    );
+
+    let failures = sqlx::query!("SELECT throttle_key, failures FROM login_failures")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(failures.len(), 2);
+    assert!(failures.iter().all(|r| r.failures == 3));
+}
+
+async fn stored_password_hash(app: &TestApp) -> String {
+    sqlx::query!(
+        "SELECT password_hash FROM users WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .password_hash
+}
+
+#[tokio::test]
+async fn logging_in_rehashes_a_weaker_password_hash() {
+    // The test user is stored with the argon2 defaults, m=4096,t=3,p=1
+    let app = spawn_app().await;
+    let old_hash = stored_password_hash(&app).await;
+
+    let response = app.login().await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let new_hash = loop {
+        let hash = stored_password_hash(&app).await;
+        if hash != old_hash {
+            break hash;
+        }
+        assert!(Instant::now() < deadline, "The password was not rehashed");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    };
+    assert!(new_hash.starts_with("$argon2id$v=19$m=15000,t=2,p=1$"));
+    app.post_logout().await;
+    let response = app.login().await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn password_hashes_as_strong_as_configured_are_kept() {
+    let app = spawn_app_with(|c| {
+        c.password_hashing.memory_kib = 4096;
+        c.password_hashing.iterations = 1;
+    })
+    .await;
+    let old_hash = stored_password_hash(&app).await;
+
+    let response = app.login().await;
+
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(stored_password_hash(&app).await, old_hash);
+}
+
+#[tokio::test]
+async fn every_throttle_store_locks_usernames_out() {
+    for store in [ThrottleStoreKind::Redis, ThrottleStoreKind::Postgres] {
+        let app = spawn_app_with(|c| {
+            c.login_throttle.store = store;
+            c.login_throttle.max_failures = 2;
+        })
+        .await;
+        for _ in 0..2 {
+            fail_login(&app, &app.test_user.username).await;
+        }
+
+        let response = app.login().await;
+
+        assert_is_redirect_to(&response, "/login");
+        let html_page = app.get_login_html().await;
+        assert!(
+            html_page.contains("Too many failed login attempts"),
+            "{store:?}"
+        );
+    }
+}
@@ -1,8 +1,19 @@
 mod admin_dashboard;
+mod admin_users;
 mod change_password;
+mod csrf;
+mod delivery_failures;
+mod dev_mailbox;
 mod health_check;
 mod helpers;
+mod invites;
 mod login;
 mod newsletter;
+mod password_reset;
+mod roles;
+mod sessions;
+mod shutdown;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_unsubscribe;
+mod two_factor;
@@ -1,61 +1,19 @@
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use chrono::{Days, Utc};
-use fake::{
-    faker::{internet::en::SafeEmail, name::en::Name},
-    Fake,
-};
 use serde_json::json;
 use tokio::join;
 use uuid::Uuid;
 use wiremock::{
     matchers::{any, method, path},
-    Mock, ResponseTemplate,
+    Mock, Request, ResponseTemplate,
 };
+use zero2prod::issue_delivery_worker::ExecutionOutcome;
 
-use crate::helpers::{assert_is_redirect_to, spawn_app, ConfirmationLinks, TestApp};
-
-async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
-    let name: String = Name().fake();
-    let email: String = SafeEmail().fake();
-    let body = serde_urlencoded::to_string(&json!({
-        "name": name,
-        "email": email
-    }))
-    .unwrap();
-
-    let _mock_guard = Mock::given(path("/email"))
-        .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
-        .named("Create unconfirmed subscriber")
-        .expect(1)
-        .mount_as_scoped(&app.email_server)
-        .await;
-
-    app.post_subscriptions(body)
-        .await
-        .error_for_status()
-        .unwrap();
-
-    let email_request = app
-        .email_server
-        .received_requests()
-        .await
-        .unwrap()
-        .pop()
-        .unwrap();
-
-    app.get_confirmation_links(&email_request)
-}
-
-async fn create_confirmed_subscriber(app: &TestApp) {
-    let confirmation_link = create_unconfirmed_subscriber(app).await;
-    reqwest::get(confirmation_link.html)
-        .await
-        .unwrap()
-        .error_for_status()
-        .unwrap();
-}
+use crate::helpers::{
+    accept_batch, assert_is_redirect_to, create_confirmed_subscriber,
+    create_unconfirmed_subscriber, spawn_app, spawn_app_with,
+};
 
 #[tokio::test]
 async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
@@ -92,9 +50,9 @@ async fn newsletters_are_delivered_to_confirmed_subscribers() {
     create_confirmed_subscriber(&app).await;
     app.login().await;
 
-    Mock::given(path("/email"))
+    Mock::given(path("/email/batch"))
         .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
+        .respond_with(accept_batch)
         .expect(1)
         .mount(&app.email_server)
         .await;
@@ -175,9 +133,9 @@ async fn newsletter_creation_is_idempotent() {
     create_confirmed_subscriber(&app).await;
     app.login().await;
 
-    Mock::given(path("/email"))
+    Mock::given(path("/email/batch"))
         .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
+        .respond_with(accept_batch)
         .expect(1)
         .mount(&app.email_server)
         .await;
@@ -213,9 +171,11 @@ async fn concurrent_form_submission_is_handled_gracefully() {
     create_confirmed_subscriber(&app).await;
     app.login().await;
 
-    Mock::given(path("/email"))
+    Mock::given(path("/email/batch"))
         .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(2)))
+        .respond_with(move |request: &Request| {
+            accept_batch(request).set_delay(Duration::from_secs(2))
+        })
         .expect(1)
         .mount(&app.email_server)
         .await;
@@ -245,7 +205,7 @@ async fn transient_errors_are_retried() {
     app.login().await;
 
     // First response is a failure
-    Mock::given(path("/email"))
+    Mock::given(path("/email/batch"))
         .and(method("POST"))
         .respond_with(ResponseTemplate::new(500))
         .up_to_n_times(1)
@@ -254,9 +214,9 @@ async fn transient_errors_are_retried() {
         .await;
 
     // Second response is a success
-    Mock::given(path("/email"))
+    Mock::given(path("/email/batch"))
         .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
+        .respond_with(accept_batch)
         .up_to_n_times(1)
         .expect(1)
         .mount(&app.email_server)
@@ -274,6 +234,301 @@ async fn transient_errors_are_retried() {
     app.dispatch_all_pending_emails().await;
 }
 
+#[tokio::test]
+async fn newsletters_are_delivered_to_all_subscribers_in_one_batch() {
+    let app = spawn_app().await;
+    for _ in 0..3 {
+        create_confirmed_subscriber(&app).await;
+    }
+    app.login().await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(accept_batch)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_publish_newsletter(&json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    }))
+    .await;
+    app.dispatch_all_pending_emails().await;
+
+    let batch_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let batch: serde_json::Value = serde_json::from_slice(&batch_request.body).unwrap();
+    assert_eq!(batch.as_array().unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn rejected_recipients_fail_without_resending_to_the_others() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.login().await;
+
+    // The provider accepts the first message of the batch and rejects the second one
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+            { "ErrorCode": 0, "Message": "OK" },
+            { "ErrorCode": 406, "Message": "Inactive recipient" },
+        ])))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_publish_newsletter(&json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    }))
+    .await;
+    app.execute_task().await;
+
+    let batch_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let batch: serde_json::Value = serde_json::from_slice(&batch_request.body).unwrap();
+    let rejected_email = batch[1]["To"].as_str().unwrap();
+    let remaining = sqlx::query!("SELECT subscriber_email FROM issue_delivery_queue")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(remaining.is_empty());
+    let failure = sqlx::query!("SELECT subscriber_email, n_attempts FROM issue_delivery_failures")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(failure.subscriber_email, rejected_email);
+    assert_eq!(failure.n_attempts, 1);
+}
+
+#[tokio::test]
+async fn workers_claim_at_most_the_configured_batch_size() {
+    let app = spawn_app_with(|c| c.worker.batch_size = 2).await;
+    for _ in 0..3 {
+        create_confirmed_subscriber(&app).await;
+    }
+    app.login().await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(accept_batch)
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_publish_newsletter(&json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    }))
+    .await;
+    app.dispatch_all_pending_emails().await;
+
+    let batch_sizes: Vec<_> = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter(|r| r.url.path() == "/email/batch")
+        .map(|r| {
+            let batch: serde_json::Value = serde_json::from_slice(&r.body).unwrap();
+            batch.as_array().unwrap().len()
+        })
+        .collect();
+    assert_eq!(batch_sizes, vec![2, 1]);
+}
+
+#[tokio::test]
+async fn a_batch_being_sent_is_left_to_the_worker_that_claimed_it() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login().await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(move |request: &Request| {
+            accept_batch(request).set_delay(Duration::from_millis(500))
+        })
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_publish_newsletter(&json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    }))
+    .await;
+
+    let (first, second) = join!(app.execute_task(), async {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        app.execute_task().await
+    });
+
+    assert!(matches!(first, ExecutionOutcome::TaskCompleted));
+    assert!(matches!(second, ExecutionOutcome::EmptyQueue));
+    let n_queued = sqlx::query!(r#"SELECT count(*) AS "n!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_queued, 0);
+}
+
+#[tokio::test]
+async fn the_claim_on_a_batch_is_renewed_while_it_is_sent() {
+    let app = spawn_app_with(|c| c.worker.claim_timeout = Duration::from_secs(1)).await;
+    create_confirmed_subscriber(&app).await;
+    app.login().await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(move |request: &Request| {
+            accept_batch(request).set_delay(Duration::from_millis(2500))
+        })
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_publish_newsletter(&json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    }))
+    .await;
+
+    // The batch takes longer to send than the claim lasts
+    let (first, second) = join!(app.execute_task(), async {
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        app.execute_task().await
+    });
+
+    assert!(matches!(first, ExecutionOutcome::TaskCompleted));
+    assert!(matches!(second, ExecutionOutcome::EmptyQueue));
+}
+
+#[tokio::test]
+async fn delivery_respects_the_messages_per_second_cap() {
+    let app = spawn_app_with(|c| c.worker.max_messages_per_second = Some(1)).await;
+    for _ in 0..3 {
+        create_confirmed_subscriber(&app).await;
+    }
+    app.login().await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(accept_batch)
+        .expect(3)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_publish_newsletter(&json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    }))
+    .await;
+
+    let start = Instant::now();
+    let mut n_rate_limited = 0;
+    loop {
+        match app.execute_task().await {
+            ExecutionOutcome::TaskCompleted => {}
+            ExecutionOutcome::RateLimited => {
+                n_rate_limited += 1;
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+            ExecutionOutcome::EmptyQueue => break,
+        }
+    }
+
+    // Three messages at one per second need at least three distinct one-second windows
+    assert!(start.elapsed() > Duration::from_secs(1));
+    assert!(n_rate_limited > 0);
+}
+
+#[tokio::test]
+async fn idle_workers_wake_up_as_soon_as_an_issue_is_published() {
+    let app = spawn_app_with(|c| c.worker.empty_queue_sleep = Duration::from_secs(600)).await;
+    create_confirmed_subscriber(&app).await;
+    app.login().await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(accept_batch)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.spawn_worker();
+    // Give the worker time to find the queue empty and go to sleep
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    app.post_publish_newsletter(&json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    }))
+    .await;
+
+    app.wait_for_batches(1, Duration::from_secs(5)).await;
+}
+
+#[tokio::test]
+async fn idle_workers_wake_up_when_a_retry_is_due() {
+    let app = spawn_app_with(|c| {
+        c.worker.empty_queue_sleep = Duration::from_secs(600);
+        c.worker.retry.base_delay = Duration::from_millis(500);
+    })
+    .await;
+    create_confirmed_subscriber(&app).await;
+    app.login().await;
+
+    // Each failure schedules the next attempt further in the future
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(2)
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(accept_batch)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.spawn_worker();
+    app.post_publish_newsletter(&json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    }))
+    .await;
+
+    app.wait_for_batches(3, Duration::from_secs(5)).await;
+}
+
 #[tokio::test]
 async fn idempotency_key_older_than_one_day_is_pruned() {
     let app = spawn_app().await;
@@ -0,0 +1,206 @@
+use std::time::{Duration, Instant};
+
+use reqwest::{redirect::Policy, Client, Url};
+use serde_json::json;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+
+const EMAIL: &str = "admin@example.com";
+const NEW_PASSWORD: &str = "a-brand-new-password";
+const SAME_RESPONSE: &str =
+    "If an account uses that email address, a link to reset its password is on its way.";
+
+async fn give_test_user_an_email(app: &TestApp) {
+    sqlx::query!(
+        "UPDATE users SET email = $1 WHERE user_id = $2",
+        EMAIL,
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+/// Asks for a reset link for the test user and returns it once the email
+/// has been sent in the background.
+async fn request_reset_link(app: &TestApp) -> Url {
+    give_test_user_an_email(app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let response = app.post_forgot_password(&json!({ "email": EMAIL })).await;
+    assert_is_redirect_to(&response, "/password/forgot");
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        if let Some(email_request) = app.email_server.received_requests().await.unwrap().pop() {
+            return app.get_email_link(&email_request);
+        }
+        assert!(Instant::now() < deadline, "No reset email was sent");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+fn token(reset_link: &Url) -> String {
+    reset_link
+        .query_pairs()
+        .find(|(name, _)| name == "token")
+        .unwrap()
+        .1
+        .into_owned()
+}
+
+fn reset_form(reset_link: &Url) -> serde_json::Value {
+    json!({
+        "token": token(reset_link),
+        "new_password": NEW_PASSWORD,
+        "new_password_check": NEW_PASSWORD,
+    })
+}
+
+#[tokio::test]
+async fn the_login_page_links_to_the_forgot_password_form() {
+    let app = spawn_app().await;
+
+    let html_page = app.get_login_html().await;
+
+    assert!(html_page.contains(r#"href="/password/forgot""#));
+}
+
+#[tokio::test]
+async fn unknown_emails_get_the_same_response_and_no_email() {
+    let app = spawn_app().await;
+    give_test_user_an_email(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    for email in ["nobody@example.com", "not-an-email"] {
+        let response = app.post_forgot_password(&json!({ "email": email })).await;
+        assert_is_redirect_to(&response, "/password/forgot");
+        let html_page = app.get_forgot_password_html().await;
+        assert!(html_page.contains(SAME_RESPONSE));
+    }
+    tokio::time::sleep(Duration::from_millis(200)).await;
+}
+
+#[tokio::test]
+async fn known_emails_get_the_same_response_and_a_reset_link() {
+    let app = spawn_app().await;
+
+    let reset_link = request_reset_link(&app).await;
+
+    let html_page = app.get_forgot_password_html().await;
+    assert!(html_page.contains(SAME_RESPONSE));
+    assert_eq!(reset_link.path(), "/password/reset");
+    let response = app.api_client.get(reset_link).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn the_reset_link_sets_a_new_password() {
+    let app = spawn_app().await;
+    let reset_link = request_reset_link(&app).await;
+
+    let response = app.post_reset_password(&reset_form(&reset_link)).await;
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("Your password has been reset - you can now log in."));
+
+    let response = app.login().await;
+    assert_is_redirect_to(&response, "/login");
+    let response = app
+        .post_login(&json!({
+            "username": &app.test_user.username,
+            "password": NEW_PASSWORD,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn the_reset_link_can_only_be_used_once() {
+    let app = spawn_app().await;
+    let reset_link = request_reset_link(&app).await;
+
+    app.post_reset_password(&reset_form(&reset_link)).await;
+    let response = app.post_reset_password(&reset_form(&reset_link)).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+    let response = app.api_client.get(reset_link).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn an_expired_reset_link_is_rejected() {
+    let app = spawn_app_with(|c| c.application.password_reset_ttl = Duration::ZERO).await;
+    let reset_link = request_reset_link(&app).await;
+
+    let response = app.post_reset_password(&reset_form(&reset_link)).await;
+
+    assert_eq!(response.status().as_u16(), 410);
+    let response = app.login().await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn mismatched_passwords_keep_the_reset_link_valid() {
+    let app = spawn_app().await;
+    let reset_link = request_reset_link(&app).await;
+
+    let response = app
+        .post_reset_password(&json!({
+            "token": token(&reset_link),
+            "new_password": NEW_PASSWORD,
+            "new_password_check": "another-new-password",
+        }))
+        .await;
+
+    let location = response
+        .headers()
+        .get("Location")
+        .unwrap()
+        .to_str()
+        .unwrap();
+    assert!(location.starts_with("/password/reset?token="));
+    let response = app.api_client.get(reset_link.clone()).send().await.unwrap();
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("You entered two different new passwords"));
+    let response = app.post_reset_password(&reset_form(&reset_link)).await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn resetting_the_password_logs_out_every_session() {
+    let app = spawn_app().await;
+    app.login().await;
+    let reset_link = request_reset_link(&app).await;
+
+    let other_client = Client::builder()
+        .redirect(Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap();
+    let response = other_client
+        .post(format!("http://{}/password/reset", &app.address))
+        .form(&reset_form(&reset_link))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+    // The stale session cookie has been cleared
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
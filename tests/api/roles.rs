@@ -0,0 +1,97 @@
+use serde_json::json;
+use uuid::Uuid;
+use zero2prod::domain::Role;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+
+fn newsletter_request_body() -> serde_json::Value {
+    json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    })
+}
+
+#[tokio::test]
+async fn the_dashboard_shows_the_role_of_the_user() {
+    let app = spawn_app().await;
+    app.login_with_role(Role::Editor).await;
+
+    let html_page = app.get_admin_dashboard_html().await;
+
+    assert!(html_page.contains("You are signed in as editor."));
+    assert!(html_page.contains("Send a Newsletter"));
+}
+
+#[tokio::test]
+async fn viewers_are_not_offered_to_send_newsletters() {
+    let app = spawn_app().await;
+    app.login_with_role(Role::Viewer).await;
+
+    let html_page = app.get_admin_dashboard_html().await;
+
+    assert!(html_page.contains("You are signed in as viewer."));
+    assert!(!html_page.contains("Send a Newsletter"));
+}
+
+#[tokio::test]
+async fn viewers_cannot_publish_newsletters() {
+    let app = spawn_app().await;
+    app.login_with_role(Role::Viewer).await;
+
+    let form = app.get_publish_newsletter().await;
+    let response = app
+        .post_publish_newsletter(&newsletter_request_body())
+        .await;
+
+    assert_eq!(form.status().as_u16(), 403);
+    assert_eq!(response.status().as_u16(), 403);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("You do not have permission to do this."));
+    let issues = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(issues.is_empty());
+}
+
+#[tokio::test]
+async fn editors_can_publish_newsletters() {
+    let app = spawn_app().await;
+    app.login_with_role(Role::Editor).await;
+
+    let response = app
+        .post_publish_newsletter(&newsletter_request_body())
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/newsletters");
+}
+
+#[tokio::test]
+async fn viewers_can_see_delivery_failures_but_not_reenqueue_them() {
+    let app = spawn_app().await;
+    app.login_with_role(Role::Viewer).await;
+
+    let page = app.get_delivery_failures().await;
+    let response = app
+        .post_reenqueue_delivery_failures(&[("failure_id", Uuid::new_v4().to_string())])
+        .await;
+
+    assert_eq!(page.status().as_u16(), 200);
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn deleted_users_are_logged_out() {
+    let app = spawn_app().await;
+    let user = app.login_with_role(Role::Editor).await;
+
+    sqlx::query!("DELETE FROM users WHERE user_id = $1", user.user_id)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
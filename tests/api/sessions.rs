@@ -0,0 +1,392 @@
+use std::time::Duration;
+
+use reqwest::{header::SET_COOKIE, redirect::Policy, Client, Response};
+use secrecy::Secret;
+use serde_json::json;
+use zero2prod::{configuration::SessionStoreKind, domain::Role};
+
+use crate::helpers::{assert_is_redirect_to, csrf_token_in, spawn_app, spawn_app_with, TestApp};
+
+const OTHER_USER_AGENT: &str = "Other <Browser>/1.0";
+
+/// Logs the test user in from a second browser.
+async fn login_elsewhere(app: &TestApp) -> Client {
+    let client = Client::builder()
+        .redirect(Policy::none())
+        .cookie_store(true)
+        .user_agent(OTHER_USER_AGENT)
+        .build()
+        .unwrap();
+    let login_form = client
+        .get(format!("http://{}/login", &app.address))
+        .send()
+        .await
+        .unwrap();
+    let csrf_token = csrf_token_in(&login_form.text().await.unwrap());
+    let response = client
+        .post(format!("http://{}/login", &app.address))
+        .form(&json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+            "csrf_token": csrf_token,
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    client
+}
+
+/// Logs the test user in and returns their session cookie, to be sent by
+/// hand, along with the CSRF token of the session.
+async fn session_cookie(app: &TestApp) -> (String, String) {
+    let client = Client::builder().redirect(Policy::none()).build().unwrap();
+    let login_form = client
+        .get(format!("http://{}/login", &app.address))
+        .send()
+        .await
+        .unwrap();
+    let cookie = cookie_value(&login_form);
+    let csrf_token = csrf_token_in(&login_form.text().await.unwrap());
+    let response = client
+        .post(format!("http://{}/login", &app.address))
+        .header("Cookie", cookie)
+        .form(&json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+            "csrf_token": &csrf_token,
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    (cookie_value(&response), csrf_token)
+}
+
+fn cookie_value(response: &Response) -> String {
+    let cookie = session_cookies(response).next().unwrap();
+    cookie.split(';').next().unwrap().to_string()
+}
+
+fn session_cookies(response: &Response) -> impl Iterator<Item = &str> {
+    response
+        .headers()
+        .get_all(SET_COOKIE)
+        .iter()
+        .map(|value| value.to_str().unwrap())
+        .filter(|cookie| cookie.starts_with("session_id="))
+}
+
+async fn get_dashboard(app: &TestApp, client: &Client) -> Response {
+    client
+        .get(format!("http://{}/admin/dashboard", &app.address))
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn other_public_id(app: &TestApp) -> String {
+    let html_page = app.get_sessions_html().await;
+    html_page
+        .split(r#"name="session_id" value=""#)
+        .nth(1)
+        .and_then(|s| s.split_once('"'))
+        .unwrap()
+        .0
+        .to_string()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_your_sessions() {
+    let app = spawn_app().await;
+
+    let response = app.get_sessions().await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_sessions_page_lists_every_session() {
+    let app = spawn_app().await;
+    app.login().await;
+    login_elsewhere(&app).await;
+
+    let html_page = app.get_sessions_html().await;
+
+    assert!(html_page.contains("This session"));
+    assert!(html_page.contains("Other &lt;Browser&gt;/1.0"));
+    assert!(html_page.contains("127.0.0.1"));
+    assert_eq!(html_page.matches(r#"name="session_id""#).count(), 1);
+}
+
+#[tokio::test]
+async fn revoking_a_session_logs_it_out() {
+    let app = spawn_app().await;
+    app.login().await;
+    let other_client = login_elsewhere(&app).await;
+
+    let response = app
+        .post_revoke_session(&json!({ "session_id": other_public_id(&app).await }))
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/sessions");
+    let html_page = app.get_sessions_html().await;
+    assert!(html_page.contains("The session has been revoked."));
+    assert!(!html_page.contains("Other &lt;Browser&gt;/1.0"));
+    let response = get_dashboard(&app, &other_client).await;
+    assert_is_redirect_to(&response, "/login");
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn sessions_of_other_users_cannot_be_revoked() {
+    let app = spawn_app().await;
+    let other_client = login_elsewhere(&app).await;
+    app.login().await;
+    let public_id = other_public_id(&app).await;
+    app.post_logout().await;
+    let colleague = app.login_with_role(Role::Owner).await;
+    assert_ne!(colleague.user_id, app.test_user.user_id);
+
+    app.post_revoke_session(&json!({ "session_id": public_id }))
+        .await;
+
+    let response = get_dashboard(&app, &other_client).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn logging_out_everywhere_ends_every_session() {
+    let app = spawn_app().await;
+    app.login().await;
+    let other_client = login_elsewhere(&app).await;
+
+    let response = app.post_log_out_everywhere().await;
+
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("You have been logged out of every session."));
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+    let response = get_dashboard(&app, &other_client).await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn changing_the_password_logs_out_every_other_session() {
+    let app = spawn_app().await;
+    app.login().await;
+    let other_client = login_elsewhere(&app).await;
+    let new_password = "a-brand-new-password";
+
+    let response = app
+        .post_change_password(&json!({
+            "current_password": &app.test_user.password,
+            "new_password": new_password,
+            "new_password_check": new_password,
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/password");
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = get_dashboard(&app, &other_client).await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn idle_sessions_expire() {
+    let app = spawn_app_with(|c| c.session.idle_timeout = Duration::from_secs(2)).await;
+    app.login().await;
+
+    tokio::time::sleep(Duration::from_millis(2500)).await;
+
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("Your session has expired - please log in again."));
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn activity_keeps_a_session_alive() {
+    for store in [
+        SessionStoreKind::Redis,
+        SessionStoreKind::Postgres,
+        SessionStoreKind::Memory,
+    ] {
+        let app = spawn_app_with(|c| {
+            c.session.store = store;
+            c.session.idle_timeout = Duration::from_secs(2);
+        })
+        .await;
+        app.login().await;
+
+        for _ in 0..4 {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            let response = app.get_admin_dashboard().await;
+            assert_eq!(response.status().as_u16(), 200, "{store:?}");
+        }
+    }
+}
+
+#[tokio::test]
+async fn unchanged_sessions_only_have_their_expiry_refreshed() {
+    let app = spawn_app_with(|c| c.session.store = SessionStoreKind::Postgres).await;
+    app.login().await;
+    let stored = sqlx::query!("SELECT session, expires_at FROM sessions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+
+    let response = app.get_admin_dashboard().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let refreshed = sqlx::query!("SELECT session, expires_at FROM sessions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(refreshed.session, stored.session);
+    assert!(refreshed.expires_at > stored.expires_at);
+}
+
+#[tokio::test]
+async fn sessions_expire_at_the_end_of_their_lifetime_despite_activity() {
+    let app = spawn_app_with(|c| c.session.lifetime = Duration::from_secs(3)).await;
+    app.login().await;
+
+    for _ in 0..2 {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let response = app.get_admin_dashboard().await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("Your session has expired - please log in again."));
+}
+
+#[tokio::test]
+async fn an_unknown_session_cookie_is_cleared() {
+    let app = spawn_app().await;
+    let (cookie, csrf_token) = session_cookie(&app).await;
+    let client = Client::builder().redirect(Policy::none()).build().unwrap();
+    // Logging out removes the session from the store
+    client
+        .post(format!("http://{}/admin/logout", &app.address))
+        .header("Cookie", &cookie)
+        .form(&json!({ "csrf_token": csrf_token }))
+        .send()
+        .await
+        .unwrap();
+
+    let response = client
+        .get(format!("http://{}/", &app.address))
+        .header("Cookie", &cookie)
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let removal = session_cookies(&response).next().unwrap();
+    assert!(removal.contains("Max-Age=0"));
+    let response = client
+        .get(format!("http://{}/admin/dashboard", &app.address))
+        .header("Cookie", &cookie)
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn public_pages_work_while_the_session_store_is_down() {
+    let app = spawn_app().await;
+    let (cookie, _) = session_cookie(&app).await;
+    let app = spawn_app_with(|c| {
+        c.session.store = SessionStoreKind::Redis;
+        c.redis_uri = Secret::new("redis://127.0.0.1:1".into());
+    })
+    .await;
+    let client = Client::builder().redirect(Policy::none()).build().unwrap();
+
+    let response = client
+        .get(format!("http://{}/", &app.address))
+        .header("Cookie", &cookie)
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    // The session may still be valid once the store is back
+    assert_eq!(session_cookies(&response).count(), 0);
+    let response = client
+        .get(format!("http://{}/admin/dashboard", &app.address))
+        .header("Cookie", &cookie)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 500);
+}
+
+#[tokio::test]
+async fn every_session_store_keeps_users_logged_in() {
+    for store in [
+        SessionStoreKind::Redis,
+        SessionStoreKind::Postgres,
+        SessionStoreKind::Memory,
+    ] {
+        let app = spawn_app_with(|c| c.session.store = store).await;
+
+        app.login().await;
+        let response = app.get_admin_dashboard().await;
+        assert_eq!(response.status().as_u16(), 200, "{store:?}");
+        app.post_logout().await;
+        let response = app.get_admin_dashboard().await;
+        assert_is_redirect_to(&response, "/login");
+    }
+}
+
+#[tokio::test]
+async fn expired_sessions_are_pruned_from_postgres() {
+    let app = spawn_app_with(|c| c.session.store = SessionStoreKind::Postgres).await;
+    app.login().await;
+    sqlx::query!("UPDATE sessions SET expires_at = now() - interval '1 second'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    assert_eq!(app.prune_expired_sessions().await, 1);
+    assert_eq!(app.prune_expired_sessions().await, 0);
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("Your session has expired - please log in again."));
+}
+
+#[tokio::test]
+async fn public_pages_ignore_an_expired_session() {
+    let app = spawn_app_with(|c| c.session.idle_timeout = Duration::from_secs(1)).await;
+    // Gone from the store once its time to live, two seconds longer, has passed
+    for wait in [Duration::from_millis(1500), Duration::from_millis(3500)] {
+        app.login().await;
+        tokio::time::sleep(wait).await;
+
+        let response = app
+            .api_client
+            .get(format!("http://{}/", &app.address))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status().as_u16(), 200, "after {wait:?}");
+        let removal = session_cookies(&response).next().unwrap();
+        assert!(removal.contains("Max-Age=0"));
+    }
+}
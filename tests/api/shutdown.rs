@@ -0,0 +1,122 @@
+use std::time::Duration;
+
+use serde_json::json;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, Request, ResponseTemplate,
+};
+
+use crate::helpers::{accept_batch, create_confirmed_subscriber, spawn_app, TestApp};
+
+/// Waits until the email server has received a request, without waiting for the response.
+async fn wait_for_email_request(app: &TestApp) {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while app
+            .email_server
+            .received_requests()
+            .await
+            .unwrap()
+            .is_empty()
+        {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("The email server did not receive a request in time");
+}
+
+#[tokio::test]
+async fn in_flight_requests_complete_after_shutdown_starts() {
+    let mut app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(500)))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let request = app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into());
+    let (response, _) = tokio::join!(request, async {
+        wait_for_email_request(&app).await;
+        app.shutdown.cancel();
+    });
+
+    assert_eq!(response.status().as_u16(), 200);
+    tokio::time::timeout(Duration::from_secs(5), &mut app.server)
+        .await
+        .expect("The server did not stop in time")
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn new_connections_are_refused_after_shutdown() {
+    let mut app = spawn_app().await;
+
+    app.shutdown.cancel();
+    tokio::time::timeout(Duration::from_secs(5), &mut app.server)
+        .await
+        .expect("The server did not stop in time")
+        .unwrap()
+        .unwrap();
+
+    let result = reqwest::Client::new()
+        .get(format!("http://{}/health_check", app.address))
+        .send()
+        .await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn worker_finishes_its_current_batch_before_stopping() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login().await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(|request: &Request| {
+            accept_batch(request).set_delay(Duration::from_millis(500))
+        })
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let shutdown = CancellationToken::new();
+    let worker = app.spawn_stoppable_worker(shutdown.clone());
+    app.post_publish_newsletter(&json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    }))
+    .await;
+    app.wait_for_batches(1, Duration::from_secs(5)).await;
+    shutdown.cancel();
+
+    tokio::time::timeout(Duration::from_secs(5), worker)
+        .await
+        .expect("The worker did not stop in time")
+        .unwrap();
+    let queued = sqlx::query!("SELECT subscriber_email FROM issue_delivery_queue")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(queued.is_empty());
+}
+
+#[tokio::test]
+async fn idle_worker_stops_straight_away() {
+    let app = spawn_app().await;
+    let shutdown = CancellationToken::new();
+    let worker = app.spawn_stoppable_worker(shutdown.clone());
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    shutdown.cancel();
+
+    tokio::time::timeout(Duration::from_secs(1), worker)
+        .await
+        .expect("The worker did not stop in time")
+        .unwrap();
+}
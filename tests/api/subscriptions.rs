@@ -125,3 +125,173 @@ async fn subscribe_returns_a_422_when_fields_are_present_but_invalid() {
         );
     }
 }
+
+#[tokio::test]
+async fn subscribing_twice_while_pending_resends_a_new_confirmation_email() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_subscriptions(body.into()).await;
+    assert_eq!(200, response.status().as_u16());
+    let response = app.post_subscriptions(body.into()).await;
+    assert_eq!(200, response.status().as_u16());
+
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let first_links = app.get_confirmation_links(&email_requests[0]);
+    let second_links = app.get_confirmation_links(&email_requests[1]);
+    assert_ne!(first_links.html, second_links.html);
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions",)
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions.");
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn concurrent_first_subscriptions_both_succeed() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let (first, second) = tokio::join!(
+        app.post_subscriptions(body.into()),
+        app.post_subscriptions(body.into())
+    );
+
+    assert_eq!(200, first.status().as_u16());
+    assert_eq!(200, second.status().as_u16());
+    let saved = sqlx::query!("SELECT status FROM subscriptions",)
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions.");
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn subscribing_with_a_confirmed_email_sends_an_already_subscribed_email() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let confirmation_links = app.get_confirmation_links(&email_requests[0]);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let response = app.post_subscriptions(body.into()).await;
+    assert_eq!(200, response.status().as_u16());
+
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let email: serde_json::Value = serde_json::from_slice(&email_requests[1].body).unwrap();
+    assert!(email["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains("You are already subscribed"));
+    assert!(!email["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains("subscriptions/confirm"));
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions",)
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions.");
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].status, "confirmed");
+}
+
+#[tokio::test]
+async fn subscribing_with_an_unsubscribed_email_restarts_double_opt_in() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'",)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app.post_subscriptions(body.into()).await;
+    assert_eq!(200, response.status().as_u16());
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "pending_confirmation");
+
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let confirmation_links = app.get_confirmation_links(&email_requests[1]);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn subscribe_does_not_reveal_whether_an_email_is_already_subscribed() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let first_response = app.post_subscriptions(body.into()).await;
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let confirmation_links = app.get_confirmation_links(&email_requests[0]);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let second_response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(first_response.status(), second_response.status());
+    assert_eq!(
+        first_response.text().await.unwrap(),
+        second_response.text().await.unwrap()
+    );
+}
@@ -3,7 +3,7 @@ use wiremock::{
     Mock, ResponseTemplate,
 };
 
-use crate::helpers::spawn_app;
+use crate::helpers::{create_unconfirmed_subscriber, spawn_app, TestApp};
 
 #[tokio::test]
 async fn confirmations_without_token_are_rejected_with_a_400() {
@@ -66,3 +66,122 @@ async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
     assert_eq!(saved.name, "le guin");
     assert_eq!(saved.status, "confirmed");
 }
+
+async fn expire_subscription_tokens(app: &TestApp) {
+    sqlx::query!("UPDATE subscription_tokens SET created_at = created_at - interval '2 days'",)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn expired_confirmation_links_are_rejected_with_a_410() {
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+    expire_subscription_tokens(&app).await;
+
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 410);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("This confirmation link has expired."));
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn a_new_confirmation_link_can_be_requested_for_an_expired_one() {
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+    expire_subscription_tokens(&app).await;
+    let (_, subscription_token) = confirmation_links
+        .html
+        .query_pairs()
+        .find(|(key, _)| key == "subscription_token")
+        .unwrap();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .api_client
+        .post(format!(
+            "http://{}/subscriptions/confirm/resend",
+            app.address
+        ))
+        .form(&[("subscription_token", subscription_token)])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let new_confirmation_links = app.get_confirmation_links(&email_request);
+    reqwest::get(new_confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn requesting_a_new_confirmation_link_invalidates_the_old_one() {
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+    expire_subscription_tokens(&app).await;
+    let (_, subscription_token) = confirmation_links
+        .html
+        .query_pairs()
+        .find(|(key, _)| key == "subscription_token")
+        .unwrap();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .api_client
+        .post(format!(
+            "http://{}/subscriptions/confirm/resend",
+            app.address
+        ))
+        .form(&[("subscription_token", subscription_token)])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+
+    let tokens = sqlx::query!("SELECT COUNT(*) AS count FROM subscription_tokens",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to count subscription tokens");
+    assert_eq!(tokens.count, Some(1));
+}
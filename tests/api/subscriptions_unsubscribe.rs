@@ -0,0 +1,261 @@
+use serde_json::json;
+use uuid::Uuid;
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{
+    accept_batch, create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app, TestApp,
+    UnsubscribeLinks,
+};
+
+async fn publish_newsletter_and_get_unsubscribe_links(app: &TestApp) -> UnsubscribeLinks {
+    app.login().await;
+
+    let _mock_guard = Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(accept_batch)
+        .named("Deliver newsletter issue")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    app.post_publish_newsletter(&json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    }))
+    .await;
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+
+    app.get_unsubscribe_links(&email_request)
+}
+
+#[tokio::test]
+async fn unsubscribe_without_token_is_rejected_with_a_400() {
+    let app = spawn_app().await;
+
+    let response = reqwest::get(&format!("http://{}/subscriptions/unsubscribe", app.address))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn unsubscribe_with_a_forged_token_is_rejected_with_a_401() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+    let forged_token = format!("{}.{}", subscriber_id.simple(), "00".repeat(32));
+
+    let response = app
+        .api_client
+        .post(format!(
+            "http://{}/subscriptions/unsubscribe?token={forged_token}",
+            app.address
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn newsletters_contain_an_unsubscribe_link() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let unsubscribe_links = publish_newsletter_and_get_unsubscribe_links(&app).await;
+    assert_eq!(unsubscribe_links.html, unsubscribe_links.plain_text);
+
+    let response = reqwest::get(unsubscribe_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains(r#"<form action="/subscriptions/unsubscribe?token="#));
+}
+
+#[tokio::test]
+async fn unsubscribing_marks_the_subscriber_as_unsubscribed() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let unsubscribe_links = publish_newsletter_and_get_unsubscribe_links(&app).await;
+
+    let response = app
+        .api_client
+        .post(unsubscribe_links.html)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.status, "unsubscribed");
+}
+
+#[tokio::test]
+async fn newsletters_are_not_delivered_to_unsubscribed_subscribers() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let unsubscribe_links = publish_newsletter_and_get_unsubscribe_links(&app).await;
+    app.api_client
+        .post(unsubscribe_links.html)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_publish_newsletter(&json!({
+        "title": "Another newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    }))
+    .await;
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn queued_issues_are_not_delivered_after_unsubscribing() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let unsubscribe_links = publish_newsletter_and_get_unsubscribe_links(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_publish_newsletter(&json!({
+        "title": "Another newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    }))
+    .await;
+    app.api_client
+        .post(unsubscribe_links.html)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn newsletters_carry_one_click_list_unsubscribe_headers() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let unsubscribe_links = publish_newsletter_and_get_unsubscribe_links(&app).await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let batch: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let header = |name: &str| {
+        batch[0]["Headers"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|h| h["Name"] == name)
+            .map(|h| h["Value"].as_str().unwrap().to_owned())
+            .unwrap()
+    };
+
+    let list_unsubscribe = header("List-Unsubscribe");
+    let link = list_unsubscribe
+        .strip_prefix('<')
+        .and_then(|l| l.strip_suffix('>'))
+        .unwrap();
+    let mut link = reqwest::Url::parse(link).unwrap();
+    link.set_port(Some(app.address.port())).unwrap();
+    assert_eq!(link, unsubscribe_links.html);
+    assert_eq!(
+        header("List-Unsubscribe-Post"),
+        "List-Unsubscribe=One-Click"
+    );
+}
+
+#[tokio::test]
+async fn one_click_unsubscribe_works_without_a_session() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let unsubscribe_links = publish_newsletter_and_get_unsubscribe_links(&app).await;
+
+    // Mailbox providers post to the List-Unsubscribe link without any cookies
+    let response = reqwest::Client::new()
+        .post(unsubscribe_links.html)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("List-Unsubscribe=One-Click")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.status, "unsubscribed");
+}
+
+#[tokio::test]
+async fn an_old_confirmation_link_does_not_resubscribe() {
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+    reqwest::get(confirmation_links.html.clone())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let unsubscribe_links = publish_newsletter_and_get_unsubscribe_links(&app).await;
+    app.api_client
+        .post(unsubscribe_links.html)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    reqwest::get(confirmation_links.html).await.unwrap();
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.status, "unsubscribed");
+}
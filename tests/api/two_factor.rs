@@ -0,0 +1,262 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::json;
+use zero2prod::domain::{Role, TotpSecret};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp, TestUser};
+
+const STEP_SECS: u64 = 30;
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+fn between<'a>(html: &'a str, start: &str, end: &str) -> Vec<&'a str> {
+    html.split(start)
+        .skip(1)
+        .map(|s| s.split_once(end).unwrap().0)
+        .collect()
+}
+
+/// Turns on two-factor authentication for the logged-in user and returns
+/// the secret along with the recovery codes.
+async fn enroll(app: &TestApp) -> (TotpSecret, Vec<String>) {
+    let html_page = app.get_two_factor_html().await;
+    let secret = between(&html_page, r#"<code id="totp-secret">"#, "</code>")[0];
+    let secret = TotpSecret::parse(secret.to_string()).unwrap();
+
+    let response = app
+        .post_two_factor(&json!({ "code": secret.generate_code(now()) }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    let recovery_codes = between(&html_page, "<li><code>", "</code></li>")
+        .into_iter()
+        .map(String::from)
+        .collect();
+
+    (secret, recovery_codes)
+}
+
+/// The enrollment code used up the current time step, so the next login
+/// needs the code of the following one.
+fn next_code(secret: &TotpSecret) -> String {
+    secret.generate_code(now() + STEP_SECS)
+}
+
+#[tokio::test]
+async fn the_enrollment_page_shows_an_otpauth_uri_and_qr_code() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    let html_page = app.get_two_factor_html().await;
+
+    assert!(html_page.contains(&format!(
+        "otpauth://totp/127.0.0.1:{}?secret=",
+        app.test_user.username
+    )));
+    assert!(html_page.contains("<svg"));
+    // The secret stays the same until it is confirmed
+    assert_eq!(html_page, app.get_two_factor_html().await);
+}
+
+#[tokio::test]
+async fn enrolling_shows_ten_recovery_codes_once() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    let (_, recovery_codes) = enroll(&app).await;
+
+    assert_eq!(recovery_codes.len(), 10);
+    let html_page = app.get_two_factor_html().await;
+    assert!(html_page.contains("Two-factor authentication is on for your account."));
+    assert!(!html_page.contains(&recovery_codes[0]));
+    let stored = sqlx::query!("SELECT code_hash FROM totp_recovery_codes")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(stored.len(), 10);
+    assert!(stored
+        .iter()
+        .all(|r| !recovery_codes.contains(&r.code_hash)));
+}
+
+#[tokio::test]
+async fn a_wrong_enrollment_code_does_not_enable_two_factor() {
+    let app = spawn_app().await;
+    app.login().await;
+    app.get_two_factor_html().await;
+
+    let response = app.post_two_factor(&json!({ "code": "000000" })).await;
+
+    assert_is_redirect_to(&response, "/admin/2fa");
+    let html_page = app.get_two_factor_html().await;
+    assert!(html_page.contains("The code is not valid"));
+    app.post_logout().await;
+    let response = app.login().await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn logging_in_takes_a_code_once_two_factor_is_on() {
+    let app = spawn_app().await;
+    app.login().await;
+    let (secret, _) = enroll(&app).await;
+    app.post_logout().await;
+
+    let response = app.login().await;
+    assert_is_redirect_to(&response, "/login/2fa");
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app
+        .post_login_two_factor(&json!({ "code": next_code(&secret) }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {}", app.test_user.username)));
+}
+
+#[tokio::test]
+async fn a_wrong_or_replayed_code_is_rejected() {
+    let app = spawn_app().await;
+    app.login().await;
+    let (secret, _) = enroll(&app).await;
+    app.post_logout().await;
+    app.login().await;
+    let code = next_code(&secret);
+    app.post_login_two_factor(&json!({ "code": code })).await;
+    app.post_logout().await;
+
+    app.login().await;
+    for code in ["000000", code.as_str()] {
+        let response = app.post_login_two_factor(&json!({ "code": code })).await;
+        assert_is_redirect_to(&response, "/login/2fa");
+    }
+
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn each_recovery_code_can_be_used_once() {
+    let app = spawn_app().await;
+    app.login().await;
+    let (_, recovery_codes) = enroll(&app).await;
+    app.post_logout().await;
+
+    app.login().await;
+    let response = app
+        .post_login_two_factor(&json!({ "code": recovery_codes[0].to_uppercase() }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    app.post_logout().await;
+
+    app.login().await;
+    let response = app
+        .post_login_two_factor(&json!({ "code": recovery_codes[0] }))
+        .await;
+    assert_is_redirect_to(&response, "/login/2fa");
+}
+
+#[tokio::test]
+async fn the_second_step_needs_the_password_first() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_login_two_factor(&json!({ "code": "000000" }))
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn owners_can_reset_two_factor_for_a_colleague() {
+    let app = spawn_app().await;
+    let colleague = TestUser::generate_with_role(Role::Editor);
+    colleague.store(&app.db_pool).await;
+    app.login_as(&colleague).await;
+    enroll(&app).await;
+    app.post_logout().await;
+
+    app.login().await;
+    let response = app
+        .post_reset_two_factor(&json!({ "username": colleague.username }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/users/2fa");
+    app.post_logout().await;
+
+    let response = app.login_as(&colleague).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let n_codes = sqlx::query!("SELECT count(*) AS \"n!\" FROM totp_recovery_codes")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_codes, 0);
+}
+
+#[tokio::test]
+async fn the_reset_confirmation_escapes_the_username() {
+    let app = spawn_app().await;
+    let mut colleague = TestUser::generate_with_role(Role::Editor);
+    colleague.username = "<i>eve</i>".into();
+    colleague.store(&app.db_pool).await;
+    app.login().await;
+
+    app.post_reset_two_factor(&json!({ "username": colleague.username }))
+        .await;
+
+    let html_page = app.get_reset_two_factor_html().await;
+    assert!(
+        html_page.contains("Two-factor authentication has been reset for &lt;i&gt;eve&lt;/i&gt;.")
+    );
+}
+
+#[tokio::test]
+async fn only_owners_can_reset_two_factor() {
+    let app = spawn_app().await;
+    app.login().await;
+    enroll(&app).await;
+    app.post_logout().await;
+    app.login_with_role(Role::Editor).await;
+
+    let response = app
+        .post_reset_two_factor(&json!({ "username": app.test_user.username }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 403);
+    let enabled = sqlx::query!(
+        "SELECT totp_secret FROM users WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .totp_secret;
+    assert!(enabled.is_some());
+}
+
+#[tokio::test]
+async fn wrong_codes_count_as_failed_logins() {
+    let app = spawn_app_with(|c| c.login_throttle.max_failures = 2).await;
+    app.login().await;
+    let (secret, _) = enroll(&app).await;
+    app.post_logout().await;
+    app.login().await;
+    for _ in 0..2 {
+        app.post_login_two_factor(&json!({ "code": "000000" }))
+            .await;
+    }
+
+    let response = app
+        .post_login_two_factor(&json!({ "code": next_code(&secret) }))
+        .await;
+
+    assert_is_redirect_to(&response, "/login/2fa");
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}